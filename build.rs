@@ -0,0 +1,5 @@
+// Migrations are embedded at compile time, so the crate has to be rebuilt
+// whenever a migration file is added or modified.
+fn main() {
+    println!("cargo:rerun-if-changed=migrations");
+}
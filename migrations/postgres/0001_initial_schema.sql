@@ -0,0 +1,37 @@
+-- Baseline schema. Every statement is idempotent so databases created by the
+-- former `--clear_database` path can adopt the migration history in place.
+
+DO $$ BEGIN
+    CREATE TYPE subscription_level AS ENUM (
+    'non-premium',
+    'basic-premium',
+    'normal-premium',
+    'enterprise-premium');
+EXCEPTION
+    WHEN duplicate_object THEN null;
+END $$;
+
+CREATE TABLE IF NOT EXISTS users (
+    user_id BIGSERIAL PRIMARY KEY,
+    username VARCHAR(50) NOT NULL UNIQUE,
+    password VARCHAR(256) NOT NULL,
+    email VARCHAR(50) NOT NULL,
+    subscription subscription_level NOT NULL DEFAULT 'non-premium'
+);
+
+CREATE TABLE IF NOT EXISTS secret_access_keys (
+    user_id BIGINT PRIMARY KEY,
+    secret_key VARCHAR(255) NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS refresh_access_keys (
+    user_id BIGINT PRIMARY KEY,
+    secret_refresh_key VARCHAR(255) NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS session_table (
+    session_uuid VARCHAR(36) PRIMARY KEY,
+    user_id BIGINT NOT NULL,
+    session_id BIGSERIAL UNIQUE,
+    log_date TIMESTAMP NOT NULL DEFAULT current_timestamp
+);
@@ -0,0 +1,11 @@
+-- Server wide key pairs that sign access tokens. A key without `activates_at` is
+-- published but not used for signing yet, a key past `retires_at` is no longer accepted.
+
+CREATE TABLE IF NOT EXISTS signing_keys (
+    key_id VARCHAR(64) PRIMARY KEY,
+    algorithm VARCHAR(16) NOT NULL,
+    private_key TEXT NOT NULL,
+    created_at BIGINT NOT NULL,
+    activates_at BIGINT,
+    retires_at BIGINT
+);
@@ -0,0 +1,14 @@
+-- Client metadata shown by the session management API. Timestamps are unix seconds like
+-- in the other tables, existing sessions take them over from `log_date`.
+
+ALTER TABLE session_table ADD COLUMN IF NOT EXISTS created_at BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE session_table ADD COLUMN IF NOT EXISTS last_used_at BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE session_table ADD COLUMN IF NOT EXISTS ip_address TEXT;
+ALTER TABLE session_table ADD COLUMN IF NOT EXISTS user_agent TEXT;
+
+UPDATE session_table
+SET created_at = EXTRACT(EPOCH FROM log_date)::BIGINT,
+    last_used_at = EXTRACT(EPOCH FROM log_date)::BIGINT
+WHERE created_at = 0;
+
+CREATE INDEX IF NOT EXISTS session_table_user_id ON session_table (user_id);
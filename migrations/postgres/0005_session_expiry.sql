@@ -0,0 +1,13 @@
+-- Sessions end `idle_timeout` seconds after their last use or at `expires_at`, whichever
+-- comes first. Both are fixed when the session is created; existing sessions get the
+-- default policy of one idle day and thirty days in total.
+
+ALTER TABLE session_table ADD COLUMN IF NOT EXISTS expires_at BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE session_table ADD COLUMN IF NOT EXISTS idle_timeout BIGINT NOT NULL DEFAULT 0;
+
+UPDATE session_table
+SET expires_at = created_at + 2592000,
+    idle_timeout = 86400
+WHERE expires_at = 0;
+
+CREATE INDEX IF NOT EXISTS refresh_tokens_session_uuid ON refresh_tokens (session_uuid);
@@ -0,0 +1,9 @@
+-- Access tokens revoked before they expire, identified by their `jti`. Entries are only
+-- needed until the token would have expired anyway and are purged afterwards.
+
+CREATE TABLE IF NOT EXISTS revoked_tokens (
+    jti VARCHAR(36) PRIMARY KEY,
+    expires_at BIGINT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS revoked_tokens_expires_at ON revoked_tokens (expires_at);
@@ -0,0 +1,8 @@
+-- Roles granted to users, checked by the guards protecting endpoints of embedding apps.
+-- A role is any name the embedding app gives meaning to.
+
+CREATE TABLE IF NOT EXISTS user_roles (
+    user_id BIGINT NOT NULL,
+    role VARCHAR(50) NOT NULL,
+    PRIMARY KEY (user_id, role)
+);
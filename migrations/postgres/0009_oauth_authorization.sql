@@ -0,0 +1,29 @@
+-- OAuth 2.0 authorization code flow. Clients list the redirect uris they may use, separated
+-- by spaces. Sessions created for a client remember it and the granted scope, so tokens
+-- refreshed later keep that scope.
+
+ALTER TABLE clients ADD COLUMN IF NOT EXISTS redirect_uris TEXT NOT NULL DEFAULT '';
+ALTER TABLE session_table ADD COLUMN IF NOT EXISTS client_id VARCHAR(36);
+ALTER TABLE session_table ADD COLUMN IF NOT EXISTS scope TEXT;
+
+CREATE TABLE IF NOT EXISTS authorization_codes (
+    code VARCHAR(64) PRIMARY KEY,
+    client_id VARCHAR(36) NOT NULL,
+    user_id BIGINT NOT NULL,
+    redirect_uri TEXT NOT NULL,
+    scope TEXT,
+    code_challenge VARCHAR(128) NOT NULL,
+    expires_at BIGINT NOT NULL,
+    used_at BIGINT
+);
+
+CREATE INDEX IF NOT EXISTS authorization_codes_expires_at ON authorization_codes (expires_at);
+
+-- Scope a user allowed a client to use, the consent step is skipped while it covers the request
+CREATE TABLE IF NOT EXISTS consents (
+    user_id BIGINT NOT NULL,
+    client_id VARCHAR(36) NOT NULL,
+    scope TEXT NOT NULL,
+    granted_at BIGINT NOT NULL,
+    PRIMARY KEY (user_id, client_id)
+);
@@ -0,0 +1,6 @@
+-- OpenID Connect. The nonce of the authentication request and the time the user logged in
+-- travel with the code into the ID token, the session keeps that time for refreshed ones.
+
+ALTER TABLE authorization_codes ADD COLUMN IF NOT EXISTS nonce TEXT;
+ALTER TABLE authorization_codes ADD COLUMN IF NOT EXISTS auth_time BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE session_table ADD COLUMN IF NOT EXISTS auth_time BIGINT;
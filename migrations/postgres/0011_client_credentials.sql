@@ -0,0 +1,4 @@
+-- OAuth client credentials grant. Machine clients list the scopes they may request for
+-- themselves, separated by spaces, clients without any can not use the grant.
+
+ALTER TABLE clients ADD COLUMN IF NOT EXISTS allowed_scopes TEXT NOT NULL DEFAULT '';
@@ -0,0 +1,32 @@
+-- SQLite has no enum types, the CHECK constraint mirrors the
+-- `subscription_level` enum of the Postgres schema.
+
+CREATE TABLE IF NOT EXISTS users (
+    user_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    username VARCHAR(50) NOT NULL UNIQUE,
+    password VARCHAR(256) NOT NULL,
+    email VARCHAR(50) NOT NULL,
+    subscription TEXT NOT NULL DEFAULT 'non-premium' CHECK (subscription IN (
+        'non-premium',
+        'basic-premium',
+        'normal-premium',
+        'enterprise-premium'))
+);
+
+CREATE TABLE IF NOT EXISTS secret_access_keys (
+    user_id INTEGER PRIMARY KEY,
+    secret_key VARCHAR(255) NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS refresh_access_keys (
+    user_id INTEGER PRIMARY KEY,
+    secret_refresh_key VARCHAR(255) NOT NULL
+);
+
+-- `session_id` is assigned by the application, SQLite only auto increments primary keys
+CREATE TABLE IF NOT EXISTS session_table (
+    session_uuid VARCHAR(36) PRIMARY KEY,
+    user_id INTEGER NOT NULL,
+    session_id INTEGER NOT NULL UNIQUE,
+    log_date TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,14 @@
+-- Every issued refresh token, so each one can be used once. Tokens rotated from the
+-- same login share a `family_id`; replaying a used token revokes the whole family.
+
+CREATE TABLE IF NOT EXISTS refresh_tokens (
+    token_id VARCHAR(36) PRIMARY KEY,
+    family_id VARCHAR(36) NOT NULL,
+    session_uuid VARCHAR(36) NOT NULL,
+    user_id INTEGER NOT NULL,
+    expires_at INTEGER NOT NULL,
+    used_at INTEGER,
+    revoked_at INTEGER
+);
+
+CREATE INDEX IF NOT EXISTS refresh_tokens_family_id ON refresh_tokens (family_id);
@@ -0,0 +1,14 @@
+-- Client metadata shown by the session management API. Timestamps are unix seconds like
+-- in the other tables, existing sessions take them over from `log_date`.
+
+ALTER TABLE session_table ADD COLUMN created_at INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE session_table ADD COLUMN last_used_at INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE session_table ADD COLUMN ip_address TEXT;
+ALTER TABLE session_table ADD COLUMN user_agent TEXT;
+
+UPDATE session_table
+SET created_at = CAST(strftime('%s', log_date) AS INTEGER),
+    last_used_at = CAST(strftime('%s', log_date) AS INTEGER)
+WHERE created_at = 0;
+
+CREATE INDEX IF NOT EXISTS session_table_user_id ON session_table (user_id);
@@ -0,0 +1,9 @@
+-- Trusted services that authenticate with client credentials. Only an argon2 hash of the
+-- secret is kept, the secret itself is shown once when the client is created.
+
+CREATE TABLE IF NOT EXISTS clients (
+    client_id VARCHAR(36) PRIMARY KEY,
+    client_secret TEXT NOT NULL,
+    name TEXT NOT NULL,
+    created_at INTEGER NOT NULL
+);
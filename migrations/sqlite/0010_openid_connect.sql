@@ -0,0 +1,6 @@
+-- OpenID Connect. The nonce of the authentication request and the time the user logged in
+-- travel with the code into the ID token, the session keeps that time for refreshed ones.
+
+ALTER TABLE authorization_codes ADD COLUMN nonce TEXT;
+ALTER TABLE authorization_codes ADD COLUMN auth_time INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE session_table ADD COLUMN auth_time INTEGER;
@@ -0,0 +1,32 @@
+-- `session_id` was computed as MAX(session_id) + 1 on insert, which hands the id of the
+-- newest revoked session to the next one and races between concurrent logins. SQLite only
+-- auto increments an INTEGER PRIMARY KEY, so the table is rebuilt around `session_id` like
+-- the BIGSERIAL column of the Postgres schema; AUTOINCREMENT keeps ids from being reused.
+
+CREATE TABLE session_table_autoincrement (
+    session_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    session_uuid VARCHAR(36) NOT NULL UNIQUE,
+    user_id INTEGER NOT NULL,
+    log_date TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    created_at INTEGER NOT NULL DEFAULT 0,
+    last_used_at INTEGER NOT NULL DEFAULT 0,
+    ip_address TEXT,
+    user_agent TEXT,
+    expires_at INTEGER NOT NULL DEFAULT 0,
+    idle_timeout INTEGER NOT NULL DEFAULT 0,
+    client_id VARCHAR(36),
+    scope TEXT,
+    auth_time INTEGER
+);
+
+INSERT INTO session_table_autoincrement (session_id, session_uuid, user_id, log_date,
+    created_at, last_used_at, ip_address, user_agent, expires_at, idle_timeout, client_id,
+    scope, auth_time)
+SELECT session_id, session_uuid, user_id, log_date, created_at, last_used_at, ip_address,
+    user_agent, expires_at, idle_timeout, client_id, scope, auth_time
+FROM session_table;
+
+DROP TABLE session_table;
+ALTER TABLE session_table_autoincrement RENAME TO session_table;
+
+CREATE INDEX IF NOT EXISTS session_table_user_id ON session_table (user_id);
@@ -0,0 +1,4 @@
+-- Token introspection is limited to clients registered as resource servers, existing
+-- clients lose it until they are registered again with --resource-server.
+
+ALTER TABLE clients ADD COLUMN resource_server BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,364 @@
+use actix_web::http::header;
+use actix_web::{web, HttpResponse, ResponseError};
+use chrono::Utc;
+use serde::Deserialize;
+use url::Url;
+
+use crate::auth::cookies::utils::get_session_uuid_from_cookie;
+use crate::auth::oauth::config::OAuthConfig;
+use crate::auth::oauth::consent::ConsentPage;
+use crate::auth::oauth::id_token::OPENID_SCOPE;
+use crate::auth::oauth::pkce::{is_valid_code_challenge, S256_METHOD};
+use crate::auth::oauth::scope::{is_covered_by, join_scope, parse_scope};
+use crate::auth::session::ensure_session_is_active;
+use crate::auth::token::signing_key::TokenSigner;
+use crate::errors::auth_error::AuthError;
+use crate::errors::oauth_error::{
+    OAuthError, ACCESS_DENIED, INVALID_SCOPE, UNSUPPORTED_RESPONSE_TYPE,
+};
+use crate::storage::{Storage, StoredAuthorizationCode, StoredClient, StoredSession};
+use crate::utils::random::random_string;
+
+const AUTHORIZATION_CODE_LENGTH: usize = 43;
+
+// Query of the authorization request, the consent form posts the same fields back with
+// the decision in `consent`
+#[derive(Deserialize)]
+pub struct AuthorizationRequest {
+    pub response_type: Option<String>,
+    pub client_id: Option<String>,
+    pub redirect_uri: Option<String>,
+    pub scope: Option<String>,
+    pub state: Option<String>,
+    pub code_challenge: Option<String>,
+    pub code_challenge_method: Option<String>,
+    pub nonce: Option<String>,
+    pub consent: Option<String>,
+}
+
+// Request that passed every check, what is left is the decision of the user
+struct ValidatedRequest<'a> {
+    client: StoredClient,
+    redirect_uri: String,
+    scopes: Vec<String>,
+    state: Option<&'a str>,
+    code_challenge: &'a str,
+    nonce: Option<&'a str>,
+}
+
+// Redirect uris are registered, so appending the response to one can not send the user
+// anywhere the client did not choose
+fn redirect_with(
+    redirect_uri: &str,
+    parameters: &[(&str, &str)],
+    state: Option<&str>,
+) -> HttpResponse {
+    let mut location = match Url::parse(redirect_uri) {
+        Ok(location) => location,
+        Err(e) => {
+            return OAuthError::invalid_request(format!(
+                "redirect uri {} is not valid ({})",
+                redirect_uri, e
+            ))
+            .error_response()
+        }
+    };
+    {
+        let mut query = location.query_pairs_mut();
+        query.extend_pairs(parameters);
+        if let Some(state) = state {
+            query.append_pair("state", state);
+        }
+    }
+    HttpResponse::Found()
+        .insert_header((header::LOCATION, location.to_string()))
+        .insert_header((header::CACHE_CONTROL, "no-store"))
+        .finish()
+}
+
+fn redirect_error(redirect_uri: &str, error: OAuthError, state: Option<&str>) -> HttpResponse {
+    redirect_with(
+        redirect_uri,
+        &[
+            ("error", error.error),
+            ("error_description", &error.description),
+        ],
+        state,
+    )
+}
+
+// Until the redirect uri is known to belong to the client, errors are shown to the user
+// instead of being sent to a uri nobody registered
+async fn validate_client(
+    request: &AuthorizationRequest,
+    storage: &dyn Storage,
+) -> Result<(StoredClient, String), OAuthError> {
+    let client_id = request
+        .client_id
+        .as_deref()
+        .ok_or_else(|| OAuthError::invalid_request("client_id is required"))?;
+    let client = match storage.get_client(client_id).await {
+        Err(AuthError::RecordNotFound) => Err(OAuthError::invalid_request(format!(
+            "client {} is not registered",
+            client_id
+        ))),
+        result => result.map_err(OAuthError::from),
+    }?;
+
+    let redirect_uri = match request.redirect_uri.as_deref() {
+        Some(redirect_uri) if client.has_redirect_uri(redirect_uri) => redirect_uri.to_string(),
+        Some(redirect_uri) => {
+            return Err(OAuthError::invalid_request(format!(
+                "redirect uri {} is not registered for the client",
+                redirect_uri
+            )))
+        }
+        // a client with a single redirect uri may leave it out
+        None => {
+            let mut registered = client.redirect_uris.split_whitespace();
+            match (registered.next(), registered.next()) {
+                (Some(redirect_uri), None) => redirect_uri.to_string(),
+                _ => return Err(OAuthError::invalid_request("redirect_uri is required")),
+            }
+        }
+    };
+    Ok((client, redirect_uri))
+}
+
+fn validate_parameters<'a>(
+    request: &'a AuthorizationRequest,
+    signer: &TokenSigner,
+) -> Result<(&'a str, Vec<String>), OAuthError> {
+    match request.response_type.as_deref() {
+        Some("code") => {}
+        Some(response_type) => {
+            return Err(OAuthError::new(
+                UNSUPPORTED_RESPONSE_TYPE,
+                format!("response type {} is not supported", response_type),
+            ))
+        }
+        None => return Err(OAuthError::invalid_request("response_type is required")),
+    }
+    let code_challenge = request
+        .code_challenge
+        .as_deref()
+        .ok_or_else(|| OAuthError::invalid_request("code_challenge is required"))?;
+    // a missing method means `plain`, which is never accepted
+    if request.code_challenge_method.as_deref() != Some(S256_METHOD) {
+        return Err(OAuthError::invalid_request(format!(
+            "code_challenge_method must be {}",
+            S256_METHOD
+        )));
+    }
+    if !is_valid_code_challenge(code_challenge) {
+        return Err(OAuthError::invalid_request(
+            "code_challenge is not a base64url encoded SHA-256 hash",
+        ));
+    }
+    let scopes = parse_scope(request.scope.as_deref())
+        .map_err(|reason| OAuthError::new(INVALID_SCOPE, reason))?;
+    if matches!(signer, TokenSigner::PerUserSecret)
+        && scopes.iter().any(|scope| scope == OPENID_SCOPE)
+    {
+        return Err(OAuthError::new(
+            INVALID_SCOPE,
+            "openid needs an asymmetric JWT_SIGNING_ALGORITHM",
+        ));
+    }
+    Ok((code_challenge, scopes))
+}
+
+// Only the session cookie of a login counts, sessions created for OAuth clients can not
+// authorize further clients
+async fn browser_session(
+    req: &actix_web::HttpRequest,
+    storage: &dyn Storage,
+) -> Result<Option<StoredSession>, AuthError> {
+    let session_uuid = match get_session_uuid_from_cookie(req) {
+        Ok(session_uuid) => session_uuid,
+        Err(_) => return Ok(None),
+    };
+    let session = match storage.get_session(&session_uuid).await {
+        Err(AuthError::RecordNotFound) => return Ok(None),
+        result => result?,
+    };
+    if session.client_id.is_some() {
+        return Ok(None);
+    }
+    match ensure_session_is_active(&session_uuid, session.user_id, storage).await {
+        Ok(session) => Ok(Some(session)),
+        Err(AuthError::SessionNotFound | AuthError::SessionExpired) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+// The login behind the browser session is the authentication the ID token reports
+async fn issue_authorization_code(
+    request: &ValidatedRequest<'_>,
+    session: &StoredSession,
+    storage: &dyn Storage,
+    oauth: &OAuthConfig,
+) -> Result<HttpResponse, OAuthError> {
+    let code = random_string(AUTHORIZATION_CODE_LENGTH);
+    storage
+        .store_authorization_code(&StoredAuthorizationCode {
+            code: code.clone(),
+            client_id: request.client.client_id.clone(),
+            user_id: session.user_id,
+            redirect_uri: request.redirect_uri.clone(),
+            scope: join_scope(&request.scopes),
+            code_challenge: request.code_challenge.to_string(),
+            nonce: request.nonce.map(str::to_string),
+            auth_time: session.auth_time.unwrap_or(session.created_at),
+            expires_at: Utc::now().timestamp() + oauth.code_lifetime,
+            used_at: None,
+        })
+        .await?;
+    Ok(redirect_with(
+        &request.redirect_uri,
+        &[("code", &code)],
+        request.state,
+    ))
+}
+
+fn consent_page(request: &ValidatedRequest<'_>, username: &str) -> HttpResponse {
+    let scope = join_scope(&request.scopes).unwrap_or_default();
+    let mut hidden_fields = vec![
+        ("response_type", "code"),
+        ("client_id", request.client.client_id.as_str()),
+        ("redirect_uri", request.redirect_uri.as_str()),
+        ("scope", scope.as_str()),
+        ("code_challenge", request.code_challenge),
+        ("code_challenge_method", S256_METHOD),
+    ];
+    if let Some(state) = request.state {
+        hidden_fields.push(("state", state));
+    }
+    if let Some(nonce) = request.nonce {
+        hidden_fields.push(("nonce", nonce));
+    }
+    let page = ConsentPage {
+        client_name: &request.client.name,
+        username,
+        scopes: &request.scopes,
+        hidden_fields,
+    }
+    .render();
+    HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .insert_header((header::CACHE_CONTROL, "no-store"))
+        .insert_header((header::X_FRAME_OPTIONS, "DENY"))
+        .body(page)
+}
+
+// Shared by the authorization request and the consent form, `consent` is only read from
+// the form
+async fn handle_authorization(
+    req: &actix_web::HttpRequest,
+    request: &AuthorizationRequest,
+    consent: Option<&str>,
+    storage: &dyn Storage,
+    signer: &TokenSigner,
+    oauth: &OAuthConfig,
+) -> Result<HttpResponse, OAuthError> {
+    let (client, redirect_uri) = validate_client(request, storage).await?;
+    let state = request.state.as_deref();
+    let (code_challenge, scopes) = match validate_parameters(request, signer) {
+        Ok(parameters) => parameters,
+        Err(e) => return Ok(redirect_error(&redirect_uri, e, state)),
+    };
+    let request = ValidatedRequest {
+        client,
+        redirect_uri,
+        scopes,
+        state,
+        code_challenge,
+        nonce: request.nonce.as_deref(),
+    };
+
+    let session = match browser_session(req, storage).await? {
+        Some(session) => session,
+        None => {
+            return Ok(match oauth.login_redirect(req.full_url().as_ref()) {
+                Some(login_url) if consent.is_none() => HttpResponse::Found()
+                    .insert_header((header::LOCATION, login_url))
+                    .finish(),
+                _ => AuthError::MissingCredentials.error_response(),
+            })
+        }
+    };
+
+    let client_id = &request.client.client_id;
+    let consented_scope = storage
+        .get_consented_scope(session.user_id, client_id)
+        .await?;
+    match consent {
+        Some("allow") => {
+            // the new consent extends the earlier one instead of narrowing it
+            let mut scopes = request.scopes.clone();
+            if let Some(consented_scope) = &consented_scope {
+                scopes.extend(consented_scope.split(' ').map(str::to_string));
+            }
+            scopes.sort();
+            scopes.dedup();
+            storage
+                .store_consent(
+                    session.user_id,
+                    client_id,
+                    &scopes.join(" "),
+                    Utc::now().timestamp(),
+                )
+                .await?;
+            issue_authorization_code(&request, &session, storage, oauth).await
+        }
+        Some("deny") => Ok(redirect_error(
+            &request.redirect_uri,
+            OAuthError::new(ACCESS_DENIED, "the user denied the request"),
+            request.state,
+        )),
+        Some(consent) => Ok(redirect_error(
+            &request.redirect_uri,
+            OAuthError::invalid_request(format!("consent must be allow or deny, got {}", consent)),
+            request.state,
+        )),
+        None => match consented_scope {
+            Some(consented_scope) if is_covered_by(&request.scopes, &consented_scope) => {
+                issue_authorization_code(&request, &session, storage, oauth).await
+            }
+            _ => {
+                let user = storage.get_user_with_user_id(session.user_id).await?;
+                Ok(consent_page(&request, &user.username))
+            }
+        },
+    }
+}
+
+pub async fn authorize(
+    req: actix_web::HttpRequest,
+    query: web::Query<AuthorizationRequest>,
+    storage: web::Data<dyn Storage>,
+    signer: web::Data<TokenSigner>,
+    oauth: web::Data<OAuthConfig>,
+) -> Result<HttpResponse, OAuthError> {
+    handle_authorization(&req, &query, None, storage.get_ref(), &signer, &oauth).await
+}
+
+// A consent sent along with the query string would let a link decide for the user
+pub async fn authorize_consent(
+    req: actix_web::HttpRequest,
+    form: web::Form<AuthorizationRequest>,
+    storage: web::Data<dyn Storage>,
+    signer: web::Data<TokenSigner>,
+    oauth: web::Data<OAuthConfig>,
+) -> Result<HttpResponse, OAuthError> {
+    let consent = form.consent.as_deref().unwrap_or_default();
+    handle_authorization(
+        &req,
+        &form,
+        Some(consent),
+        storage.get_ref(),
+        &signer,
+        &oauth,
+    )
+    .await
+}
@@ -0,0 +1,84 @@
+use actix_web::{web, HttpResponse};
+use serde::{Deserialize, Serialize};
+
+use crate::auth::api_requests::token::{
+    AUTHORIZATION_CODE_GRANT, CLIENT_CREDENTIALS_GRANT, REFRESH_TOKEN_GRANT,
+};
+use crate::auth::oauth::config::OAuthConfig;
+use crate::auth::oauth::id_token::{EMAIL_SCOPE, OPENID_SCOPE, PROFILE_SCOPE};
+use crate::auth::oauth::pkce::S256_METHOD;
+use crate::auth::token::signing_key::TokenSigner;
+
+// OpenID Connect discovery document, relying parties configure themselves from it given
+// only the issuer
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OpenIdConfiguration {
+    pub issuer: String,
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+    pub userinfo_endpoint: String,
+    pub jwks_uri: String,
+    pub response_types_supported: Vec<String>,
+    pub grant_types_supported: Vec<String>,
+    pub subject_types_supported: Vec<String>,
+    pub id_token_signing_alg_values_supported: Vec<String>,
+    pub scopes_supported: Vec<String>,
+    pub claims_supported: Vec<String>,
+    pub token_endpoint_auth_methods_supported: Vec<String>,
+    pub code_challenge_methods_supported: Vec<String>,
+}
+
+fn strings(values: &[&str]) -> Vec<String> {
+    values.iter().map(|value| value.to_string()).collect()
+}
+
+pub async fn openid_configuration(
+    req: actix_web::HttpRequest,
+    signer: web::Data<TokenSigner>,
+    oauth: web::Data<OAuthConfig>,
+) -> HttpResponse {
+    let issuer = oauth.issuer(&req);
+    // without a keyring there are no ID tokens, so the server is no OpenID provider and the
+    // document could not name a signing algorithm
+    let keyring = match signer.get_ref() {
+        TokenSigner::Keyring(keyring) => keyring,
+        TokenSigner::PerUserSecret => return HttpResponse::NotFound().finish(),
+    };
+    let algorithms = keyring
+        .algorithms()
+        .iter()
+        .map(|algorithm| format!("{:?}", algorithm))
+        .collect();
+    HttpResponse::Ok().json(OpenIdConfiguration {
+        authorization_endpoint: format!("{}/oauth/authorize", issuer),
+        token_endpoint: format!("{}/oauth/token", issuer),
+        userinfo_endpoint: format!("{}/oauth/userinfo", issuer),
+        jwks_uri: format!("{}/.well-known/jwks.json", issuer),
+        issuer,
+        response_types_supported: strings(&["code"]),
+        grant_types_supported: strings(&[
+            AUTHORIZATION_CODE_GRANT,
+            REFRESH_TOKEN_GRANT,
+            CLIENT_CREDENTIALS_GRANT,
+        ]),
+        subject_types_supported: strings(&["public"]),
+        id_token_signing_alg_values_supported: algorithms,
+        scopes_supported: strings(&[OPENID_SCOPE, EMAIL_SCOPE, PROFILE_SCOPE]),
+        claims_supported: strings(&[
+            "iss",
+            "sub",
+            "aud",
+            "exp",
+            "iat",
+            "auth_time",
+            "nonce",
+            "email",
+            "preferred_username",
+        ]),
+        token_endpoint_auth_methods_supported: strings(&[
+            "client_secret_basic",
+            "client_secret_post",
+        ]),
+        code_challenge_methods_supported: strings(&[S256_METHOD]),
+    })
+}
@@ -0,0 +1,42 @@
+use actix_web::{web, HttpResponse};
+use serde::Deserialize;
+
+use crate::auth::client::authenticate_client;
+use crate::auth::token::introspection::introspect_token;
+use crate::auth::token::revocation::RevocationList;
+use crate::auth::token::signing_key::TokenSigner;
+use crate::errors::auth_error::AuthError;
+use crate::storage::Storage;
+
+#[derive(Deserialize)]
+pub struct IntrospectionRequest {
+    pub token: String,
+    pub client_id: Option<String>,
+    pub client_secret: Option<String>,
+}
+
+// Only clients registered as resource servers may ask, otherwise anyone could probe stolen
+// tokens and any partner client could read who is using the other clients
+pub async fn introspect(
+    req: actix_web::HttpRequest,
+    form: web::Form<IntrospectionRequest>,
+    storage: web::Data<dyn Storage>,
+    signer: web::Data<TokenSigner>,
+    revocations: web::Data<RevocationList>,
+) -> Result<HttpResponse, AuthError> {
+    let storage = storage.get_ref();
+    let client = authenticate_client(
+        &req,
+        form.client_id.as_deref(),
+        form.client_secret.as_deref(),
+        storage,
+    )
+    .await?;
+    if !client.resource_server {
+        return Err(AuthError::InsufficientPermissions(
+            "a client registered as resource server".to_string(),
+        ));
+    }
+    let introspection = introspect_token(&form.token, storage, &signer, &revocations).await?;
+    Ok(HttpResponse::Ok().json(introspection))
+}
@@ -0,0 +1,8 @@
+use actix_web::{web, HttpResponse};
+
+use crate::auth::token::signing_key::TokenSigner;
+
+// Public keys that verify access tokens, empty while per-user secrets are used
+pub async fn jwks(signer: web::Data<TokenSigner>) -> HttpResponse {
+    HttpResponse::Ok().json(signer.jwk_set())
+}
@@ -1,38 +1,108 @@
-use crate::auth::cookies::headers::{
-    get_new_access_token_cookie_header, get_new_refresh_token_cookie_header,
-    get_new_session_uuid_cookie_header,
-};
+use crate::auth::session::{drop_session_on_failure, enforce_session_limit, SessionPolicy};
+use crate::auth::token::access_token::create_access_token;
+use crate::auth::token::delivery::token_response;
+use crate::auth::token::refresh_token::create_refresh_token;
+use crate::auth::token::signing_key::TokenSigner;
 use crate::auth::utils::password::verify_password;
 use actix_web::http::header;
 use actix_web::{web, HttpResponse};
+use chrono::Utc;
 
 use crate::auth::user::{User, UserInfo};
+use crate::errors::auth_error::AuthError;
+use crate::storage::{NewSession, Storage};
 
-pub async fn proceed_with_login(user_data: &User, stored_user: &UserInfo) -> HttpResponse {
-    if let Ok(valid) = verify_password(&user_data.password, &stored_user.password).await {
-        if valid {
-            let pool = crate::db::create_pool().await.unwrap();
-            let session_uuid = crate::db::create_session(stored_user, &pool).await.unwrap();
+pub async fn proceed_with_login(
+    req: &actix_web::HttpRequest,
+    user_data: &User,
+    stored_user: &UserInfo,
+    storage: &dyn Storage,
+    signer: &TokenSigner,
+    policy: &SessionPolicy,
+) -> Result<HttpResponse, AuthError> {
+    if !verify_password(&user_data.password, &stored_user.password).await? {
+        return Err(AuthError::InvalidCredentials);
+    }
 
-            let access_token_header = get_new_access_token_cookie_header(stored_user).await;
-            let refresh_token_header = get_new_refresh_token_cookie_header(stored_user).await;
-            let session_header = get_new_session_uuid_cookie_header(&session_uuid).await;
+    // the address is only shown back to the user, a spoofed forwarding header does no harm
+    let ip_address = req
+        .connection_info()
+        .realip_remote_addr()
+        .map(str::to_string);
+    let user_agent = req
+        .headers()
+        .get(header::USER_AGENT)
+        .and_then(|value| value.to_str().ok());
+    let now = Utc::now().timestamp();
+    let session_uuid = storage
+        .create_session(&NewSession {
+            user_id: stored_user.user_id,
+            created_at: now,
+            expires_at: now + policy.absolute_lifetime,
+            idle_timeout: policy.idle_timeout,
+            ip_address: ip_address.as_deref(),
+            user_agent,
+            client_id: None,
+            scope: None,
+            auth_time: Some(now),
+        })
+        .await?;
 
-            return HttpResponse::Ok()
-                .append_header((header::SET_COOKIE, access_token_header))
-                .append_header((header::SET_COOKIE, refresh_token_header))
-                .append_header((header::SET_COOKIE, session_header))
-                .finish();
-        }
-    }
-    HttpResponse::Unauthorized().body("Invalid username or password")
+    // the refresh token is stored last, so nothing else outlives a dropped session
+    let issued = issue_session_tokens(
+        user_data,
+        stored_user,
+        &session_uuid,
+        storage,
+        signer,
+        policy,
+    )
+    .await;
+    drop_session_on_failure(issued, &session_uuid, storage).await
 }
 
-pub async fn login(user_data: web::Json<User>) -> impl actix_web::Responder {
-    let pool = crate::db::create_pool().await.unwrap();
+async fn issue_session_tokens(
+    user_data: &User,
+    stored_user: &UserInfo,
+    session_uuid: &str,
+    storage: &dyn Storage,
+    signer: &TokenSigner,
+    policy: &SessionPolicy,
+) -> Result<HttpResponse, AuthError> {
+    enforce_session_limit(stored_user.user_id, session_uuid, policy, storage).await?;
 
-    match crate::db::get_user_from_db(&user_data.username, &pool).await {
-        Ok(stored_user) => proceed_with_login(&user_data, &stored_user).await,
-        Err(_) => HttpResponse::Unauthorized().body("Invalid username or password"),
+    let access_token = create_access_token(stored_user, session_uuid, storage, signer).await?;
+    let refresh_token = create_refresh_token(stored_user, session_uuid, storage).await?;
+    token_response(
+        user_data.token_delivery,
+        access_token,
+        refresh_token,
+        Some(session_uuid),
+    )
+    .await
+}
+
+pub async fn login(
+    req: actix_web::HttpRequest,
+    user_data: web::Json<User>,
+    storage: web::Data<dyn Storage>,
+    signer: web::Data<TokenSigner>,
+    policy: web::Data<SessionPolicy>,
+) -> Result<HttpResponse, AuthError> {
+    match storage.get_user(&user_data.username).await {
+        Ok(stored_user) => {
+            proceed_with_login(
+                &req,
+                &user_data,
+                &stored_user,
+                storage.get_ref(),
+                &signer,
+                &policy,
+            )
+            .await
+        }
+        // an unknown username must be indistinguishable from a wrong password
+        Err(AuthError::RecordNotFound) => Err(AuthError::InvalidCredentials),
+        Err(e) => Err(e),
     }
 }
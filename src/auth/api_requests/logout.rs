@@ -1,22 +1,14 @@
-use actix_web::HttpResponse;
+use actix_web::{web, HttpResponse};
 
-use crate::auth::cookies::utils::get_session_uuid_from_cookie;
-use crate::auth::utils::validate_request::validate_http_request;
-use crate::db::drop_session;
-use crate::logging::log::log_warn;
+use crate::auth::guard::authenticated_user::AuthenticatedUser;
+use crate::errors::auth_error::AuthError;
+use crate::storage::Storage;
 
-pub async fn logout(req: actix_web::HttpRequest) -> impl actix_web::Responder {
-    match validate_http_request(&req).await {
-        Ok(_) => {
-            let session_uuid = get_session_uuid_from_cookie(&req).unwrap();
-            if drop_session(&session_uuid).await {
-                return HttpResponse::Ok().body("logout sucessfull");
-            }
-            HttpResponse::InternalServerError().into()
-        }
-        Err(e) => {
-            log_warn(&e.to_string());
-            HttpResponse::Unauthorized().body("You are not authorized to make this request")
-        }
-    }
+pub async fn logout(
+    user: AuthenticatedUser,
+    storage: web::Data<dyn Storage>,
+) -> Result<HttpResponse, AuthError> {
+    // the session the token is bound to, every token issued for it stops working
+    storage.drop_session(&user.session).await?;
+    Ok(HttpResponse::Ok().body("logout sucessfull"))
 }
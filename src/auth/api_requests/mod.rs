@@ -1,5 +1,14 @@
+pub mod authorize;
+pub mod discovery;
+pub mod introspect;
+pub mod jwks;
 pub mod login;
 pub mod logout;
 pub mod ping;
 pub mod refresh_token;
 pub mod register;
+pub mod revoke;
+pub mod sessions;
+pub mod token;
+pub mod userinfo;
+pub mod verify;
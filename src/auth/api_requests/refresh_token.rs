@@ -1,41 +1,55 @@
-use actix_web::http::header;
-use actix_web::HttpResponse;
+use actix_web::{web, HttpResponse};
+use serde::Deserialize;
 
-use crate::auth::cookies::headers::{
-    get_new_access_token_cookie_header, get_new_refresh_token_cookie_header,
-};
 use crate::auth::cookies::utils::{extract_refresh_token, extract_user_id_from_cookie};
-use crate::auth::token::refresh_token::validate_refresh_token;
+use crate::auth::token::access_token::{create_access_token, unverified_user_id};
+use crate::auth::token::delivery::{token_response, TokenDelivery};
+use crate::auth::token::refresh_token::{rotate_refresh_token, validate_refresh_token};
+use crate::auth::token::signing_key::TokenSigner;
+use crate::errors::auth_error::AuthError;
+use crate::storage::Storage;
 
-pub async fn refresh_token(req: actix_web::HttpRequest) -> impl actix_web::Responder {
-    match (
-        extract_refresh_token(&req),
-        extract_user_id_from_cookie(&req).await,
-    ) {
-        (Ok(token), Ok(user_id)) => {
-            let pool = crate::db::create_pool().await.unwrap();
-
-            if crate::db::does_user_id_exists(&pool, user_id)
-                .await
-                .unwrap()
-                && validate_refresh_token(&token, user_id, &pool)
-                    .await
-                    .unwrap()
-            {
-                let pool = crate::db::create_pool().await.unwrap();
-                let user = crate::db::get_user_from_db_with_user_id(user_id, &pool)
-                    .await
-                    .unwrap();
-                let token_cookie_header = get_new_access_token_cookie_header(&user).await;
-                let refresh_cookie_header = get_new_refresh_token_cookie_header(&user).await;
-                return HttpResponse::Ok()
-                    .append_header((header::SET_COOKIE, token_cookie_header))
-                    .append_header((header::SET_COOKIE, refresh_cookie_header))
-                    .finish();
-            }
+#[derive(Deserialize)]
+pub struct RefreshRequest {
+    pub refresh_token: String,
+}
 
-            HttpResponse::Unauthorized().into()
+// A refresh token sent in the body is answered in the body, otherwise the cookies are used.
+// Without a session cookie the user id comes from the token, `validate_refresh_token`
+// verifies it against the signature.
+pub async fn refresh_token(
+    req: actix_web::HttpRequest,
+    body: Option<web::Json<RefreshRequest>>,
+    storage: web::Data<dyn Storage>,
+    signer: web::Data<TokenSigner>,
+) -> Result<HttpResponse, AuthError> {
+    let storage = storage.get_ref();
+    let (token, user_id, delivery) = match body {
+        Some(body) => {
+            let token = body.into_inner().refresh_token;
+            let user_id = unverified_user_id(&token).ok_or(AuthError::InvalidToken)?;
+            (token, user_id, TokenDelivery::Body)
         }
-        _ => HttpResponse::BadRequest().into(),
+        None => (
+            extract_refresh_token(&req)?,
+            extract_user_id_from_cookie(&req, storage).await?,
+            TokenDelivery::Cookie,
+        ),
+    };
+
+    if !storage.does_user_id_exists(user_id).await? {
+        return Err(AuthError::InvalidToken);
     }
+    let (refresh_claims, session) = validate_refresh_token(&token, user_id, storage).await?;
+    // OAuth clients refresh at the token endpoint, where the client is authenticated too
+    if session.client_id.is_some() {
+        return Err(AuthError::InvalidToken);
+    }
+
+    let user = storage.get_user_with_user_id(user_id).await?;
+    // rotating first makes sure a replayed token never gets a new access token
+    let refresh_token = rotate_refresh_token(&refresh_claims, &user, storage).await?;
+    let access_token =
+        create_access_token(&user, &refresh_claims.session, storage, &signer).await?;
+    token_response(delivery, access_token, refresh_token, None).await
 }
@@ -0,0 +1,32 @@
+use actix_web::{web, HttpResponse};
+use serde::Deserialize;
+
+use crate::auth::token::revocation::{revoke_token, RevocationList};
+use crate::auth::token::signing_key::TokenSigner;
+use crate::errors::auth_error::AuthError;
+use crate::storage::Storage;
+
+#[derive(Deserialize)]
+pub struct RevocationRequest {
+    pub token: String,
+    pub token_type_hint: Option<String>,
+}
+
+// Possessing the token is enough to revoke it, nothing more can be done with a token
+// that is revoked than with one that is kept
+pub async fn revoke(
+    form: web::Form<RevocationRequest>,
+    storage: web::Data<dyn Storage>,
+    signer: web::Data<TokenSigner>,
+    revocations: web::Data<RevocationList>,
+) -> Result<HttpResponse, AuthError> {
+    revoke_token(
+        &form.token,
+        form.token_type_hint.as_deref(),
+        storage.get_ref(),
+        &signer,
+        &revocations,
+    )
+    .await?;
+    Ok(HttpResponse::Ok().finish())
+}
@@ -0,0 +1,76 @@
+use actix_web::{web, HttpResponse};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+
+use crate::auth::guard::authenticated_user::AuthenticatedUser;
+use crate::errors::auth_error::AuthError;
+use crate::storage::{Storage, StoredSession};
+
+// Session as shown to its owner, the session uuid never leaves the cookie
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SessionInfo {
+    pub id: i64,
+    pub created_at: i64,
+    pub last_used_at: i64,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub current: bool,
+}
+
+impl SessionInfo {
+    fn new(session: StoredSession, current_session_uuid: &str) -> Self {
+        SessionInfo {
+            id: session.session_id,
+            created_at: session.created_at,
+            last_used_at: session.last_used_at,
+            current: session.session_uuid == current_session_uuid,
+            ip_address: session.ip_address,
+            user_agent: session.user_agent,
+        }
+    }
+}
+
+pub async fn list_sessions(
+    user: AuthenticatedUser,
+    storage: web::Data<dyn Storage>,
+) -> Result<HttpResponse, AuthError> {
+    // expired sessions wait for the sweeper, they can not be used anymore
+    let now = Utc::now().timestamp();
+    let sessions: Vec<SessionInfo> = storage
+        .get_user_sessions(user.user_id)
+        .await?
+        .into_iter()
+        .filter(|session| !session.is_expired(now))
+        .map(|session| SessionInfo::new(session, &user.session))
+        .collect();
+    Ok(HttpResponse::Ok().json(sessions))
+}
+
+pub async fn revoke_session(
+    user: AuthenticatedUser,
+    session_id: web::Path<i64>,
+    storage: web::Data<dyn Storage>,
+) -> Result<HttpResponse, AuthError> {
+    let session_id = session_id.into_inner();
+    // only sessions of the caller can be found, someone else's id is just as unknown
+    let session = storage
+        .get_user_sessions(user.user_id)
+        .await?
+        .into_iter()
+        .find(|session| session.session_id == session_id)
+        .ok_or(AuthError::RecordNotFound)?;
+    storage.drop_session(&session.session_uuid).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+pub async fn revoke_other_sessions(
+    user: AuthenticatedUser,
+    storage: web::Data<dyn Storage>,
+) -> Result<HttpResponse, AuthError> {
+    for session in storage.get_user_sessions(user.user_id).await? {
+        if session.session_uuid != user.session {
+            storage.drop_session(&session.session_uuid).await?;
+        }
+    }
+    Ok(HttpResponse::NoContent().finish())
+}
@@ -0,0 +1,278 @@
+use actix_web::{web, HttpResponse};
+use chrono::Utc;
+use serde::Deserialize;
+
+use crate::auth::client::authenticate_client;
+use crate::auth::oauth::config::OAuthConfig;
+use crate::auth::oauth::id_token::{create_id_token, IdTokenRequest, OPENID_SCOPE};
+use crate::auth::oauth::pkce::verify_code_verifier;
+use crate::auth::oauth::scope::{has_scope, is_covered_by, join_scope, parse_scope};
+use crate::auth::session::{drop_session_on_failure, enforce_session_limit, SessionPolicy};
+use crate::auth::token::access_token::{create_access_token, unverified_user_id};
+use crate::auth::token::client_token::create_client_token;
+use crate::auth::token::delivery::{bearer_token_response, client_token_response};
+use crate::auth::token::refresh_token::{
+    create_refresh_token, rotate_refresh_token, validate_refresh_token,
+};
+use crate::auth::token::signing_key::TokenSigner;
+use crate::errors::auth_error::AuthError;
+use crate::errors::oauth_error::{
+    OAuthError, INVALID_SCOPE, UNAUTHORIZED_CLIENT, UNSUPPORTED_GRANT_TYPE,
+};
+use crate::storage::{NewSession, Storage, StoredClient};
+
+pub const AUTHORIZATION_CODE_GRANT: &str = "authorization_code";
+pub const REFRESH_TOKEN_GRANT: &str = "refresh_token";
+pub const CLIENT_CREDENTIALS_GRANT: &str = "client_credentials";
+
+#[derive(Deserialize)]
+pub struct TokenRequest {
+    pub grant_type: Option<String>,
+    pub code: Option<String>,
+    pub redirect_uri: Option<String>,
+    pub code_verifier: Option<String>,
+    pub refresh_token: Option<String>,
+    pub scope: Option<String>,
+    pub client_id: Option<String>,
+    pub client_secret: Option<String>,
+}
+
+fn required<'a>(value: &'a Option<String>, name: &str) -> Result<&'a str, OAuthError> {
+    value
+        .as_deref()
+        .ok_or_else(|| OAuthError::invalid_request(format!("{} is required", name)))
+}
+
+// Every check runs before the code is marked as used, the code is only burned by a request
+// that would have succeeded otherwise
+async fn exchange_authorization_code(
+    req: &actix_web::HttpRequest,
+    form: &TokenRequest,
+    client: &StoredClient,
+    storage: &dyn Storage,
+    signer: &TokenSigner,
+    policy: &SessionPolicy,
+    issuer: &str,
+) -> Result<HttpResponse, OAuthError> {
+    let code = required(&form.code, "code")?;
+    let redirect_uri = required(&form.redirect_uri, "redirect_uri")?;
+    let code_verifier = required(&form.code_verifier, "code_verifier")?;
+
+    let authorization_code = match storage.get_authorization_code(code).await {
+        Err(AuthError::RecordNotFound) => {
+            Err(OAuthError::invalid_grant("authorization code is not valid"))
+        }
+        result => result.map_err(OAuthError::from),
+    }?;
+    let now = Utc::now().timestamp();
+    if authorization_code.client_id != client.client_id {
+        return Err(OAuthError::invalid_grant(
+            "authorization code was issued to another client",
+        ));
+    }
+    if authorization_code.expires_at <= now {
+        return Err(OAuthError::invalid_grant("authorization code has expired"));
+    }
+    if authorization_code.redirect_uri != redirect_uri {
+        return Err(OAuthError::invalid_grant(
+            "redirect_uri does not match the authorization request",
+        ));
+    }
+    if !verify_code_verifier(code_verifier, &authorization_code.code_challenge) {
+        return Err(OAuthError::invalid_grant(
+            "code_verifier does not match the code challenge",
+        ));
+    }
+    if !storage.use_authorization_code(code, now).await? {
+        return Err(OAuthError::invalid_grant(
+            "authorization code was already used",
+        ));
+    }
+
+    let user = storage
+        .get_user_with_user_id(authorization_code.user_id)
+        .await?;
+    let ip_address = req
+        .connection_info()
+        .realip_remote_addr()
+        .map(str::to_string);
+    let session_uuid = storage
+        .create_session(&NewSession {
+            user_id: user.user_id,
+            created_at: now,
+            expires_at: now + policy.absolute_lifetime,
+            idle_timeout: policy.idle_timeout,
+            ip_address: ip_address.as_deref(),
+            user_agent: Some(&client.name),
+            client_id: Some(&client.client_id),
+            scope: authorization_code.scope.as_deref(),
+            auth_time: Some(authorization_code.auth_time),
+        })
+        .await?;
+    // the ID token is created before anything is stored, the refresh token last, so a
+    // failure leaves nothing behind but the session, which is dropped again
+    let issued: Result<HttpResponse, OAuthError> = async {
+        let scope = authorization_code.scope.as_deref();
+        let id_token = if has_scope(scope, OPENID_SCOPE) {
+            Some(create_id_token(
+                &user,
+                &IdTokenRequest {
+                    issuer,
+                    client_id: &client.client_id,
+                    scope,
+                    auth_time: Some(authorization_code.auth_time),
+                    nonce: authorization_code.nonce.as_deref(),
+                },
+                signer,
+            )?)
+        } else {
+            None
+        };
+        enforce_session_limit(user.user_id, &session_uuid, policy, storage).await?;
+
+        let access_token = create_access_token(&user, &session_uuid, storage, signer).await?;
+        let refresh_token = create_refresh_token(&user, &session_uuid, storage).await?;
+        Ok(bearer_token_response(
+            access_token,
+            refresh_token,
+            authorization_code.scope.clone(),
+            id_token,
+        ))
+    }
+    .await;
+    drop_session_on_failure(issued, &session_uuid, storage).await
+}
+
+// Refresh tokens of a client only work for that client, the session remembers who it was
+// created for. A new ID token reports the original login and carries no nonce.
+async fn refresh_access_token(
+    form: &TokenRequest,
+    client: &StoredClient,
+    storage: &dyn Storage,
+    signer: &TokenSigner,
+    issuer: &str,
+) -> Result<HttpResponse, OAuthError> {
+    let token = required(&form.refresh_token, "refresh_token")?;
+    let user_id = unverified_user_id(token).ok_or(AuthError::InvalidToken)?;
+    if !storage.does_user_id_exists(user_id).await? {
+        return Err(AuthError::InvalidToken.into());
+    }
+    let (refresh_claims, session) = validate_refresh_token(token, user_id, storage).await?;
+    if session.client_id.as_deref() != Some(client.client_id.as_str()) {
+        return Err(OAuthError::invalid_grant(
+            "refresh token was not issued to the client",
+        ));
+    }
+
+    let user = storage.get_user_with_user_id(user_id).await?;
+    // the ID token can not fail after the presented refresh token is used up
+    let scope = session.scope.as_deref();
+    let id_token = if has_scope(scope, OPENID_SCOPE) {
+        Some(create_id_token(
+            &user,
+            &IdTokenRequest {
+                issuer,
+                client_id: &client.client_id,
+                scope,
+                auth_time: session.auth_time,
+                nonce: None,
+            },
+            signer,
+        )?)
+    } else {
+        None
+    };
+    let refresh_token = rotate_refresh_token(&refresh_claims, &user, storage).await?;
+    let access_token = create_access_token(&user, &refresh_claims.session, storage, signer).await?;
+    Ok(bearer_token_response(
+        access_token,
+        refresh_token,
+        session.scope,
+        id_token,
+    ))
+}
+
+// Only clients registered with allowed scopes are machine clients. Leaving out the scope asks
+// for every allowed one.
+fn issue_client_token(
+    form: &TokenRequest,
+    client: &StoredClient,
+    signer: &TokenSigner,
+    oauth: &OAuthConfig,
+) -> Result<HttpResponse, OAuthError> {
+    if client.allowed_scopes.is_empty() {
+        return Err(OAuthError::new(
+            UNAUTHORIZED_CLIENT,
+            "client is not allowed to use the client_credentials grant",
+        ));
+    }
+    if matches!(signer, TokenSigner::PerUserSecret) {
+        return Err(OAuthError::new(
+            UNSUPPORTED_GRANT_TYPE,
+            "client_credentials needs an asymmetric JWT_SIGNING_ALGORITHM",
+        ));
+    }
+    let requested = parse_scope(form.scope.as_deref())
+        .map_err(|reason| OAuthError::new(INVALID_SCOPE, reason))?;
+    let scope = if requested.is_empty() {
+        Some(client.allowed_scopes.clone())
+    } else if is_covered_by(&requested, &client.allowed_scopes) {
+        join_scope(&requested)
+    } else {
+        return Err(OAuthError::new(
+            INVALID_SCOPE,
+            "scope is not allowed for the client",
+        ));
+    };
+    let access_token =
+        create_client_token(client, scope.clone(), oauth.client_token_lifetime, signer)?;
+    Ok(client_token_response(
+        access_token,
+        oauth.client_token_lifetime,
+        scope,
+    ))
+}
+
+// Token endpoint of RFC 6749, clients authenticate like they do for introspection
+pub async fn token(
+    req: actix_web::HttpRequest,
+    form: web::Form<TokenRequest>,
+    storage: web::Data<dyn Storage>,
+    signer: web::Data<TokenSigner>,
+    session_policy: web::Data<SessionPolicy>,
+    oauth: web::Data<OAuthConfig>,
+) -> Result<HttpResponse, OAuthError> {
+    let storage = storage.get_ref();
+    let issuer = oauth.issuer(&req);
+    let client = authenticate_client(
+        &req,
+        form.client_id.as_deref(),
+        form.client_secret.as_deref(),
+        storage,
+    )
+    .await?;
+
+    match form.grant_type.as_deref() {
+        Some(AUTHORIZATION_CODE_GRANT) => {
+            exchange_authorization_code(
+                &req,
+                &form,
+                &client,
+                storage,
+                &signer,
+                &session_policy,
+                &issuer,
+            )
+            .await
+        }
+        Some(REFRESH_TOKEN_GRANT) => {
+            refresh_access_token(&form, &client, storage, &signer, &issuer).await
+        }
+        Some(CLIENT_CREDENTIALS_GRANT) => issue_client_token(&form, &client, &signer, &oauth),
+        Some(grant_type) => Err(OAuthError::new(
+            UNSUPPORTED_GRANT_TYPE,
+            format!("grant type {} is not supported", grant_type),
+        )),
+        None => Err(OAuthError::invalid_request("grant_type is required")),
+    }
+}
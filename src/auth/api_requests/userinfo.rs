@@ -0,0 +1,31 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use serde::{Deserialize, Serialize};
+
+use crate::auth::guard::authenticated_user::AuthenticatedUser;
+use crate::auth::oauth::id_token::{email_claim, preferred_username_claim, OPENID_SCOPE};
+use crate::errors::auth_error::AuthError;
+use crate::storage::Storage;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UserInfoResponse {
+    pub sub: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preferred_username: Option<String>,
+}
+
+// OpenID Connect userinfo, answers the same claims the ID token of the grant carries
+pub async fn userinfo(
+    req: HttpRequest,
+    storage: web::Data<dyn Storage>,
+) -> Result<HttpResponse, AuthError> {
+    let user = AuthenticatedUser::authenticate_with_scope(&req, OPENID_SCOPE).await?;
+    let scope = user.claims.scope.as_deref();
+    let stored_user = storage.get_user_with_user_id(user.user_id).await?;
+    Ok(HttpResponse::Ok().json(UserInfoResponse {
+        sub: stored_user.user_id.to_string(),
+        email: email_claim(&stored_user, scope),
+        preferred_username: preferred_username_claim(&stored_user, scope),
+    }))
+}
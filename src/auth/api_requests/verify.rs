@@ -0,0 +1,46 @@
+use actix_web::http::header;
+use actix_web::{web, HttpResponse, ResponseError};
+
+use crate::auth::forward_auth::ForwardAuthConfig;
+use crate::auth::guard::authenticated_user::AuthenticatedUser;
+use crate::errors::auth_error::AuthError;
+use crate::storage::{meets_subscription_level, Storage};
+
+// Forward authentication for nginx `auth_request` and Traefik `ForwardAuth`. Proxies only
+// tell 2xx, 401 and 403 apart, so a request without any token is answered with 401 too.
+pub async fn verify(
+    req: actix_web::HttpRequest,
+    storage: web::Data<dyn Storage>,
+    config: web::Data<ForwardAuthConfig>,
+) -> Result<HttpResponse, AuthError> {
+    let user = match AuthenticatedUser::authenticate(&req).await {
+        Ok(user) => user,
+        Err(e) if e.status_code().is_server_error() => return Err(e),
+        Err(e) => {
+            if let Some(location) = config.login_redirect(&req) {
+                return Ok(HttpResponse::Found()
+                    .insert_header((header::LOCATION, location))
+                    .finish());
+            }
+            return Err(match e {
+                AuthError::MissingCookie(_) => AuthError::MissingCredentials,
+                e => e,
+            });
+        }
+    };
+
+    let subscription_level = storage.get_user_subscription_level(user.user_id).await?;
+    if let Some(required) = config.required_subscription_level(&req) {
+        if !meets_subscription_level(&subscription_level, required) {
+            return Err(AuthError::InsufficientPermissions(format!(
+                "subscription level {} or higher is required",
+                required
+            )));
+        }
+    }
+    Ok(HttpResponse::Ok()
+        .insert_header((config.user_header.clone(), user.username))
+        .insert_header((config.user_id_header.clone(), user.user_id.to_string()))
+        .insert_header((config.subscription_header.clone(), subscription_level))
+        .finish())
+}
@@ -0,0 +1,109 @@
+use actix_web::http::header;
+use base64::Engine;
+use chrono::Utc;
+use percent_encoding::percent_decode_str;
+use url::Url;
+use uuid::Uuid;
+
+use crate::auth::oauth::scope::{join_scope, parse_scope};
+use crate::auth::utils::password::{hash_password, verify_password};
+use crate::errors::auth_error::AuthError;
+use crate::storage::{Storage, StoredClient};
+use crate::utils::random::random_string;
+
+const CLIENT_SECRET_LENGTH: usize = 48;
+
+// Redirect uris are compared as exact strings, so they have to be absolute and may not
+// carry a fragment the authorization response could not be appended to
+pub fn validate_redirect_uri(redirect_uri: &str) -> Result<(), AuthError> {
+    let url = Url::parse(redirect_uri)
+        .map_err(|e| AuthError::InvalidRedirectUri(format!("{} ({})", redirect_uri, e)))?;
+    if url.fragment().is_some() {
+        return Err(AuthError::InvalidRedirectUri(format!(
+            "{} has a fragment",
+            redirect_uri
+        )));
+    }
+    if redirect_uri.contains(char::is_whitespace) {
+        return Err(AuthError::InvalidRedirectUri(format!(
+            "{} contains whitespace",
+            redirect_uri
+        )));
+    }
+    Ok(())
+}
+
+// The secret is returned only here, the storage keeps its hash. Clients given allowed scopes
+// are machine clients that may obtain tokens for themselves, resource servers may introspect
+// the tokens presented to them.
+pub async fn create_client(
+    storage: &dyn Storage,
+    name: &str,
+    redirect_uris: &[String],
+    allowed_scopes: &[String],
+    resource_server: bool,
+) -> Result<(StoredClient, String), AuthError> {
+    for redirect_uri in redirect_uris {
+        validate_redirect_uri(redirect_uri)?;
+    }
+    let allowed_scopes =
+        parse_scope(Some(&allowed_scopes.join(" "))).map_err(AuthError::InvalidScope)?;
+    let client_secret = random_string(CLIENT_SECRET_LENGTH);
+    let client = StoredClient {
+        client_id: Uuid::new_v4().to_string(),
+        client_secret: hash_password(&client_secret)?,
+        name: name.to_string(),
+        created_at: Utc::now().timestamp(),
+        redirect_uris: redirect_uris.join(" "),
+        allowed_scopes: join_scope(&allowed_scopes).unwrap_or_default(),
+        resource_server,
+    };
+    storage.store_client(&client).await?;
+    Ok((client, client_secret))
+}
+
+// RFC 6749 section 2.3.1, both halves are form-urlencoded before they are joined
+fn form_urldecode(value: &str) -> Option<String> {
+    percent_decode_str(&value.replace('+', " "))
+        .decode_utf8()
+        .ok()
+        .map(|decoded| decoded.into_owned())
+}
+
+fn basic_credentials(req: &actix_web::HttpRequest) -> Option<(String, String)> {
+    let value = req.headers().get(header::AUTHORIZATION)?.to_str().ok()?;
+    let encoded = value.strip_prefix("Basic ")?;
+    let decoded = base64::engine::general_purpose::STANDARD
+        .decode(encoded.trim())
+        .ok()?;
+    let (client_id, client_secret) = std::str::from_utf8(&decoded).ok()?.split_once(':')?;
+    Some((form_urldecode(client_id)?, form_urldecode(client_secret)?))
+}
+
+// Credentials come from HTTP Basic authentication or from the `client_id` and
+// `client_secret` parameters of the request body, the header wins when both are sent
+pub async fn authenticate_client(
+    req: &actix_web::HttpRequest,
+    client_id: Option<&str>,
+    client_secret: Option<&str>,
+    storage: &dyn Storage,
+) -> Result<StoredClient, AuthError> {
+    let (client_id, client_secret) = match basic_credentials(req) {
+        Some(credentials) => credentials,
+        None => match (client_id, client_secret) {
+            (Some(client_id), Some(client_secret)) => {
+                (client_id.to_string(), client_secret.to_string())
+            }
+            _ => return Err(AuthError::InvalidClient),
+        },
+    };
+
+    let client = match storage.get_client(&client_id).await {
+        Err(AuthError::RecordNotFound) => Err(AuthError::InvalidClient),
+        result => result,
+    }?;
+    if !verify_password(&client_secret, &client.client_secret).await? {
+        return Err(AuthError::InvalidClient);
+    }
+    Ok(client)
+}
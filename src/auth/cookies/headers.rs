@@ -1,49 +1,44 @@
-use crate::auth::token::access_token::create_access_token;
-use crate::auth::token::refresh_token::create_refresh_token;
 use actix_web::http::header::HeaderValue;
 use cookie::{Cookie, SameSite};
 
-use crate::auth::user::UserInfo;
+use crate::errors::auth_error::AuthError;
 
 // Created cookies needs to have two methods set up:
 // a) set_http_only(true) - This prevents access via client-side scripts
 // b) set_secure(true)    - Ensures cookie is only transmitted over HTTPS
 
-pub async fn get_new_access_token_cookie_header(stored_user: &UserInfo) -> HeaderValue {
-    // TODO: make safe version
-    let token = create_access_token(stored_user).await.unwrap();
+fn to_header_value(cookie: &Cookie) -> Result<HeaderValue, AuthError> {
+    HeaderValue::from_str(&cookie.to_string()).map_err(|e| {
+        AuthError::Internal(format!(
+            "cookie {} is not a valid header value: {}",
+            cookie.name(),
+            e
+        ))
+    })
+}
+
+pub fn get_access_token_cookie_header(token: String) -> Result<HeaderValue, AuthError> {
     let mut access_token_cookie = Cookie::new("token", token);
     access_token_cookie.set_http_only(true);
     access_token_cookie.set_secure(true);
     access_token_cookie.set_same_site(SameSite::Strict);
-    HeaderValue::from_str(&access_token_cookie.to_string()).unwrap()
+    to_header_value(&access_token_cookie)
 }
 
-pub async fn get_new_refresh_token_cookie_header(stored_user: &UserInfo) -> HeaderValue {
-    // TODO: make safe version
-    let refresh_token = create_refresh_token(stored_user).await.unwrap();
+pub fn get_refresh_token_cookie_header(refresh_token: String) -> Result<HeaderValue, AuthError> {
     let mut refresh_token_cookie = Cookie::new("refresh_token", refresh_token);
     refresh_token_cookie.set_http_only(true);
     refresh_token_cookie.set_secure(true);
     refresh_token_cookie.set_same_site(SameSite::Strict);
-    HeaderValue::from_str(&refresh_token_cookie.to_string()).unwrap()
+    to_header_value(&refresh_token_cookie)
 }
 
-pub async fn get_new_session_uuid_cookie_header(session_uuid: &String) -> HeaderValue {
+pub async fn get_new_session_uuid_cookie_header(
+    session_uuid: &str,
+) -> Result<HeaderValue, AuthError> {
     let mut session_cookie = Cookie::new("session", session_uuid);
     session_cookie.set_http_only(true);
     session_cookie.set_secure(true);
     session_cookie.set_same_site(SameSite::Strict);
-    HeaderValue::from_str(&session_cookie.to_string()).unwrap()
-}
-
-pub fn extract_refresh_token(
-    req: &actix_web::HttpRequest,
-) -> Result<String, actix_web::error::Error> {
-    match req.cookie("refresh_token") {
-        Some(cookie) => Ok(cookie.value().to_string()),
-        None => Err(actix_web::error::ErrorBadRequest(
-            "No refresh token in cookie",
-        )),
-    }
+    to_header_value(&session_cookie)
 }
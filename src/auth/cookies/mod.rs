@@ -1,3 +1,2 @@
-pub mod errors;
 pub mod headers;
 pub mod utils;
@@ -1,40 +1,34 @@
-use crate::auth::cookies::errors::NoValueInCookie;
-use crate::db::get_user_id_with_session_uuid;
+use crate::errors::auth_error::AuthError;
+use crate::storage::Storage;
 
-pub fn get_token_from_cookie(
-    req: &actix_web::HttpRequest,
-) -> Result<String, Box<dyn std::error::Error>> {
+pub fn get_token_from_cookie(req: &actix_web::HttpRequest) -> Result<String, AuthError> {
     match req.cookie("token") {
         Some(cookie) => Ok(cookie.value().to_string()),
-        None => Err(Box::new(NoValueInCookie)),
+        None => Err(AuthError::MissingCookie("token")),
     }
 }
 
-pub fn get_session_uuid_from_cookie(
-    req: &actix_web::HttpRequest,
-) -> Result<String, Box<dyn std::error::Error>> {
+pub fn get_session_uuid_from_cookie(req: &actix_web::HttpRequest) -> Result<String, AuthError> {
     match req.cookie("session") {
         Some(cookie) => Ok(cookie.value().to_string()),
-        None => Err(Box::new(NoValueInCookie)),
+        None => Err(AuthError::MissingCookie("session")),
     }
 }
 
 pub async fn extract_user_id_from_cookie(
     req: &actix_web::HttpRequest,
-) -> Result<i64, Box<dyn std::error::Error>> {
-    match get_session_uuid_from_cookie(req) {
-        Ok(session_uuid) => get_user_id_with_session_uuid(&session_uuid).await,
-        Err(e) => Err(e),
+    storage: &dyn Storage,
+) -> Result<i64, AuthError> {
+    let session_uuid = get_session_uuid_from_cookie(req)?;
+    match storage.get_session_user_id(&session_uuid).await {
+        Err(AuthError::RecordNotFound) => Err(AuthError::SessionNotFound),
+        result => result,
     }
 }
 
-pub fn extract_refresh_token(
-    req: &actix_web::HttpRequest,
-) -> Result<String, actix_web::error::Error> {
+pub fn extract_refresh_token(req: &actix_web::HttpRequest) -> Result<String, AuthError> {
     match req.cookie("refresh_token") {
         Some(cookie) => Ok(cookie.value().to_string()),
-        None => Err(actix_web::error::ErrorBadRequest(
-            "No refresh token in cookie",
-        )),
+        None => Err(AuthError::MissingCookie("refresh_token")),
     }
 }
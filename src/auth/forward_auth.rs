@@ -0,0 +1,176 @@
+use std::str::FromStr;
+
+use actix_web::http::header::HeaderName;
+use actix_web::HttpRequest;
+use percent_encoding::percent_decode_str;
+use url::Url;
+
+use crate::logging::log::log_warn;
+use crate::startup::environment_constants::EnvironmentConstants;
+use crate::storage::SUBSCRIPTION_LEVELS;
+
+// Header names are case insensitive, `HeaderName` keeps them in lowercase
+pub const DEFAULT_USER_HEADER: &str = "x-auth-user";
+pub const DEFAULT_USER_ID_HEADER: &str = "x-auth-user-id";
+pub const DEFAULT_SUBSCRIPTION_HEADER: &str = "x-auth-subscription";
+
+// Traefik sends the original request in `X-Forwarded-*` headers, nginx only passes what
+// it is told to, `proxy_set_header X-Original-URI $request_uri` is the usual choice
+const FORWARDED_URI_HEADERS: [&str; 2] = ["X-Forwarded-Uri", "X-Original-URI"];
+const FORWARDED_HOST_HEADER: &str = "X-Forwarded-Host";
+const FORWARDED_PROTO_HEADER: &str = "X-Forwarded-Proto";
+const REDIRECT_PARAMETER: &str = "rd";
+
+// How `/auth/verify` answers a reverse proxy. Path rules are kept normalized and longest
+// prefix first, so the most specific rule decides the subscription level a path requires.
+#[derive(Clone)]
+pub struct ForwardAuthConfig {
+    pub user_header: HeaderName,
+    pub user_id_header: HeaderName,
+    pub subscription_header: HeaderName,
+    pub login_url: Option<Url>,
+    pub path_rules: Vec<(String, String)>,
+}
+
+fn parse_header_name(name: &str, default: &'static str) -> HeaderName {
+    HeaderName::from_str(name).unwrap_or_else(|_| {
+        log_warn(&format!(
+            "{} is not a valid header name, {} is used instead",
+            name, default
+        ));
+        HeaderName::from_static(default)
+    })
+}
+
+// The path the upstream will most likely serve: percent-decoded, lowercase, without empty
+// or dot segments. Matching anything less lets `//premium`, `/x/../premium`, `/%70remium`
+// or `/Premium` past the rule of `/premium`.
+pub fn normalize_path(path: &str) -> String {
+    let decoded = percent_decode_str(path).decode_utf8_lossy().to_lowercase();
+    let mut segments: Vec<&str> = Vec::new();
+    for segment in decoded.split(['/', '\\']) {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop();
+            }
+            segment => segments.push(segment),
+        }
+    }
+    format!("/{}", segments.join("/"))
+}
+
+// Rules match whole segments, the rule of `/premium` covers `/premium/video` but not
+// `/premiumfoo`
+fn matches_prefix(path: &str, prefix: &str) -> bool {
+    prefix == "/"
+        || path
+            .strip_prefix(prefix)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+pub fn parse_path_rules(rules: &str) -> Result<Vec<(String, String)>, String> {
+    let mut path_rules = rules
+        .split(',')
+        .map(str::trim)
+        .filter(|rule| !rule.is_empty())
+        .map(|rule| match rule.split_once('=') {
+            Some((prefix, level))
+                if prefix.starts_with('/') && SUBSCRIPTION_LEVELS.contains(&level.trim()) =>
+            {
+                Ok((normalize_path(prefix.trim()), level.trim().to_string()))
+            }
+            _ => Err(format!(
+                "invalid path rule {}, expected /prefix=subscription-level",
+                rule
+            )),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    path_rules.sort_by_key(|(prefix, _)| std::cmp::Reverse(prefix.len()));
+    Ok(path_rules)
+}
+
+impl ForwardAuthConfig {
+    // Invalid path rules fail the startup, dropping one would open its paths to everyone
+    pub fn from_environment(
+        env_constants: &EnvironmentConstants,
+    ) -> Result<ForwardAuthConfig, String> {
+        let login_url = match env_constants.forward_auth_login_url.as_str() {
+            "" => None,
+            login_url => Url::parse(login_url)
+                .inspect_err(|e| {
+                    log_warn(&format!(
+                        "login url {} is not valid ({}), unauthenticated requests get 401",
+                        login_url, e
+                    ))
+                })
+                .ok(),
+        };
+        let path_rules = parse_path_rules(&env_constants.forward_auth_path_rules)?;
+        Ok(ForwardAuthConfig {
+            user_header: parse_header_name(
+                &env_constants.forward_auth_user_header,
+                DEFAULT_USER_HEADER,
+            ),
+            user_id_header: parse_header_name(
+                &env_constants.forward_auth_user_id_header,
+                DEFAULT_USER_ID_HEADER,
+            ),
+            subscription_header: parse_header_name(
+                &env_constants.forward_auth_subscription_header,
+                DEFAULT_SUBSCRIPTION_HEADER,
+            ),
+            login_url,
+            path_rules,
+        })
+    }
+
+    // Without a forwarded uri the rules of `/` apply, so a misconfigured proxy does not
+    // skip them
+    pub fn required_subscription_level(&self, req: &HttpRequest) -> Option<&str> {
+        let uri = forwarded_header(req, &FORWARDED_URI_HEADERS).unwrap_or("/");
+        let path = normalize_path(uri.split(['?', '#']).next().unwrap_or("/"));
+        self.path_rules
+            .iter()
+            .find(|(prefix, _)| matches_prefix(&path, prefix))
+            .map(|(_, level)| level.as_str())
+    }
+
+    // The original url goes along when the proxy told it, so the login page can send the
+    // user back
+    pub fn login_redirect(&self, req: &HttpRequest) -> Option<String> {
+        let mut login_url = self.login_url.clone()?;
+        let original_url = match (
+            forwarded_header(req, &[FORWARDED_PROTO_HEADER]),
+            forwarded_header(req, &[FORWARDED_HOST_HEADER]),
+            forwarded_header(req, &FORWARDED_URI_HEADERS),
+        ) {
+            (Some(proto), Some(host), Some(uri)) => Some(format!("{}://{}{}", proto, host, uri)),
+            _ => None,
+        };
+        if let Some(original_url) = original_url {
+            login_url
+                .query_pairs_mut()
+                .append_pair(REDIRECT_PARAMETER, &original_url);
+        }
+        Some(login_url.to_string())
+    }
+}
+
+impl Default for ForwardAuthConfig {
+    fn default() -> ForwardAuthConfig {
+        ForwardAuthConfig {
+            user_header: HeaderName::from_static(DEFAULT_USER_HEADER),
+            user_id_header: HeaderName::from_static(DEFAULT_USER_ID_HEADER),
+            subscription_header: HeaderName::from_static(DEFAULT_SUBSCRIPTION_HEADER),
+            login_url: None,
+            path_rules: Vec::new(),
+        }
+    }
+}
+
+fn forwarded_header<'a>(req: &'a HttpRequest, names: &[&str]) -> Option<&'a str> {
+    names
+        .iter()
+        .find_map(|name| req.headers().get(*name)?.to_str().ok())
+}
@@ -0,0 +1,96 @@
+use std::future::Future;
+use std::pin::Pin;
+
+use actix_web::dev::Payload;
+use actix_web::{web, FromRequest, HttpMessage, HttpRequest};
+
+use crate::auth::oauth::scope::has_scope;
+use crate::auth::token::access_token::Claims;
+use crate::auth::token::revocation::RevocationList;
+use crate::auth::token::signing_key::TokenSigner;
+use crate::auth::utils::validate_request::validate_http_request;
+use crate::errors::auth_error::AuthError;
+use crate::storage::Storage;
+
+// Caller of a protected endpoint, validated from the bearer token or the cookies.
+// Taking it as a handler argument is all an endpoint needs to require authentication.
+#[derive(Debug, Clone)]
+pub struct AuthenticatedUser {
+    pub user_id: i64,
+    pub username: String,
+    pub session: String,
+    pub client_id: Option<String>,
+    pub claims: Claims,
+}
+
+pub(crate) fn app_data<T: ?Sized + 'static>(
+    req: &HttpRequest,
+    name: &str,
+) -> Result<web::Data<T>, AuthError> {
+    req.app_data::<web::Data<T>>()
+        .cloned()
+        .ok_or_else(|| AuthError::Internal(format!("{} is not registered as app data", name)))
+}
+
+impl AuthenticatedUser {
+    // Only the user's own logins are let through. A token issued to an OAuth client acts
+    // within its scope and must not manage the user's sessions or pass the gateway.
+    pub async fn authenticate(req: &HttpRequest) -> Result<AuthenticatedUser, AuthError> {
+        let user = AuthenticatedUser::validate(req).await?;
+        if user.client_id.is_some() {
+            return Err(AuthError::InsufficientPermissions(
+                "a token of the user's own login".to_string(),
+            ));
+        }
+        Ok(user)
+    }
+
+    // For endpoints serving OAuth clients, any token granted `scope` is accepted
+    pub async fn authenticate_with_scope(
+        req: &HttpRequest,
+        scope: &str,
+    ) -> Result<AuthenticatedUser, AuthError> {
+        let user = AuthenticatedUser::validate(req).await?;
+        if !has_scope(user.claims.scope.as_deref(), scope) {
+            return Err(AuthError::InsufficientPermissions(format!(
+                "scope {}",
+                scope
+            )));
+        }
+        Ok(user)
+    }
+
+    // The user is kept in the request extensions, so a guarded scope and the handler behind
+    // it validate the token only once
+    async fn validate(req: &HttpRequest) -> Result<AuthenticatedUser, AuthError> {
+        let authenticated = req.extensions().get::<AuthenticatedUser>().cloned();
+        if let Some(user) = authenticated {
+            return Ok(user);
+        }
+
+        let storage = app_data::<dyn Storage>(req, "Storage")?;
+        let signer = app_data::<TokenSigner>(req, "TokenSigner")?;
+        let revocations = app_data::<RevocationList>(req, "RevocationList")?;
+        let (claims, session) =
+            validate_http_request(req, storage.get_ref(), &signer, &revocations).await?;
+        let user = AuthenticatedUser {
+            user_id: claims.user_id()?,
+            username: claims.username.clone(),
+            session: claims.session.clone(),
+            client_id: session.client_id,
+            claims,
+        };
+        req.extensions_mut().insert(user.clone());
+        Ok(user)
+    }
+}
+
+impl FromRequest for AuthenticatedUser {
+    type Error = AuthError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let req = req.clone();
+        Box::pin(async move { AuthenticatedUser::authenticate(&req).await })
+    }
+}
@@ -0,0 +1,2 @@
+pub mod authenticated_user;
+pub mod require_auth;
@@ -0,0 +1,119 @@
+use std::future::{ready, Future, Ready};
+use std::pin::Pin;
+use std::rc::Rc;
+
+use actix_web::body::EitherBody;
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::HttpRequest;
+
+use crate::auth::guard::authenticated_user::{app_data, AuthenticatedUser};
+use crate::errors::auth_error::AuthError;
+use crate::storage::{meets_subscription_level, Storage, SUBSCRIPTION_LEVELS};
+
+// Middleware for a `web::scope` whose every endpoint requires an authenticated user, e.g.
+// `web::scope("/reports").wrap(RequireAuth::new().role("analyst"))`. Handlers behind it
+// still take `AuthenticatedUser` to learn who is calling.
+#[derive(Clone, Default)]
+pub struct RequireAuth {
+    roles: Vec<String>,
+    subscription_level: Option<String>,
+}
+
+impl RequireAuth {
+    pub fn new() -> RequireAuth {
+        RequireAuth::default()
+    }
+
+    // Every role added is required
+    pub fn role(mut self, role: &str) -> RequireAuth {
+        self.roles.push(role.to_string());
+        self
+    }
+
+    // Higher subscription levels are let through as well. Panics on an unknown level, the
+    // guards are set up once when the server starts.
+    pub fn subscription_level(mut self, level: &str) -> RequireAuth {
+        assert!(
+            SUBSCRIPTION_LEVELS.contains(&level),
+            "unknown subscription level {}",
+            level
+        );
+        self.subscription_level = Some(level.to_string());
+        self
+    }
+
+    async fn authorize(&self, req: &HttpRequest) -> Result<(), AuthError> {
+        let user = AuthenticatedUser::authenticate(req).await?;
+        let storage = app_data::<dyn Storage>(req, "Storage")?;
+        if !self.roles.is_empty() {
+            let granted = storage.get_user_roles(user.user_id).await?;
+            if let Some(missing) = self.roles.iter().find(|role| !granted.contains(role)) {
+                return Err(AuthError::InsufficientPermissions(format!(
+                    "role {} is required",
+                    missing
+                )));
+            }
+        }
+        if let Some(required) = &self.subscription_level {
+            let level = storage.get_user_subscription_level(user.user_id).await?;
+            if !meets_subscription_level(&level, required) {
+                return Err(AuthError::InsufficientPermissions(format!(
+                    "subscription level {} or higher is required",
+                    required
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for RequireAuth
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = actix_web::Error;
+    type Transform = RequireAuthMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RequireAuthMiddleware {
+            service: Rc::new(service),
+            requirements: Rc::new(self.clone()),
+        }))
+    }
+}
+
+pub struct RequireAuthMiddleware<S> {
+    service: Rc<S>,
+    requirements: Rc<RequireAuth>,
+}
+
+impl<S, B> Service<ServiceRequest> for RequireAuthMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = actix_web::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+        let requirements = Rc::clone(&self.requirements);
+        Box::pin(async move {
+            // rejected requests are answered here, so outer middleware still sees a response
+            if let Err(e) = requirements.authorize(req.request()).await {
+                return Ok(req.error_response(e).map_into_right_body());
+            }
+            service
+                .call(req)
+                .await
+                .map(ServiceResponse::map_into_left_body)
+        })
+    }
+}
@@ -1,5 +1,10 @@
 pub mod api_requests;
+pub mod client;
 pub mod cookies;
+pub mod forward_auth;
+pub mod guard;
+pub mod oauth;
+pub mod session;
 pub mod token;
 pub mod user;
 pub mod utils;
@@ -0,0 +1,93 @@
+use actix_web::HttpRequest;
+use url::Url;
+
+use crate::logging::log::log_warn;
+use crate::startup::environment_constants::EnvironmentConstants;
+
+pub const DEFAULT_CODE_LIFETIME: i64 = 60;
+pub const DEFAULT_CLIENT_TOKEN_LIFETIME: i64 = 300;
+
+// How `/oauth/authorize` treats users without a session, how long an issued authorization
+// code may wait for its exchange and a client credentials token lives, in seconds, and the
+// issuer named in ID tokens
+#[derive(Clone)]
+pub struct OAuthConfig {
+    pub login_url: Option<Url>,
+    pub code_lifetime: i64,
+    pub client_token_lifetime: i64,
+    pub issuer: Option<String>,
+}
+
+impl OAuthConfig {
+    pub fn from_environment(env_constants: &EnvironmentConstants) -> OAuthConfig {
+        let login_url = match env_constants.oauth_login_url.as_str() {
+            "" => None,
+            login_url => Url::parse(login_url)
+                .inspect_err(|e| {
+                    log_warn(&format!(
+                        "oauth login url {} is not valid ({}), users without a session get 401",
+                        login_url, e
+                    ))
+                })
+                .ok(),
+        };
+        let code_lifetime = match env_constants.oauth_code_lifetime {
+            code_lifetime if code_lifetime > 0 => code_lifetime,
+            code_lifetime => {
+                log_warn(&format!(
+                    "authorization code lifetime {} is not positive, {} is used instead",
+                    code_lifetime, DEFAULT_CODE_LIFETIME
+                ));
+                DEFAULT_CODE_LIFETIME
+            }
+        };
+        let client_token_lifetime = match env_constants.oauth_client_token_lifetime {
+            client_token_lifetime if client_token_lifetime > 0 => client_token_lifetime,
+            client_token_lifetime => {
+                log_warn(&format!(
+                    "client token lifetime {} is not positive, {} is used instead",
+                    client_token_lifetime, DEFAULT_CLIENT_TOKEN_LIFETIME
+                ));
+                DEFAULT_CLIENT_TOKEN_LIFETIME
+            }
+        };
+        let issuer = Some(env_constants.oidc_issuer.trim_end_matches('/').to_string())
+            .filter(|issuer| !issuer.is_empty());
+        OAuthConfig {
+            login_url,
+            code_lifetime,
+            client_token_lifetime,
+            issuer,
+        }
+    }
+
+    // Relying parties compare the issuer exactly, deriving it from the request only suits
+    // deployments reached under a single host
+    pub fn issuer(&self, req: &HttpRequest) -> String {
+        match &self.issuer {
+            Some(issuer) => issuer.clone(),
+            None => {
+                let connection_info = req.connection_info();
+                format!("{}://{}", connection_info.scheme(), connection_info.host())
+            }
+        }
+    }
+
+    // The authorization request goes along, so the login page can send the user back
+    pub fn login_redirect(&self, authorize_url: &str) -> Option<String> {
+        let mut login_url = self.login_url.clone()?;
+        login_url.query_pairs_mut().append_pair("rd", authorize_url);
+        Some(login_url.to_string())
+    }
+}
+
+impl Default for OAuthConfig {
+    fn default() -> OAuthConfig {
+        OAuthConfig {
+            login_url: None,
+            code_lifetime: DEFAULT_CODE_LIFETIME,
+            client_token_lifetime: DEFAULT_CLIENT_TOKEN_LIFETIME,
+            issuer: None,
+        }
+    }
+}
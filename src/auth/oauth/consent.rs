@@ -0,0 +1,61 @@
+// The page posts the authorization request back to `/oauth/authorize` together with the
+// decision of the user. The session cookie is `SameSite=Strict`, so a form on another
+// site can not decide in the name of the user.
+pub struct ConsentPage<'a> {
+    pub client_name: &'a str,
+    pub username: &'a str,
+    pub scopes: &'a [String],
+    pub hidden_fields: Vec<(&'static str, &'a str)>,
+}
+
+pub fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for character in text.chars() {
+        match character {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#x27;"),
+            character => escaped.push(character),
+        }
+    }
+    escaped
+}
+
+impl ConsentPage<'_> {
+    pub fn render(&self) -> String {
+        let scopes = if self.scopes.is_empty() {
+            "<li>your account</li>".to_string()
+        } else {
+            self.scopes
+                .iter()
+                .map(|scope| format!("<li>{}</li>", escape_html(scope)))
+                .collect()
+        };
+        let hidden_fields: String = self
+            .hidden_fields
+            .iter()
+            .map(|(name, value)| {
+                format!(
+                    "<input type=\"hidden\" name=\"{}\" value=\"{}\">",
+                    name,
+                    escape_html(value)
+                )
+            })
+            .collect();
+        format!(
+            "<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"><title>Authorize {client}</title></head>\n\
+             <body>\n<p>Signed in as {username}</p>\n\
+             <p>{client} asks for access to:</p>\n<ul>{scopes}</ul>\n\
+             <form method=\"post\" action=\"/oauth/authorize\">{hidden_fields}\
+             <button type=\"submit\" name=\"consent\" value=\"allow\">Allow</button>\
+             <button type=\"submit\" name=\"consent\" value=\"deny\">Deny</button>\
+             </form>\n</body>\n</html>\n",
+            client = escape_html(self.client_name),
+            username = escape_html(self.username),
+            scopes = scopes,
+            hidden_fields = hidden_fields,
+        )
+    }
+}
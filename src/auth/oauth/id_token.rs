@@ -0,0 +1,85 @@
+use chrono::Utc;
+use jsonwebtoken::encode;
+use serde::{Deserialize, Serialize};
+
+use crate::auth::oauth::scope::has_scope;
+use crate::auth::token::access_token::token_header;
+use crate::auth::token::constants::{ACCESS_TOKEN_EXPIRATION, ID_TOKEN_TYPE};
+use crate::auth::token::signing_key::TokenSigner;
+use crate::auth::user::UserInfo;
+use crate::errors::auth_error::AuthError;
+
+pub const OPENID_SCOPE: &str = "openid";
+pub const EMAIL_SCOPE: &str = "email";
+pub const PROFILE_SCOPE: &str = "profile";
+
+// Claims of an OpenID Connect ID token, `aud` is the client the user logged in to. Optional
+// claims are only present when the scope asked for them.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct IdTokenClaims {
+    pub iss: String,
+    pub sub: String,
+    pub aud: String,
+    pub exp: i64,
+    pub iat: i64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auth_time: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nonce: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preferred_username: Option<String>,
+}
+
+// What the scope reveals about the user, shared by ID tokens and the userinfo endpoint
+pub fn email_claim(user: &UserInfo, scope: Option<&str>) -> Option<String> {
+    Some(user.email.clone()).filter(|_| has_scope(scope, EMAIL_SCOPE))
+}
+
+pub fn preferred_username_claim(user: &UserInfo, scope: Option<&str>) -> Option<String> {
+    Some(user.username.clone()).filter(|_| has_scope(scope, PROFILE_SCOPE))
+}
+
+pub struct IdTokenRequest<'a> {
+    pub issuer: &'a str,
+    pub client_id: &'a str,
+    pub scope: Option<&'a str>,
+    pub auth_time: Option<i64>,
+    pub nonce: Option<&'a str>,
+}
+
+// Relying parties verify ID tokens with the published keys, per-user secrets can not be
+// shared with them, so `openid` is refused before a code is issued in that case
+pub fn create_id_token(
+    user: &UserInfo,
+    request: &IdTokenRequest,
+    signer: &TokenSigner,
+) -> Result<String, AuthError> {
+    let keyring = match signer {
+        TokenSigner::Keyring(keyring) => keyring,
+        TokenSigner::PerUserSecret => {
+            return Err(AuthError::Internal(
+                "ID tokens need an asymmetric signing algorithm".to_string(),
+            ))
+        }
+    };
+    let now = Utc::now().timestamp();
+    let claims = IdTokenClaims {
+        iss: request.issuer.to_string(),
+        sub: user.user_id.to_string(),
+        aud: request.client_id.to_string(),
+        exp: now + ACCESS_TOKEN_EXPIRATION,
+        iat: now,
+        auth_time: request.auth_time,
+        nonce: request.nonce.map(str::to_string),
+        email: email_claim(user, request.scope),
+        preferred_username: preferred_username_claim(user, request.scope),
+    };
+    let (header, encoding_key) = keyring.signing_key()?;
+    Ok(encode(
+        &token_header(header, ID_TOKEN_TYPE),
+        &claims,
+        &encoding_key,
+    )?)
+}
@@ -0,0 +1,5 @@
+pub mod config;
+pub mod consent;
+pub mod id_token;
+pub mod pkce;
+pub mod scope;
@@ -0,0 +1,37 @@
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+
+// `plain` would hand the verifier to anyone who sees the authorization request
+pub const S256_METHOD: &str = "S256";
+
+const MIN_CODE_VERIFIER_LENGTH: usize = 43;
+const MAX_CODE_VERIFIER_LENGTH: usize = 128;
+
+// RFC 7636, the verifier uses the unreserved characters of RFC 3986 only
+pub fn is_valid_code_verifier(code_verifier: &str) -> bool {
+    (MIN_CODE_VERIFIER_LENGTH..=MAX_CODE_VERIFIER_LENGTH).contains(&code_verifier.len())
+        && code_verifier
+            .bytes()
+            .all(|byte| byte.is_ascii_alphanumeric() || b"-._~".contains(&byte))
+}
+
+// A S256 challenge is the unpadded base64url encoding of a SHA-256 hash
+pub fn is_valid_code_challenge(code_challenge: &str) -> bool {
+    URL_SAFE_NO_PAD
+        .decode(code_challenge)
+        .map(|hash| hash.len() == 32)
+        .unwrap_or(false)
+}
+
+pub fn code_challenge(code_verifier: &str) -> String {
+    URL_SAFE_NO_PAD.encode(openssl::sha::sha256(code_verifier.as_bytes()))
+}
+
+pub fn verify_code_verifier(code_verifier: &str, code_challenge_to_match: &str) -> bool {
+    if !is_valid_code_verifier(code_verifier) {
+        return false;
+    }
+    let challenge = code_challenge(code_verifier);
+    challenge.len() == code_challenge_to_match.len()
+        && openssl::memcmp::eq(challenge.as_bytes(), code_challenge_to_match.as_bytes())
+}
@@ -0,0 +1,36 @@
+// Scopes are space separated tokens of printable ASCII without `"` and `\`, the order
+// carries no meaning, so they are kept sorted and without duplicates
+pub fn parse_scope(scope: Option<&str>) -> Result<Vec<String>, String> {
+    let mut scopes = Vec::new();
+    for token in scope
+        .unwrap_or_default()
+        .split(' ')
+        .filter(|token| !token.is_empty())
+    {
+        if !token
+            .bytes()
+            .all(|byte| (0x21..=0x7e).contains(&byte) && byte != b'"' && byte != b'\\')
+        {
+            return Err(format!("scope {} contains invalid characters", token));
+        }
+        scopes.push(token.to_string());
+    }
+    scopes.sort();
+    scopes.dedup();
+    Ok(scopes)
+}
+
+pub fn join_scope(scopes: &[String]) -> Option<String> {
+    (!scopes.is_empty()).then(|| scopes.join(" "))
+}
+
+// A consent given earlier counts for every scope it named
+pub fn is_covered_by(requested: &[String], consented: &str) -> bool {
+    requested
+        .iter()
+        .all(|scope| consented.split(' ').any(|granted| granted == scope))
+}
+
+pub fn has_scope(scope: Option<&str>, name: &str) -> bool {
+    scope.is_some_and(|scope| scope.split(' ').any(|granted| granted == name))
+}
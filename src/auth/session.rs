@@ -0,0 +1,220 @@
+use std::time::Duration;
+
+use chrono::Utc;
+
+use crate::errors::auth_error::AuthError;
+use crate::logging::log::{log_error, log_info, log_warn};
+use crate::startup::environment_constants::EnvironmentConstants;
+use crate::storage::{Storage, StoredSession, SUBSCRIPTION_LEVELS};
+
+pub const DEFAULT_SESSION_IDLE_TIMEOUT: i64 = 86400;
+pub const DEFAULT_SESSION_ABSOLUTE_LIFETIME: i64 = 2592000;
+pub const DEFAULT_SESSION_SWEEP_INTERVAL: u64 = 600;
+
+pub const REJECT_NEW_SESSION: &str = "reject";
+pub const EVICT_OLDEST_SESSION: &str = "evict-oldest";
+
+// What happens to a login that would exceed the session limit of the user
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SessionLimitPolicy {
+    RejectNew,
+    EvictOldest,
+}
+
+impl std::str::FromStr for SessionLimitPolicy {
+    type Err = String;
+
+    fn from_str(policy: &str) -> Result<Self, Self::Err> {
+        match policy {
+            REJECT_NEW_SESSION => Ok(SessionLimitPolicy::RejectNew),
+            EVICT_OLDEST_SESSION => Ok(SessionLimitPolicy::EvictOldest),
+            _ => Err(format!(
+                "unknown session limit policy {}, expected {} or {}",
+                policy, REJECT_NEW_SESSION, EVICT_OLDEST_SESSION
+            )),
+        }
+    }
+}
+
+// Timeouts given to sessions created from now on, both are in seconds. They are stored
+// with every session, so changing them does not shorten sessions that already exist.
+// `session_limits` follows the order of `SUBSCRIPTION_LEVELS`, 0 means unlimited.
+#[derive(Clone)]
+pub struct SessionPolicy {
+    pub idle_timeout: i64,
+    pub absolute_lifetime: i64,
+    pub session_limits: [usize; 4],
+    pub limit_policy: SessionLimitPolicy,
+}
+
+impl SessionPolicy {
+    pub fn from_environment(env_constants: &EnvironmentConstants) -> SessionPolicy {
+        let limit_policy = env_constants
+            .session_limit_policy
+            .parse()
+            .unwrap_or_else(|e| {
+                log_warn(&format!("{}, new logins over the limit are rejected", e));
+                SessionLimitPolicy::RejectNew
+            });
+        SessionPolicy {
+            idle_timeout: env_constants.session_idle_timeout,
+            absolute_lifetime: env_constants.session_absolute_lifetime,
+            session_limits: [
+                env_constants.session_limit_non_premium,
+                env_constants.session_limit_basic_premium,
+                env_constants.session_limit_normal_premium,
+                env_constants.session_limit_enterprise_premium,
+            ],
+            limit_policy,
+        }
+    }
+
+    pub fn session_limit(&self, subscription_level: &str) -> Option<usize> {
+        SUBSCRIPTION_LEVELS
+            .iter()
+            .position(|level| *level == subscription_level)
+            .map(|tier| self.session_limits[tier])
+            .filter(|limit| *limit > 0)
+    }
+}
+
+impl Default for SessionPolicy {
+    fn default() -> SessionPolicy {
+        SessionPolicy {
+            idle_timeout: DEFAULT_SESSION_IDLE_TIMEOUT,
+            absolute_lifetime: DEFAULT_SESSION_ABSOLUTE_LIFETIME,
+            session_limits: [0; 4],
+            limit_policy: SessionLimitPolicy::RejectNew,
+        }
+    }
+}
+
+// Runs once the new session is stored, so concurrent logins agree on which sessions are
+// the oldest ones. Rejecting drops the new session unless it fits within the limit,
+// evicting keeps only the newest sessions.
+pub async fn enforce_session_limit(
+    user_id: i64,
+    new_session_uuid: &str,
+    policy: &SessionPolicy,
+    storage: &dyn Storage,
+) -> Result<(), AuthError> {
+    if policy.session_limits.iter().all(|limit| *limit == 0) {
+        return Ok(());
+    }
+    let subscription_level = storage.get_user_subscription_level(user_id).await?;
+    let limit = match policy.session_limit(&subscription_level) {
+        Some(limit) => limit,
+        None => return Ok(()),
+    };
+
+    let now = Utc::now().timestamp();
+    let mut sessions: Vec<_> = storage
+        .get_user_sessions(user_id)
+        .await?
+        .into_iter()
+        .filter(|session| !session.is_expired(now))
+        .collect();
+    if sessions.len() <= limit {
+        return Ok(());
+    }
+    sessions.sort_by_key(|session| (session.created_at, session.session_id));
+
+    match policy.limit_policy {
+        SessionLimitPolicy::RejectNew => {
+            if sessions[..limit]
+                .iter()
+                .any(|session| session.session_uuid == new_session_uuid)
+            {
+                return Ok(());
+            }
+            storage.drop_session(new_session_uuid).await?;
+            Err(AuthError::SessionLimitReached)
+        }
+        SessionLimitPolicy::EvictOldest => {
+            let evicted = sessions.len() - limit;
+            for session in &sessions[..evicted] {
+                storage.drop_session(&session.session_uuid).await?;
+            }
+            log_info(&format!(
+                "Evicted {} sessions of user {} over the limit of {}",
+                evicted, user_id, limit
+            ));
+            Ok(())
+        }
+    }
+}
+
+// Issuing the tokens of a new session is compensated, not transactional: the session is
+// dropped again when anything after its creation fails. A crash in between, or a drop that
+// fails too, leaves a session without tokens that counts toward the session limit until the
+// sweeper removes it after its idle timeout. A failed drop is what the caller gets then,
+// the original error is only logged.
+pub async fn drop_session_on_failure<T, E>(
+    result: Result<T, E>,
+    session_uuid: &str,
+    storage: &dyn Storage,
+) -> Result<T, E>
+where
+    E: From<AuthError> + std::fmt::Display,
+{
+    let error = match result {
+        Ok(value) => return Ok(value),
+        Err(error) => error,
+    };
+    if let Err(drop_error) = storage.drop_session(session_uuid).await {
+        log_error(&format!(
+            "Issuing the tokens of a new session failed and the session stays behind.\nReason {}",
+            error
+        ));
+        return Err(drop_error.into());
+    }
+    Err(error)
+}
+
+// Tokens are only as valid as the session they were issued for, once it is dropped or
+// expired every token bound to it is rejected. Every successful check counts as a use of
+// the session.
+pub async fn ensure_session_is_active(
+    session_uuid: &str,
+    user_id: i64,
+    storage: &dyn Storage,
+) -> Result<StoredSession, AuthError> {
+    let mut session = match storage.get_session(session_uuid).await {
+        Err(AuthError::RecordNotFound) => Err(AuthError::SessionNotFound),
+        result => result,
+    }?;
+    if session.user_id != user_id {
+        return Err(AuthError::InvalidToken);
+    }
+
+    let now = Utc::now().timestamp();
+    if session.is_expired(now) {
+        storage.drop_session(session_uuid).await?;
+        return Err(AuthError::SessionExpired);
+    }
+    storage.touch_session(session_uuid, now).await?;
+    session.last_used_at = now;
+    Ok(session)
+}
+
+// `sub` holds the user id the token was issued to
+pub fn ensure_subject_matches(subject: &str, user_id: i64) -> Result<(), AuthError> {
+    if subject != user_id.to_string() {
+        return Err(AuthError::InvalidToken);
+    }
+    Ok(())
+}
+
+// Expired sessions are rejected when used anyway, the sweeper only keeps abandoned ones
+// and their refresh tokens from piling up
+pub async fn sweep_expired_sessions_periodically(storage: &dyn Storage, period: Duration) {
+    let mut interval = actix_rt::time::interval(period);
+    loop {
+        interval.tick().await;
+        match storage.purge_expired_sessions(Utc::now().timestamp()).await {
+            Ok(0) => {}
+            Ok(purged) => log_info(&format!("Purged {} expired sessions", purged)),
+            Err(e) => log_error(&format!("Session sweep failed.\nReason {}", e)),
+        }
+    }
+}
@@ -1,70 +1,231 @@
-use chrono::{DateTime, NaiveDateTime, Utc};
-use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
+use chrono::{DateTime, Utc};
+use jsonwebtoken::errors::ErrorKind;
+use jsonwebtoken::{
+    decode, decode_header, encode, Algorithm, DecodingKey, EncodingKey, Header, Validation,
+};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
+use uuid::Uuid;
 
-use crate::auth::token::constants::{ACCESS_TOKEN_EXPIRATION, ALGORITHM};
-use crate::auth::token::errors::ExpiredAccessTokenError;
+use crate::auth::session::{ensure_session_is_active, ensure_subject_matches};
+use crate::auth::token::constants::{
+    ACCESS_TOKEN_AUDIENCE, ACCESS_TOKEN_EXPIRATION, ACCESS_TOKEN_TYPE, ALGORITHM,
+};
+use crate::auth::token::keyring::Keyring;
+use crate::auth::token::revocation::RevocationList;
+use crate::auth::token::signing_key::TokenSigner;
 use crate::auth::user::UserInfo;
+use crate::errors::auth_error::AuthError;
 use crate::logging::log::log_info;
+use crate::storage::{Storage, StoredSession};
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Claims {
+    pub sub: String,
+    pub aud: String,
+    pub session: String,
     pub username: String,
     pub exp: i64,
+    pub iat: i64,
+    pub jti: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
 }
 
+impl Claims {
+    pub fn user_id(&self) -> Result<i64, AuthError> {
+        self.sub.parse().map_err(|_| AuthError::InvalidToken)
+    }
+}
+
+// Leaves out the session uuid, it is as good as the session cookie
 impl std::fmt::Display for Claims {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        let naive_datetime = NaiveDateTime::from_timestamp_opt(self.exp, 0);
-        let exp_date = DateTime::<Utc>::from_naive_utc_and_offset(naive_datetime.unwrap(), Utc);
-        write!(
-            f,
-            "Claims {{ username: {}, exp: {} }}",
-            self.username,
-            exp_date.format("%H:%M:%S %d-%m-%Y")
-        )
+        match DateTime::<Utc>::from_timestamp(self.exp, 0) {
+            Some(exp_date) => write!(
+                f,
+                "Claims {{ sub: {}, username: {}, exp: {} }}",
+                self.sub,
+                self.username,
+                exp_date.format("%H:%M:%S %d-%m-%Y")
+            ),
+            None => write!(
+                f,
+                "Claims {{ sub: {}, username: {}, exp: {} }}",
+                self.sub, self.username, self.exp
+            ),
+        }
     }
 }
 
-pub async fn create_access_token(user: &UserInfo) -> Result<String, Box<dyn std::error::Error>> {
+// The scope follows the session, so refreshing never widens what an OAuth client was granted
+pub async fn create_access_token(
+    user: &UserInfo,
+    session_uuid: &str,
+    storage: &dyn Storage,
+    signer: &TokenSigner,
+) -> Result<String, AuthError> {
+    let scope = match storage.get_session(session_uuid).await {
+        Err(AuthError::RecordNotFound) => Err(AuthError::SessionNotFound),
+        result => result,
+    }?
+    .scope;
+    let now = Utc::now().timestamp();
     let claims = Claims {
+        sub: user.user_id.to_string(),
+        aud: ACCESS_TOKEN_AUDIENCE.to_string(),
+        session: session_uuid.to_string(),
         username: user.username.clone(),
-        exp: (Utc::now().timestamp() + ACCESS_TOKEN_EXPIRATION),
+        exp: (now + ACCESS_TOKEN_EXPIRATION),
+        iat: now,
+        jti: Uuid::new_v4().to_string(),
+        scope,
     };
 
-    let pool = crate::db::create_pool().await?;
-
-    let secret_key = crate::db::get_secret_access_key(user.user_id, &pool).await?;
-    log_info(&format!("secret key : {}", &secret_key));
-    let token = encode(
-        &Header::new(ALGORITHM),
-        &claims,
-        &EncodingKey::from_secret(secret_key.as_ref()),
-    )
-    .unwrap();
+    let token = match signer {
+        TokenSigner::PerUserSecret => {
+            // every registered user has a key, a missing one is a server side inconsistency
+            let secret_key = match storage.get_secret_access_key(user.user_id).await {
+                Err(AuthError::RecordNotFound) => Err(AuthError::Internal(format!(
+                    "no secret access key stored for user {}",
+                    user.user_id
+                ))),
+                result => result,
+            }?;
+            encode(
+                &token_header(Header::new(ALGORITHM), ACCESS_TOKEN_TYPE),
+                &claims,
+                &EncodingKey::from_secret(secret_key.as_ref()),
+            )?
+        }
+        TokenSigner::Keyring(keyring) => {
+            let (header, encoding_key) = keyring.signing_key()?;
+            encode(
+                &token_header(header, ACCESS_TOKEN_TYPE),
+                &claims,
+                &encoding_key,
+            )?
+        }
+    };
     Ok(token)
 }
 
-pub async fn validate_token(
+pub(crate) fn token_header(mut header: Header, token_type: &str) -> Header {
+    header.typ = Some(token_type.to_string());
+    header
+}
+
+// Reads the header and checks `typ`, a token signed by the same key is never accepted in
+// place of another kind, e.g. an ID token as an access token
+pub(crate) fn decode_typed_header(token: &str, token_type: &str) -> Result<Header, AuthError> {
+    let header = decode_header(token).map_err(|_| AuthError::InvalidToken)?;
+    if header.typ.as_deref() != Some(token_type) {
+        return Err(AuthError::InvalidToken);
+    }
+    Ok(header)
+}
+
+fn access_token_validation(algorithm: Algorithm) -> Validation {
+    let mut validation = Validation::new(algorithm);
+    validation.set_required_spec_claims(&["exp", "aud"]);
+    validation.set_audience(&[ACCESS_TOKEN_AUDIENCE]);
+    validation
+}
+
+async fn decode_with_user_secret(
     token: &str,
     user_id: i64,
-) -> Result<Claims, Box<dyn std::error::Error>> {
-    let pool = crate::db::create_pool().await?;
-    let secret_key = crate::db::get_secret_access_key(user_id, &pool).await?;
-    log_info(&format!(
-        "obtaining secret_key from database: {}",
-        &secret_key
-    ));
+    storage: &dyn Storage,
+) -> Result<Claims, AuthError> {
+    decode_typed_header(token, ACCESS_TOKEN_TYPE)?;
+    let secret_key = storage.get_secret_access_key(user_id).await?;
     let claims = decode::<Claims>(
         token,
         &DecodingKey::from_secret(secret_key.as_bytes()),
-        &Validation::new(ALGORITHM),
-    )?;
+        &access_token_validation(ALGORITHM),
+    )
+    .map_err(to_validation_error)?;
+    Ok(claims.claims)
+}
 
-    log_info(&format!("obtained claims:\t{}", claims.claims));
+// Shared with client tokens, the key named by `kid` decides which algorithm is accepted
+pub(crate) fn decode_with_keyring<T: DeserializeOwned>(
+    token: &str,
+    token_type: &str,
+    keyring: &Keyring,
+) -> Result<T, AuthError> {
+    let header = decode_typed_header(token, token_type)?;
+    let (algorithm, decoding_key) = header
+        .kid
+        .and_then(|key_id| keyring.decoding_key(&key_id))
+        .ok_or(AuthError::InvalidToken)?;
+    let claims = decode::<T>(token, &decoding_key, &access_token_validation(algorithm))
+        .map_err(to_validation_error)?;
+    Ok(claims.claims)
+}
+
+#[derive(Deserialize)]
+struct Subject {
+    sub: String,
+}
+
+// Access and refresh tokens both carry the user id in `sub`. It only tells whose keys verify
+// the token, nothing read here is trusted before that.
+pub fn unverified_user_id(token: &str) -> Option<i64> {
+    let mut validation = Validation::default();
+    validation.insecure_disable_signature_validation();
+    validation.validate_exp = false;
+    validation.required_spec_claims.clear();
+    decode::<Subject>(token, &DecodingKey::from_secret(&[]), &validation)
+        .ok()
+        .and_then(|subject| subject.claims.sub.parse().ok())
+}
+
+fn to_validation_error(error: jsonwebtoken::errors::Error) -> AuthError {
+    match error.kind() {
+        ErrorKind::ExpiredSignature => AuthError::ExpiredAccessToken,
+        _ => AuthError::InvalidToken,
+    }
+}
+
+// Checks the signature and the subject only, the token may still be revoked or belong to
+// a dropped session
+pub async fn decode_access_token(
+    token: &str,
+    user_id: i64,
+    storage: &dyn Storage,
+    signer: &TokenSigner,
+) -> Result<Claims, AuthError> {
+    let claims = match signer {
+        TokenSigner::PerUserSecret => decode_with_user_secret(token, user_id, storage).await?,
+        TokenSigner::Keyring(keyring) => decode_with_keyring(token, ACCESS_TOKEN_TYPE, keyring)?,
+    };
+    // the keyring is shared by every user, so the subject has to be checked as well
+    ensure_subject_matches(&claims.sub, user_id)?;
+    Ok(claims)
+}
+
+// Answers the session as well, it tells a first-party login from an OAuth client grant
+pub async fn validate_token(
+    token: &str,
+    user_id: i64,
+    storage: &dyn Storage,
+    signer: &TokenSigner,
+    revocations: &RevocationList,
+) -> Result<(Claims, StoredSession), AuthError> {
+    let claims = decode_access_token(token, user_id, storage, signer).await?;
+    if revocations
+        .is_revoked(&claims.jti, claims.exp, storage)
+        .await?
+    {
+        return Err(AuthError::TokenRevoked);
+    }
+    let session = ensure_session_is_active(&claims.session, user_id, storage).await?;
+
+    log_info(&format!("obtained claims:\t{}", claims));
     let current_time = Utc::now().timestamp();
-    if current_time - claims.claims.exp > 0 {
-        return Err(Box::new(ExpiredAccessTokenError));
+    if current_time - claims.exp > 0 {
+        return Err(AuthError::ExpiredAccessToken);
     }
-    Ok(claims.claims)
+    Ok((claims, session))
 }
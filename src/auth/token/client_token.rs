@@ -0,0 +1,92 @@
+use chrono::Utc;
+use jsonwebtoken::encode;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::auth::token::access_token::{decode_with_keyring, token_header};
+use crate::auth::token::constants::{ACCESS_TOKEN_AUDIENCE, CLIENT_TOKEN_TYPE};
+use crate::auth::token::revocation::RevocationList;
+use crate::auth::token::signing_key::TokenSigner;
+use crate::errors::auth_error::AuthError;
+use crate::storage::{Storage, StoredClient};
+
+// Access token a machine client obtained for itself. `sub` is the client id instead of a
+// user id and there is no username or session behind it, so it never authenticates a user.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClientClaims {
+    pub sub: String,
+    pub aud: String,
+    pub client_id: String,
+    pub exp: i64,
+    pub iat: i64,
+    pub jti: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+}
+
+// There is no user whose secret could sign the token, resource servers verify it with the
+// published keys instead
+pub fn create_client_token(
+    client: &StoredClient,
+    scope: Option<String>,
+    lifetime: i64,
+    signer: &TokenSigner,
+) -> Result<String, AuthError> {
+    let keyring = match signer {
+        TokenSigner::Keyring(keyring) => keyring,
+        TokenSigner::PerUserSecret => {
+            return Err(AuthError::Internal(
+                "client tokens need an asymmetric signing algorithm".to_string(),
+            ))
+        }
+    };
+    let now = Utc::now().timestamp();
+    let claims = ClientClaims {
+        sub: client.client_id.clone(),
+        aud: ACCESS_TOKEN_AUDIENCE.to_string(),
+        client_id: client.client_id.clone(),
+        exp: now + lifetime,
+        iat: now,
+        jti: Uuid::new_v4().to_string(),
+        scope,
+    };
+    let (header, encoding_key) = keyring.signing_key()?;
+    Ok(encode(
+        &token_header(header, CLIENT_TOKEN_TYPE),
+        &claims,
+        &encoding_key,
+    )?)
+}
+
+// Checks the signature only, the token may still be revoked or belong to a deleted client
+pub fn decode_client_token(token: &str, signer: &TokenSigner) -> Result<ClientClaims, AuthError> {
+    let claims: ClientClaims = match signer {
+        TokenSigner::Keyring(keyring) => decode_with_keyring(token, CLIENT_TOKEN_TYPE, keyring)?,
+        TokenSigner::PerUserSecret => return Err(AuthError::InvalidToken),
+    };
+    if claims.sub != claims.client_id {
+        return Err(AuthError::InvalidToken);
+    }
+    Ok(claims)
+}
+
+// Deleting a client takes its tokens with it, like dropping the session of a user
+pub async fn validate_client_token(
+    token: &str,
+    storage: &dyn Storage,
+    signer: &TokenSigner,
+    revocations: &RevocationList,
+) -> Result<ClientClaims, AuthError> {
+    let claims = decode_client_token(token, signer)?;
+    if revocations
+        .is_revoked(&claims.jti, claims.exp, storage)
+        .await?
+    {
+        return Err(AuthError::TokenRevoked);
+    }
+    match storage.get_client(&claims.client_id).await {
+        Err(AuthError::RecordNotFound) => Err(AuthError::InvalidToken),
+        result => result,
+    }?;
+    Ok(claims)
+}
@@ -1,6 +1,12 @@
 use jsonwebtoken::Algorithm;
 
+pub const ACCESS_TOKEN_AUDIENCE: &str = "auth_server";
 pub const ACCESS_TOKEN_EXPIRATION: i64 = 60 * 60;
+// `typ` of each kind of token, so none of them is accepted in place of another
+pub const ACCESS_TOKEN_TYPE: &str = "at+jwt";
+pub const CLIENT_TOKEN_TYPE: &str = "client-at+jwt";
+pub const ID_TOKEN_TYPE: &str = "JWT";
+pub const REFRESH_TOKEN_TYPE: &str = "refresh+jwt";
 pub const ALGORITHM: Algorithm = Algorithm::HS256;
 pub const REFRESH_ALGORITHM: Algorithm = Algorithm::HS512;
 pub const REFRESH_KEY_LENGTH: usize = 64;
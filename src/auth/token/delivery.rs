@@ -0,0 +1,112 @@
+use actix_web::http::header;
+use actix_web::HttpResponse;
+use serde::{Deserialize, Serialize};
+
+use crate::auth::cookies::headers::{
+    get_access_token_cookie_header, get_new_session_uuid_cookie_header,
+    get_refresh_token_cookie_header,
+};
+use crate::auth::token::constants::ACCESS_TOKEN_EXPIRATION;
+use crate::errors::auth_error::AuthError;
+
+// Browsers keep the tokens in `SameSite=Strict` cookies, mobile apps and CLIs can not and
+// ask for them in the response body instead, then send the access token as a bearer token
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TokenDelivery {
+    #[default]
+    Cookie,
+    Body,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TokenResponse {
+    pub access_token: String,
+    pub token_type: String,
+    pub expires_in: i64,
+    pub refresh_token: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id_token: Option<String>,
+}
+
+// Tokens in the response body must never be cached, RFC 6749 requires `no-store`
+pub fn bearer_token_response(
+    access_token: String,
+    refresh_token: String,
+    scope: Option<String>,
+    id_token: Option<String>,
+) -> HttpResponse {
+    HttpResponse::Ok()
+        .insert_header((header::CACHE_CONTROL, "no-store"))
+        .json(TokenResponse {
+            access_token,
+            token_type: "Bearer".to_string(),
+            expires_in: ACCESS_TOKEN_EXPIRATION,
+            refresh_token,
+            scope,
+            id_token,
+        })
+}
+
+// Client credentials tokens come without a refresh token, the client simply asks again
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ClientTokenResponse {
+    pub access_token: String,
+    pub token_type: String,
+    pub expires_in: i64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+}
+
+pub fn client_token_response(
+    access_token: String,
+    expires_in: i64,
+    scope: Option<String>,
+) -> HttpResponse {
+    HttpResponse::Ok()
+        .insert_header((header::CACHE_CONTROL, "no-store"))
+        .json(ClientTokenResponse {
+            access_token,
+            token_type: "Bearer".to_string(),
+            expires_in,
+            scope,
+        })
+}
+
+// The session cookie is only set when the session is established, the body never carries
+// it as the tokens already name their session
+pub async fn token_response(
+    delivery: TokenDelivery,
+    access_token: String,
+    refresh_token: String,
+    session_uuid: Option<&str>,
+) -> Result<HttpResponse, AuthError> {
+    match delivery {
+        TokenDelivery::Body => Ok(bearer_token_response(
+            access_token,
+            refresh_token,
+            None,
+            None,
+        )),
+        TokenDelivery::Cookie => {
+            let mut response = HttpResponse::Ok();
+            response.append_header((
+                header::SET_COOKIE,
+                get_access_token_cookie_header(access_token)?,
+            ));
+            response.append_header((
+                header::SET_COOKIE,
+                get_refresh_token_cookie_header(refresh_token)?,
+            ));
+            if let Some(session_uuid) = session_uuid {
+                response.append_header((
+                    header::SET_COOKIE,
+                    get_new_session_uuid_cookie_header(session_uuid).await?,
+                ));
+            }
+            Ok(response.finish())
+        }
+    }
+}
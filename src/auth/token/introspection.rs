@@ -0,0 +1,94 @@
+use actix_web::ResponseError;
+use serde::{Deserialize, Serialize};
+
+use crate::auth::token::access_token::{unverified_user_id, validate_token};
+use crate::auth::token::client_token::validate_client_token;
+use crate::auth::token::revocation::RevocationList;
+use crate::auth::token::signing_key::TokenSigner;
+use crate::errors::auth_error::AuthError;
+use crate::storage::Storage;
+
+// RFC 7662 response, everything but `active` is left out for inactive tokens
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Introspection {
+    pub active: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sub: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exp: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub iat: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub subscription_level: Option<String>,
+}
+
+impl Introspection {
+    fn inactive() -> Introspection {
+        Introspection::default()
+    }
+}
+
+// Tokens of machine clients name the client instead of a user
+async fn introspect_client_token(
+    token: &str,
+    storage: &dyn Storage,
+    signer: &TokenSigner,
+    revocations: &RevocationList,
+) -> Result<Introspection, AuthError> {
+    let claims = match validate_client_token(token, storage, signer, revocations).await {
+        Ok(claims) => claims,
+        Err(e) if e.status_code().is_server_error() => return Err(e),
+        Err(_) => return Ok(Introspection::inactive()),
+    };
+    Ok(Introspection {
+        active: true,
+        sub: Some(claims.sub),
+        client_id: Some(claims.client_id),
+        exp: Some(claims.exp),
+        iat: Some(claims.iat),
+        scope: claims.scope,
+        ..Introspection::default()
+    })
+}
+
+// A token is active when it would be accepted by the server itself, so the session checks
+// apply and the lookup counts as a use of the session. Refresh tokens are only ever
+// presented to this server and are reported inactive.
+pub async fn introspect_token(
+    token: &str,
+    storage: &dyn Storage,
+    signer: &TokenSigner,
+    revocations: &RevocationList,
+) -> Result<Introspection, AuthError> {
+    let user_id = match unverified_user_id(token) {
+        Some(user_id) => user_id,
+        None => return introspect_client_token(token, storage, signer, revocations).await,
+    };
+    let (claims, session) = match validate_token(token, user_id, storage, signer, revocations).await
+    {
+        Ok(validated) => validated,
+        Err(e) if e.status_code().is_server_error() => return Err(e),
+        Err(_) => return Ok(Introspection::inactive()),
+    };
+
+    let subscription_level = storage.get_user_subscription_level(user_id).await?;
+    Ok(Introspection {
+        active: true,
+        sub: Some(claims.sub),
+        username: Some(claims.username),
+        client_id: session.client_id,
+        exp: Some(claims.exp),
+        iat: Some(claims.iat),
+        scope: claims.scope,
+        session_id: Some(session.session_id),
+        subscription_level: Some(subscription_level),
+    })
+}
@@ -0,0 +1,121 @@
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use openssl::rand::rand_bytes;
+use openssl::symm::{decrypt_aead, encrypt_aead, Cipher};
+
+use crate::errors::auth_error::AuthError;
+use crate::logging::log::log_warn;
+use crate::startup::environment_constants::EnvironmentConstants;
+
+// Marks private keys sealed with AES-256-GCM, anything else in the column is a plain PEM
+pub const ENCRYPTED_KEY_PREFIX: &str = "aes-256-gcm:";
+
+const ENCRYPTION_KEY_LENGTH: usize = 32;
+const NONCE_LENGTH: usize = 12;
+const TAG_LENGTH: usize = 16;
+
+// How the private keys of the keyring are kept in the storage. Plaintext has to be chosen
+// explicitly, a database dump must not be enough to sign tokens.
+#[derive(Clone)]
+pub enum KeyEncryption {
+    Plaintext,
+    Aes256Gcm([u8; ENCRYPTION_KEY_LENGTH]),
+}
+
+impl KeyEncryption {
+    pub fn from_environment(constants: &EnvironmentConstants) -> Result<KeyEncryption, AuthError> {
+        if !constants.signing_key_encryption_key.is_empty() {
+            return KeyEncryption::from_base64(&constants.signing_key_encryption_key);
+        }
+        if constants.signing_keys_unencrypted {
+            log_warn("signing keys are stored unencrypted");
+            return Ok(KeyEncryption::Plaintext);
+        }
+        Err(AuthError::Internal(
+            "set SIGNING_KEY_ENCRYPTION_KEY, or SIGNING_KEYS_UNENCRYPTED=true to store signing \
+             keys in plaintext"
+                .to_string(),
+        ))
+    }
+
+    // `encoded_key` is the base64 of 32 random bytes, e.g. `openssl rand -base64 32`
+    pub fn from_base64(encoded_key: &str) -> Result<KeyEncryption, AuthError> {
+        let key = STANDARD
+            .decode(encoded_key.trim())
+            .ok()
+            .and_then(|key| <[u8; ENCRYPTION_KEY_LENGTH]>::try_from(key).ok())
+            .ok_or_else(|| {
+                AuthError::Internal(
+                    "the signing key encryption key must be 32 bytes encoded in base64".to_string(),
+                )
+            })?;
+        Ok(KeyEncryption::Aes256Gcm(key))
+    }
+
+    // The key id is authenticated along, so a sealed key can not be passed off as another one
+    pub fn seal(&self, key_id: &str, private_key_pem: &str) -> Result<String, AuthError> {
+        let key = match self {
+            KeyEncryption::Plaintext => return Ok(private_key_pem.to_string()),
+            KeyEncryption::Aes256Gcm(key) => key,
+        };
+        let mut nonce = [0u8; NONCE_LENGTH];
+        rand_bytes(&mut nonce).map_err(|e| AuthError::Internal(e.to_string()))?;
+        let mut tag = [0u8; TAG_LENGTH];
+        let ciphertext = encrypt_aead(
+            Cipher::aes_256_gcm(),
+            key,
+            Some(&nonce),
+            key_id.as_bytes(),
+            private_key_pem.as_bytes(),
+            &mut tag,
+        )
+        .map_err(|e| AuthError::Internal(e.to_string()))?;
+        let sealed = [&nonce[..], &ciphertext, &tag].concat();
+        Ok(format!(
+            "{}{}",
+            ENCRYPTED_KEY_PREFIX,
+            STANDARD.encode(sealed)
+        ))
+    }
+
+    // Keys stored before the encryption was configured are still read as plain PEM
+    pub fn open(&self, key_id: &str, stored_private_key: &str) -> Result<String, AuthError> {
+        let sealed = match stored_private_key.strip_prefix(ENCRYPTED_KEY_PREFIX) {
+            Some(sealed) => sealed,
+            None => return Ok(stored_private_key.to_string()),
+        };
+        let key = match self {
+            KeyEncryption::Plaintext => {
+                return Err(AuthError::Internal(
+                    "the signing key is encrypted, but SIGNING_KEY_ENCRYPTION_KEY is not set"
+                        .to_string(),
+                ))
+            }
+            KeyEncryption::Aes256Gcm(key) => key,
+        };
+        let sealed = STANDARD
+            .decode(sealed)
+            .map_err(|e| AuthError::Internal(e.to_string()))?;
+        if sealed.len() < NONCE_LENGTH + TAG_LENGTH {
+            return Err(AuthError::Internal(
+                "the encrypted signing key is truncated".to_string(),
+            ));
+        }
+        let (nonce, rest) = sealed.split_at(NONCE_LENGTH);
+        let (ciphertext, tag) = rest.split_at(rest.len() - TAG_LENGTH);
+        let private_key_pem = decrypt_aead(
+            Cipher::aes_256_gcm(),
+            key,
+            Some(nonce),
+            key_id.as_bytes(),
+            ciphertext,
+            tag,
+        )
+        .map_err(|_| {
+            AuthError::Internal(
+                "the signing key can not be decrypted with SIGNING_KEY_ENCRYPTION_KEY".to_string(),
+            )
+        })?;
+        String::from_utf8(private_key_pem).map_err(|e| AuthError::Internal(e.to_string()))
+    }
+}
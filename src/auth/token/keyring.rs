@@ -0,0 +1,284 @@
+use std::sync::{RwLock, RwLockReadGuard};
+use std::time::Duration;
+
+use chrono::Utc;
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey, Header};
+
+use crate::auth::token::key_encryption::KeyEncryption;
+use crate::auth::token::signing_key::{generate_private_key_pem, SigningKey};
+use crate::errors::auth_error::AuthError;
+use crate::logging::log::log_error;
+use crate::storage::{Storage, StoredSigningKey};
+
+pub const DEFAULT_REFRESH_INTERVAL: u64 = 60;
+
+pub struct KeyringEntry {
+    pub signing_key: SigningKey,
+    pub created_at: i64,
+    pub activates_at: Option<i64>,
+    pub retires_at: Option<i64>,
+}
+
+impl KeyringEntry {
+    pub fn is_retired(&self, now: i64) -> bool {
+        matches!(self.retires_at, Some(retires_at) if retires_at <= now)
+    }
+
+    pub fn is_active(&self, now: i64) -> bool {
+        !self.is_retired(now)
+            && matches!(self.activates_at, Some(activates_at) if activates_at <= now)
+    }
+}
+
+// Every signing key known to the server. New tokens are signed with the newest active key,
+// while any key that is not retired yet still verifies, so a rotation logs nobody out.
+pub struct Keyring {
+    entries: RwLock<Vec<KeyringEntry>>,
+    encryption: KeyEncryption,
+}
+
+impl Keyring {
+    pub async fn load(
+        storage: &dyn Storage,
+        encryption: KeyEncryption,
+    ) -> Result<Keyring, AuthError> {
+        let keyring = Keyring {
+            entries: RwLock::default(),
+            encryption,
+        };
+        keyring.reload(storage).await?;
+        Ok(keyring)
+    }
+
+    // Picks up keys generated, promoted or retired by the admin commands. A key that can not
+    // be opened fails the whole reload, usually SIGNING_KEY_ENCRYPTION_KEY is wrong and
+    // carrying on without the key would log out everyone signed by it.
+    pub async fn reload(&self, storage: &dyn Storage) -> Result<(), AuthError> {
+        let entries = storage
+            .get_signing_keys()
+            .await?
+            .iter()
+            .map(|stored_key| {
+                to_keyring_entry(stored_key, &self.encryption).map_err(|e| {
+                    AuthError::Internal(format!(
+                        "signing key {} can not be loaded: {}",
+                        stored_key.key_id, e
+                    ))
+                })
+            })
+            .collect::<Result<Vec<KeyringEntry>, AuthError>>()?;
+        *self
+            .entries
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = entries;
+        Ok(())
+    }
+
+    pub async fn refresh_periodically(&self, storage: &dyn Storage, period: Duration) {
+        let mut interval = actix_rt::time::interval(period);
+        // the first tick completes immediately and the keyring was just loaded
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            if let Err(e) = self.reload(storage).await {
+                log_error(&format!("Keyring refresh failed.\nReason {}", e));
+            }
+        }
+    }
+
+    fn entries(&self) -> RwLockReadGuard<'_, Vec<KeyringEntry>> {
+        self.entries
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    pub fn has_active_key(&self) -> bool {
+        let now = Utc::now().timestamp();
+        self.entries().iter().any(|entry| entry.is_active(now))
+    }
+
+    pub fn signing_key(&self) -> Result<(Header, EncodingKey), AuthError> {
+        let now = Utc::now().timestamp();
+        self.entries()
+            .iter()
+            .filter(|entry| entry.is_active(now))
+            .max_by_key(|entry| (entry.activates_at, entry.created_at))
+            .map(|entry| {
+                (
+                    entry.signing_key.header(),
+                    entry.signing_key.encoding_key().clone(),
+                )
+            })
+            .ok_or_else(|| AuthError::Internal("no active signing key in the keyring".to_string()))
+    }
+
+    pub fn decoding_key(&self, key_id: &str) -> Option<(Algorithm, DecodingKey)> {
+        let now = Utc::now().timestamp();
+        self.entries()
+            .iter()
+            .find(|entry| entry.signing_key.key_id == key_id && !entry.is_retired(now))
+            .map(|entry| {
+                (
+                    entry.signing_key.algorithm,
+                    entry.signing_key.decoding_key().clone(),
+                )
+            })
+    }
+
+    // Algorithms of the keys that are not retired, the ones verifiers have to support
+    pub fn algorithms(&self) -> Vec<Algorithm> {
+        let now = Utc::now().timestamp();
+        let mut algorithms: Vec<Algorithm> = Vec::new();
+        for entry in self.entries().iter().filter(|entry| !entry.is_retired(now)) {
+            if !algorithms.contains(&entry.signing_key.algorithm) {
+                algorithms.push(entry.signing_key.algorithm);
+            }
+        }
+        algorithms
+    }
+
+    // Pending keys are published as well, so verifiers already know them when they are promoted
+    pub fn jwk_set(&self) -> JwkSet {
+        let now = Utc::now().timestamp();
+        JwkSet {
+            keys: self
+                .entries()
+                .iter()
+                .filter(|entry| !entry.is_retired(now))
+                .map(|entry| entry.signing_key.jwk().clone())
+                .collect(),
+        }
+    }
+}
+
+fn to_keyring_entry(
+    stored_key: &StoredSigningKey,
+    encryption: &KeyEncryption,
+) -> Result<KeyringEntry, Box<dyn std::error::Error>> {
+    let private_key_pem = encryption.open(&stored_key.key_id, &stored_key.private_key)?;
+    let signing_key = SigningKey::from_pem(
+        stored_key.algorithm.parse()?,
+        private_key_pem.as_bytes(),
+        Some(stored_key.key_id.clone()),
+    )?;
+    Ok(KeyringEntry {
+        signing_key,
+        created_at: stored_key.created_at,
+        activates_at: stored_key.activates_at,
+        retires_at: stored_key.retires_at,
+    })
+}
+
+async fn store_new_signing_key(
+    storage: &dyn Storage,
+    encryption: &KeyEncryption,
+    algorithm: Algorithm,
+    private_key_pem: &str,
+    key_id: Option<String>,
+    activate: bool,
+) -> Result<StoredSigningKey, Box<dyn std::error::Error>> {
+    let signing_key = SigningKey::from_pem(algorithm, private_key_pem.as_bytes(), key_id)?;
+    let now = Utc::now().timestamp();
+    let stored_key = StoredSigningKey {
+        key_id: signing_key.key_id.clone(),
+        algorithm: format!("{:?}", algorithm),
+        private_key: encryption.seal(&signing_key.key_id, private_key_pem)?,
+        created_at: now,
+        activates_at: Some(now).filter(|_| activate),
+        retires_at: None,
+    };
+    storage.store_signing_key(&stored_key).await?;
+    Ok(stored_key)
+}
+
+// Generated keys stay pending unless activated right away, promote them once every
+// replica and verifier had the time to fetch the new JWKS
+pub async fn generate_signing_key(
+    storage: &dyn Storage,
+    encryption: &KeyEncryption,
+    algorithm: Algorithm,
+    activate: bool,
+) -> Result<StoredSigningKey, Box<dyn std::error::Error>> {
+    let private_key_pem = generate_private_key_pem(algorithm)?;
+    store_new_signing_key(
+        storage,
+        encryption,
+        algorithm,
+        &private_key_pem,
+        None,
+        activate,
+    )
+    .await
+}
+
+// Adds an existing key to the keyring, a key that is already stored is left untouched
+pub async fn import_signing_key(
+    storage: &dyn Storage,
+    encryption: &KeyEncryption,
+    algorithm: Algorithm,
+    private_key_pem: &str,
+    key_id: Option<String>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let key_id = SigningKey::from_pem(algorithm, private_key_pem.as_bytes(), key_id)?.key_id;
+    let stored_keys = storage.get_signing_keys().await?;
+    if stored_keys.iter().any(|stored| stored.key_id == key_id) {
+        return Ok(());
+    }
+    store_new_signing_key(
+        storage,
+        encryption,
+        algorithm,
+        private_key_pem,
+        Some(key_id),
+        true,
+    )
+    .await?;
+    Ok(())
+}
+
+// Makes the key the newest active one, so it signs every token issued from now on
+pub async fn promote_signing_key(
+    storage: &dyn Storage,
+    key_id: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let now = Utc::now().timestamp();
+    let stored_keys = storage.get_signing_keys().await?;
+    match stored_keys.iter().find(|stored| stored.key_id == key_id) {
+        Some(stored) if matches!(stored.retires_at, Some(retires_at) if retires_at <= now) => {
+            Err(format!("signing key {} is retired", key_id).into())
+        }
+        Some(_) => Ok(storage.set_signing_key_activation(key_id, now).await?),
+        None => Err(format!("signing key {} does not exist", key_id).into()),
+    }
+}
+
+// Tokens signed with a retired key are rejected, retire a key only after the tokens it
+// signed have expired
+pub async fn retire_signing_key(
+    storage: &dyn Storage,
+    key_id: &str,
+    retires_at: i64,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let now = Utc::now().timestamp();
+    let stored_keys = storage.get_signing_keys().await?;
+    if !stored_keys.iter().any(|stored| stored.key_id == key_id) {
+        return Err(format!("signing key {} does not exist", key_id).into());
+    }
+
+    let other_active_key = stored_keys.iter().any(|stored| {
+        stored.key_id != key_id
+            && matches!(stored.activates_at, Some(activates_at) if activates_at <= now)
+            && !matches!(stored.retires_at, Some(other_retires_at) if other_retires_at <= retires_at)
+    });
+    if !other_active_key {
+        return Err(format!(
+            "signing key {} is the last active key, promote another one first",
+            key_id
+        )
+        .into());
+    }
+    Ok(storage
+        .set_signing_key_retirement(key_id, retires_at)
+        .await?)
+}
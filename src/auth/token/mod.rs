@@ -1,4 +1,10 @@
 pub mod access_token;
+pub mod client_token;
 pub mod constants;
-pub mod errors;
+pub mod delivery;
+pub mod introspection;
+pub mod key_encryption;
+pub mod keyring;
 pub mod refresh_token;
+pub mod revocation;
+pub mod signing_key;
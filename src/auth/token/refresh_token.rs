@@ -1,50 +1,158 @@
 use chrono::Utc;
+use jsonwebtoken::errors::ErrorKind;
 use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
 use serde::{Deserialize, Serialize};
+use uuid::Uuid;
 
-use crate::auth::token::constants::{REFRESH_ALGORITHM, REFRESH_TOKEN_EXPIRATION};
+use crate::auth::session::{ensure_session_is_active, ensure_subject_matches};
+use crate::auth::token::access_token::{decode_typed_header, token_header};
+use crate::auth::token::constants::{
+    REFRESH_ALGORITHM, REFRESH_TOKEN_EXPIRATION, REFRESH_TOKEN_TYPE,
+};
 use crate::auth::user::UserInfo;
+use crate::errors::auth_error::AuthError;
+use crate::logging::log::log_warn;
+use crate::storage::{Storage, StoredRefreshToken, StoredSession};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct RefreshClaims {
     pub session: String,
     pub sub: String,
     pub exp: i64,
+    pub jti: String,
 }
 
-pub async fn create_refresh_token(user: &UserInfo) -> Result<String, Box<dyn std::error::Error>> {
-    let pool = crate::db::create_pool().await?;
-    let secret_refresh_key = crate::db::get_secret_refresh_key(user.user_id, &pool).await?;
-    let session_uuid = crate::db::get_session_uuid(user.user_id, &pool).await?;
+async fn issue_refresh_token(
+    user: &UserInfo,
+    session_uuid: String,
+    family_id: String,
+    storage: &dyn Storage,
+) -> Result<String, AuthError> {
+    let secret_refresh_key = match storage.get_secret_refresh_key(user.user_id).await {
+        Err(AuthError::RecordNotFound) => Err(AuthError::Internal(format!(
+            "no secret refresh key stored for user {}",
+            user.user_id
+        ))),
+        result => result,
+    }?;
 
     let refresh_claims = RefreshClaims {
         session: session_uuid,
-        sub: user.username.clone(),
+        sub: user.user_id.to_string(),
         exp: (Utc::now().timestamp() + REFRESH_TOKEN_EXPIRATION),
+        jti: Uuid::new_v4().to_string(),
     };
 
     let refresh_token = encode(
-        &Header::new(REFRESH_ALGORITHM),
+        &token_header(Header::new(REFRESH_ALGORITHM), REFRESH_TOKEN_TYPE),
         &refresh_claims,
         &EncodingKey::from_secret(secret_refresh_key.as_ref()),
     )?;
+    storage
+        .store_refresh_token(&StoredRefreshToken {
+            token_id: refresh_claims.jti,
+            family_id,
+            session_uuid: refresh_claims.session,
+            user_id: user.user_id,
+            expires_at: refresh_claims.exp,
+            used_at: None,
+            revoked_at: None,
+        })
+        .await?;
     Ok(refresh_token)
 }
 
-pub async fn validate_refresh_token(
+// Starts a new token family for the session being established, every device the user
+// logs in from gets its own session and its own refresh chain
+pub async fn create_refresh_token(
+    user: &UserInfo,
+    session_uuid: &str,
+    storage: &dyn Storage,
+) -> Result<String, AuthError> {
+    issue_refresh_token(
+        user,
+        session_uuid.to_string(),
+        Uuid::new_v4().to_string(),
+        storage,
+    )
+    .await
+}
+
+// Checks the signature and the subject only, the token may still be used up or belong to
+// a dropped session
+pub async fn decode_refresh_token(
     token: &str,
     user_id: i64,
-    pool: &sqlx::Pool<sqlx::Postgres>,
-) -> Result<bool, Box<dyn std::error::Error>> {
-    let user = crate::db::get_user_from_db_with_user_id(user_id, pool).await?;
-    let secret_key = crate::db::get_secret_refresh_key(user.user_id, pool).await?;
+    storage: &dyn Storage,
+) -> Result<RefreshClaims, AuthError> {
+    let user = storage.get_user_with_user_id(user_id).await?;
+    let secret_key = storage.get_secret_refresh_key(user.user_id).await?;
 
+    decode_typed_header(token, REFRESH_TOKEN_TYPE)?;
     let refresh_claims = decode::<RefreshClaims>(
         token,
         &DecodingKey::from_secret(secret_key.as_bytes()),
         &Validation::new(REFRESH_ALGORITHM),
-    )?;
+    )
+    .map_err(|e| match e.kind() {
+        ErrorKind::ExpiredSignature => AuthError::ExpiredRefreshToken,
+        _ => AuthError::InvalidToken,
+    })?;
 
     let current_timestamp = Utc::now().timestamp();
-    Ok(refresh_claims.claims.exp - current_timestamp > 0)
+    if refresh_claims.claims.exp - current_timestamp <= 0 {
+        return Err(AuthError::ExpiredRefreshToken);
+    }
+    ensure_subject_matches(&refresh_claims.claims.sub, user.user_id)?;
+    Ok(refresh_claims.claims)
+}
+
+pub async fn validate_refresh_token(
+    token: &str,
+    user_id: i64,
+    storage: &dyn Storage,
+) -> Result<(RefreshClaims, StoredSession), AuthError> {
+    let refresh_claims = decode_refresh_token(token, user_id, storage).await?;
+    let session = ensure_session_is_active(&refresh_claims.session, user_id, storage).await?;
+    Ok((refresh_claims, session))
+}
+
+// Every refresh token can be used once. Presenting it again means it leaked, so the whole
+// family and the session it belongs to are revoked, logging out the thief and the owner.
+pub async fn rotate_refresh_token(
+    refresh_claims: &RefreshClaims,
+    user: &UserInfo,
+    storage: &dyn Storage,
+) -> Result<String, AuthError> {
+    let stored_token = match storage.get_refresh_token(&refresh_claims.jti).await {
+        Err(AuthError::RecordNotFound) => Err(AuthError::InvalidToken),
+        result => result,
+    }?;
+    if stored_token.user_id != user.user_id || stored_token.session_uuid != refresh_claims.session {
+        return Err(AuthError::InvalidToken);
+    }
+
+    let now = Utc::now().timestamp();
+    if !storage
+        .use_refresh_token(&stored_token.token_id, now)
+        .await?
+    {
+        log_warn(&format!(
+            "refresh token {} of user {} was presented again, revoking token family {}",
+            stored_token.token_id, user.user_id, stored_token.family_id
+        ));
+        storage
+            .revoke_refresh_token_family(&stored_token.family_id, now)
+            .await?;
+        storage.drop_session(&stored_token.session_uuid).await?;
+        return Err(AuthError::RefreshTokenReused);
+    }
+
+    issue_refresh_token(
+        user,
+        stored_token.session_uuid,
+        stored_token.family_id,
+        storage,
+    )
+    .await
 }
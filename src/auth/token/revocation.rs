@@ -0,0 +1,198 @@
+use std::collections::HashMap;
+use std::sync::{Mutex, MutexGuard};
+
+use actix_web::ResponseError;
+use chrono::Utc;
+
+use crate::auth::token::access_token::{decode_access_token, unverified_user_id};
+use crate::auth::token::client_token::decode_client_token;
+use crate::auth::token::refresh_token::decode_refresh_token;
+use crate::auth::token::signing_key::TokenSigner;
+use crate::errors::auth_error::AuthError;
+use crate::logging::log::log_info;
+use crate::startup::environment_constants::EnvironmentConstants;
+use crate::storage::Storage;
+
+pub const DEFAULT_REVOCATION_CACHE_TTL: i64 = 30;
+pub const REFRESH_TOKEN_HINT: &str = "refresh_token";
+
+// Stale entries are only dropped once the cache grows past this size
+const MAX_CACHED_TOKENS: usize = 10_000;
+
+struct CachedRevocation {
+    revoked: bool,
+    valid_until: i64,
+}
+
+// Revoked access tokens, looked up by `jti`. Revocations are remembered until the token
+// expires, tokens that are not revoked are only remembered for `cache_ttl` seconds, so a
+// revocation made by another replica is picked up after at most that long.
+pub struct RevocationList {
+    cache: Mutex<HashMap<String, CachedRevocation>>,
+    cache_ttl: i64,
+}
+
+impl RevocationList {
+    pub fn new(cache_ttl: i64) -> RevocationList {
+        RevocationList {
+            cache: Mutex::new(HashMap::new()),
+            cache_ttl,
+        }
+    }
+
+    pub fn from_environment(env_constants: &EnvironmentConstants) -> RevocationList {
+        RevocationList::new(env_constants.revocation_cache_ttl)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<String, CachedRevocation>> {
+        self.cache
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn remember(&self, jti: &str, revoked: bool, valid_until: i64, now: i64) {
+        let mut cache = self.lock();
+        if cache.len() >= MAX_CACHED_TOKENS {
+            cache.retain(|_, cached| cached.valid_until > now);
+        }
+        cache.insert(
+            jti.to_string(),
+            CachedRevocation {
+                revoked,
+                valid_until,
+            },
+        );
+    }
+
+    pub async fn is_revoked(
+        &self,
+        jti: &str,
+        expires_at: i64,
+        storage: &dyn Storage,
+    ) -> Result<bool, AuthError> {
+        let now = Utc::now().timestamp();
+        if let Some(cached) = self
+            .lock()
+            .get(jti)
+            .filter(|cached| cached.valid_until > now)
+        {
+            return Ok(cached.revoked);
+        }
+
+        let revoked = storage.is_access_token_revoked(jti).await?;
+        let valid_until = if revoked {
+            expires_at
+        } else {
+            expires_at.min(now + self.cache_ttl)
+        };
+        self.remember(jti, revoked, valid_until, now);
+        Ok(revoked)
+    }
+
+    pub async fn revoke(
+        &self,
+        jti: &str,
+        expires_at: i64,
+        storage: &dyn Storage,
+    ) -> Result<(), AuthError> {
+        storage.revoke_access_token(jti, expires_at).await?;
+        self.remember(jti, true, expires_at, Utc::now().timestamp());
+        Ok(())
+    }
+}
+
+impl Default for RevocationList {
+    fn default() -> RevocationList {
+        RevocationList::new(DEFAULT_REVOCATION_CACHE_TTL)
+    }
+}
+
+async fn revoke_access_token(
+    token: &str,
+    user_id: i64,
+    storage: &dyn Storage,
+    signer: &TokenSigner,
+    revocations: &RevocationList,
+) -> Result<bool, AuthError> {
+    let claims = match decode_access_token(token, user_id, storage, signer).await {
+        Ok(claims) => claims,
+        Err(e) if e.status_code().is_server_error() => return Err(e),
+        Err(_) => return Ok(false),
+    };
+    revocations.revoke(&claims.jti, claims.exp, storage).await?;
+    log_info(&format!(
+        "access token {} of user {} was revoked",
+        claims.jti, user_id
+    ));
+    Ok(true)
+}
+
+// Tokens of machine clients have no session or refresh token, the `jti` is all there is
+async fn revoke_client_token(
+    token: &str,
+    signer: &TokenSigner,
+    storage: &dyn Storage,
+    revocations: &RevocationList,
+) -> Result<(), AuthError> {
+    let claims = match decode_client_token(token, signer) {
+        Ok(claims) => claims,
+        Err(_) => return Ok(()),
+    };
+    revocations.revoke(&claims.jti, claims.exp, storage).await?;
+    log_info(&format!(
+        "access token {} of client {} was revoked",
+        claims.jti, claims.client_id
+    ));
+    Ok(())
+}
+
+// Access tokens are bound to the session of the refresh token, so the session goes as well
+async fn revoke_refresh_token(
+    token: &str,
+    user_id: i64,
+    storage: &dyn Storage,
+) -> Result<bool, AuthError> {
+    let refresh_claims = match decode_refresh_token(token, user_id, storage).await {
+        Ok(refresh_claims) => refresh_claims,
+        Err(e) if e.status_code().is_server_error() => return Err(e),
+        Err(_) => return Ok(false),
+    };
+    match storage.get_refresh_token(&refresh_claims.jti).await {
+        Ok(stored_token) if stored_token.user_id == user_id => {
+            storage
+                .delete_refresh_token_family(&stored_token.family_id)
+                .await?
+        }
+        Ok(_) | Err(AuthError::RecordNotFound) => {}
+        Err(e) => return Err(e),
+    }
+    storage.drop_session(&refresh_claims.session).await?;
+    log_info(&format!(
+        "refresh token {} of user {} was revoked together with its session",
+        refresh_claims.jti, user_id
+    ));
+    Ok(true)
+}
+
+// RFC 7009: a token that is invalid, expired or already revoked needs no revocation, so
+// only server side failures are reported. The hint only decides which kind is tried first.
+pub async fn revoke_token(
+    token: &str,
+    token_type_hint: Option<&str>,
+    storage: &dyn Storage,
+    signer: &TokenSigner,
+    revocations: &RevocationList,
+) -> Result<(), AuthError> {
+    let user_id = match unverified_user_id(token) {
+        Some(user_id) => user_id,
+        None => return revoke_client_token(token, signer, storage, revocations).await,
+    };
+    if token_type_hint == Some(REFRESH_TOKEN_HINT) {
+        if !revoke_refresh_token(token, user_id, storage).await? {
+            revoke_access_token(token, user_id, storage, signer, revocations).await?;
+        }
+    } else if !revoke_access_token(token, user_id, storage, signer, revocations).await? {
+        revoke_refresh_token(token, user_id, storage).await?;
+    }
+    Ok(())
+}
@@ -0,0 +1,250 @@
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use jsonwebtoken::jwk::{
+    AlgorithmParameters, CommonParameters, EllipticCurve, EllipticCurveKeyParameters,
+    EllipticCurveKeyType, Jwk, JwkSet, OctetKeyPairParameters, OctetKeyPairType, PublicKeyUse,
+    RSAKeyParameters, RSAKeyType,
+};
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey, Header};
+use openssl::bn::BigNumContext;
+use openssl::ec::{EcGroup, EcKey};
+use openssl::nid::Nid;
+use openssl::pkey::{Id, PKey, Private};
+use openssl::rsa::Rsa;
+
+use crate::auth::token::constants::ALGORITHM;
+use crate::auth::token::key_encryption::KeyEncryption;
+use crate::auth::token::keyring::{generate_signing_key, import_signing_key, Keyring};
+use crate::logging::log::log_warn;
+use crate::startup::environment_constants::EnvironmentConstants;
+use crate::storage::Storage;
+
+// Algorithms that can sign access tokens with a server wide key pair
+pub const ASYMMETRIC_ALGORITHMS: [Algorithm; 3] =
+    [Algorithm::RS256, Algorithm::ES256, Algorithm::EdDSA];
+
+const P256_COORDINATE_LENGTH: i32 = 32;
+const RSA_KEY_BITS: u32 = 2048;
+
+pub struct SigningKey {
+    pub key_id: String,
+    pub algorithm: Algorithm,
+    encoding_key: EncodingKey,
+    decoding_key: DecodingKey,
+    jwk: Jwk,
+}
+
+impl SigningKey {
+    // Loads a PEM encoded private key (PKCS#1, SEC1 or PKCS#8), the public half is derived from it.
+    // Without an explicit key id the RFC 7638 thumbprint of the public key is used.
+    pub fn from_pem(
+        algorithm: Algorithm,
+        private_key_pem: &[u8],
+        key_id: Option<String>,
+    ) -> Result<SigningKey, Box<dyn std::error::Error>> {
+        let private_key = PKey::private_key_from_pem(private_key_pem)?;
+        let (encoding_key, key_parameters) = match (algorithm, private_key.id()) {
+            (Algorithm::RS256, Id::RSA) => rsa_key(&private_key)?,
+            (Algorithm::ES256, Id::EC) => ec_key(&private_key)?,
+            (Algorithm::EdDSA, Id::ED25519) => ed25519_key(&private_key)?,
+            (algorithm, _) => {
+                return Err(
+                    format!("private key does not match the {:?} algorithm", algorithm).into(),
+                )
+            }
+        };
+
+        let key_id = match key_id {
+            Some(key_id) => key_id,
+            None => thumbprint(&key_parameters),
+        };
+        let jwk = Jwk {
+            common: CommonParameters {
+                public_key_use: Some(PublicKeyUse::Signature),
+                algorithm: Some(algorithm),
+                key_id: Some(key_id.clone()),
+                ..Default::default()
+            },
+            algorithm: key_parameters,
+        };
+        let decoding_key = DecodingKey::from_jwk(&jwk)?;
+
+        Ok(SigningKey {
+            key_id,
+            algorithm,
+            encoding_key,
+            decoding_key,
+            jwk,
+        })
+    }
+
+    pub fn header(&self) -> Header {
+        let mut header = Header::new(self.algorithm);
+        header.kid = Some(self.key_id.clone());
+        header
+    }
+
+    pub fn encoding_key(&self) -> &EncodingKey {
+        &self.encoding_key
+    }
+
+    pub fn decoding_key(&self) -> &DecodingKey {
+        &self.decoding_key
+    }
+
+    pub fn jwk(&self) -> &Jwk {
+        &self.jwk
+    }
+}
+
+// How access tokens are signed, per-user secrets are kept for deployments that never
+// hand tokens to other services
+pub enum TokenSigner {
+    PerUserSecret,
+    Keyring(Keyring),
+}
+
+impl TokenSigner {
+    pub fn jwk_set(&self) -> JwkSet {
+        match self {
+            TokenSigner::PerUserSecret => JwkSet { keys: Vec::new() },
+            TokenSigner::Keyring(keyring) => keyring.jwk_set(),
+        }
+    }
+}
+
+pub fn parse_asymmetric_algorithm(
+    algorithm: &str,
+) -> Result<Algorithm, Box<dyn std::error::Error>> {
+    let algorithm: Algorithm = algorithm.parse()?;
+    if !ASYMMETRIC_ALGORITHMS.contains(&algorithm) {
+        return Err(format!("{:?} can not be used to sign access tokens", algorithm).into());
+    }
+    Ok(algorithm)
+}
+
+pub fn generate_private_key_pem(
+    algorithm: Algorithm,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let pem = match algorithm {
+        Algorithm::RS256 => Rsa::generate(RSA_KEY_BITS)?.private_key_to_pem()?,
+        Algorithm::ES256 => {
+            let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1)?;
+            EcKey::generate(&group)?.private_key_to_pem()?
+        }
+        Algorithm::EdDSA => PKey::generate_ed25519()?.private_key_to_pem_pkcs8()?,
+        algorithm => {
+            return Err(format!("{:?} can not be used to sign access tokens", algorithm).into())
+        }
+    };
+    Ok(String::from_utf8(pem)?)
+}
+
+pub async fn load_token_signer(
+    constants: &EnvironmentConstants,
+    storage: &dyn Storage,
+) -> Result<TokenSigner, Box<dyn std::error::Error>> {
+    if constants.jwt_signing_algorithm.parse::<Algorithm>()? == ALGORITHM {
+        return Ok(TokenSigner::PerUserSecret);
+    }
+    let algorithm = parse_asymmetric_algorithm(&constants.jwt_signing_algorithm)?;
+    let encryption = KeyEncryption::from_environment(constants)?;
+
+    if !constants.jwt_private_key_path.is_empty() {
+        let private_key_pem = std::fs::read_to_string(&constants.jwt_private_key_path)?;
+        let key_id = Some(constants.jwt_key_id.clone()).filter(|key_id| !key_id.is_empty());
+        import_signing_key(storage, &encryption, algorithm, &private_key_pem, key_id).await?;
+    }
+
+    // only a keyring that was never set up gets a key, stored keys that do not load are an
+    // error of `Keyring::load` and pending or retired ones have to be promoted by hand
+    if storage.get_signing_keys().await?.is_empty() {
+        let stored_key = generate_signing_key(storage, &encryption, algorithm, true).await?;
+        log_warn(&format!(
+            "no signing key found, generated {} key {}",
+            stored_key.algorithm, stored_key.key_id
+        ));
+    }
+    let keyring = Keyring::load(storage, encryption).await?;
+    if !keyring.has_active_key() {
+        return Err("no active signing key, promote one with `auth_server keys promote`".into());
+    }
+    Ok(TokenSigner::Keyring(keyring))
+}
+
+fn base64_url(bytes: &[u8]) -> String {
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+fn rsa_key(
+    private_key: &PKey<Private>,
+) -> Result<(EncodingKey, AlgorithmParameters), Box<dyn std::error::Error>> {
+    let rsa = private_key.rsa()?;
+    let parameters = AlgorithmParameters::RSA(RSAKeyParameters {
+        key_type: RSAKeyType::RSA,
+        n: base64_url(&rsa.n().to_vec()),
+        e: base64_url(&rsa.e().to_vec()),
+    });
+    Ok((
+        EncodingKey::from_rsa_der(&rsa.private_key_to_der()?),
+        parameters,
+    ))
+}
+
+fn ec_key(
+    private_key: &PKey<Private>,
+) -> Result<(EncodingKey, AlgorithmParameters), Box<dyn std::error::Error>> {
+    let ec_key: EcKey<Private> = private_key.ec_key()?;
+    if ec_key.group().curve_name() != Some(Nid::X9_62_PRIME256V1) {
+        return Err("ES256 requires a P-256 key".into());
+    }
+
+    let mut context = BigNumContext::new()?;
+    let mut x = openssl::bn::BigNum::new()?;
+    let mut y = openssl::bn::BigNum::new()?;
+    ec_key
+        .public_key()
+        .affine_coordinates(ec_key.group(), &mut x, &mut y, &mut context)?;
+    let parameters = AlgorithmParameters::EllipticCurve(EllipticCurveKeyParameters {
+        key_type: EllipticCurveKeyType::EC,
+        curve: EllipticCurve::P256,
+        x: base64_url(&x.to_vec_padded(P256_COORDINATE_LENGTH)?),
+        y: base64_url(&y.to_vec_padded(P256_COORDINATE_LENGTH)?),
+    });
+    Ok((
+        EncodingKey::from_ec_der(&private_key.private_key_to_pkcs8()?),
+        parameters,
+    ))
+}
+
+fn ed25519_key(
+    private_key: &PKey<Private>,
+) -> Result<(EncodingKey, AlgorithmParameters), Box<dyn std::error::Error>> {
+    let parameters = AlgorithmParameters::OctetKeyPair(OctetKeyPairParameters {
+        key_type: OctetKeyPairType::OctetKeyPair,
+        curve: EllipticCurve::Ed25519,
+        x: base64_url(&private_key.raw_public_key()?),
+    });
+    Ok((
+        EncodingKey::from_ed_der(&private_key.private_key_to_pkcs8()?),
+        parameters,
+    ))
+}
+
+// RFC 7638: SHA-256 over the required members in lexicographic order, without whitespace
+fn thumbprint(parameters: &AlgorithmParameters) -> String {
+    let canonical_json = match parameters {
+        AlgorithmParameters::RSA(rsa) => {
+            format!(r#"{{"e":"{}","kty":"RSA","n":"{}"}}"#, rsa.e, rsa.n)
+        }
+        AlgorithmParameters::EllipticCurve(ec) => format!(
+            r#"{{"crv":"P-256","kty":"EC","x":"{}","y":"{}"}}"#,
+            ec.x, ec.y
+        ),
+        AlgorithmParameters::OctetKeyPair(okp) => {
+            format!(r#"{{"crv":"Ed25519","kty":"OKP","x":"{}"}}"#, okp.x)
+        }
+        AlgorithmParameters::OctetKey(oct) => format!(r#"{{"k":"{}","kty":"oct"}}"#, oct.value),
+    };
+    base64_url(&openssl::sha::sha256(canonical_json.as_bytes()))
+}
@@ -1,5 +1,7 @@
 use serde::{Deserialize, Serialize};
 
+use crate::auth::token::delivery::TokenDelivery;
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct RegisterUserInfo {
     pub username: String,
@@ -11,6 +13,8 @@ pub struct RegisterUserInfo {
 pub struct User {
     pub username: String,
     pub password: String,
+    #[serde(default)]
+    pub token_delivery: TokenDelivery,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
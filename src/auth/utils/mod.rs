@@ -1,3 +1,2 @@
-pub mod errors;
 pub mod password;
 pub mod validate_request;
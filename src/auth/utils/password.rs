@@ -1,12 +1,11 @@
 use crate::utils::random::random_string;
 use argon2::Config;
 
+use crate::errors::auth_error::AuthError;
+
 const SALT_LENGTH: usize = 16;
 
-pub async fn verify_password(
-    password: &str,
-    stored_password: &str,
-) -> Result<bool, Box<dyn std::error::Error>> {
+pub async fn verify_password(password: &str, stored_password: &str) -> Result<bool, AuthError> {
     Ok(argon2::verify_encoded(
         stored_password,
         password.as_bytes(),
@@ -1,27 +1,51 @@
+use actix_web::http::header;
+
 use crate::auth::cookies::utils::{extract_user_id_from_cookie, get_token_from_cookie};
-use crate::auth::token::access_token::{validate_token, Claims};
-use crate::auth::token::errors::TokenValidationNotSuccessfull;
+use crate::auth::token::access_token::{unverified_user_id, validate_token, Claims};
+use crate::auth::token::revocation::RevocationList;
+use crate::auth::token::signing_key::TokenSigner;
+use crate::errors::auth_error::AuthError;
 use crate::logging::log::log_warn;
+use crate::storage::{Storage, StoredSession};
+
+// Other authorization schemes are left to the cookies
+fn get_bearer_token(req: &actix_web::HttpRequest) -> Option<String> {
+    let value = req.headers().get(header::AUTHORIZATION)?.to_str().ok()?;
+    let (scheme, token) = value.split_once(' ')?;
+    if !scheme.eq_ignore_ascii_case("Bearer") {
+        return None;
+    }
+    Some(token.trim().to_string())
+}
 
 async fn proceed_with_validation(
     token: &str,
-    req: &actix_web::HttpRequest,
-) -> Result<Claims, Box<dyn std::error::Error>> {
-    let user_id = extract_user_id_from_cookie(req).await?;
-    match validate_token(token, user_id).await {
-        Ok(claims) => Ok(claims),
-        Err(e) => {
-            log_warn(&e.to_string());
-            Err(Box::new(TokenValidationNotSuccessfull))
-        }
-    }
+    user_id: i64,
+    storage: &dyn Storage,
+    signer: &TokenSigner,
+    revocations: &RevocationList,
+) -> Result<(Claims, StoredSession), AuthError> {
+    validate_token(token, user_id, storage, signer, revocations)
+        .await
+        .inspect_err(|e| log_warn(&e.to_string()))
 }
 
+// A bearer token names its user itself, `validate_token` then checks the signature of that
+// user, cookies name the user through the session cookie
 pub async fn validate_http_request(
     req: &actix_web::HttpRequest,
-) -> Result<Claims, Box<dyn std::error::Error>> {
-    match get_token_from_cookie(req) {
-        Ok(token) => proceed_with_validation(&token, req).await,
-        Err(e) => Err(e),
+    storage: &dyn Storage,
+    signer: &TokenSigner,
+    revocations: &RevocationList,
+) -> Result<(Claims, StoredSession), AuthError> {
+    if let Some(token) = get_bearer_token(req) {
+        let user_id = unverified_user_id(&token).ok_or(AuthError::InvalidToken)?;
+        if !storage.does_user_id_exists(user_id).await? {
+            return Err(AuthError::InvalidToken);
+        }
+        return proceed_with_validation(&token, user_id, storage, signer, revocations).await;
     }
+    let token = get_token_from_cookie(req)?;
+    let user_id = extract_user_id_from_cookie(req, storage).await?;
+    proceed_with_validation(&token, user_id, storage, signer, revocations).await
 }
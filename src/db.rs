@@ -1,202 +1,167 @@
+use std::time::Duration;
+
 use sqlx::postgres::PgPoolOptions;
 
 use uuid::Uuid;
 
 use crate::auth::user::UserInfo;
-use crate::logging::log::{log_error, log_warn};
+use crate::errors::auth_error::AuthError;
+use crate::logging::log::{log_error, log_info, log_warn};
+use crate::startup::environment_constants::EnvironmentConstants;
+use crate::storage::{
+    NewSession, NewUser, StoredAuthorizationCode, StoredClient, StoredRefreshToken, StoredSession,
+    StoredSigningKey,
+};
 
-pub const MAX_DB_CONNECTIONS: u32 = 7;
 pub const SECRET_ACCESS_KEY_TABLE: &str = "secret_access_keys";
 pub const SECRET_REFRESH_KEY_TABLE: &str = "refresh_access_keys";
 pub const USERS_TABLE: &str = "users";
 pub const SESSION_TABLE: &str = "session_table";
 pub const SUBSCRIPTION_LEVEL_TYPE: &str = "subscription_level";
+pub const SIGNING_KEY_TABLE: &str = "signing_keys";
+pub const REFRESH_TOKEN_TABLE: &str = "refresh_tokens";
+pub const REVOKED_TOKEN_TABLE: &str = "revoked_tokens";
+pub const CLIENT_TABLE: &str = "clients";
+pub const USER_ROLE_TABLE: &str = "user_roles";
+pub const AUTHORIZATION_CODE_TABLE: &str = "authorization_codes";
+pub const CONSENT_TABLE: &str = "consents";
+pub const MIGRATIONS_TABLE: &str = "_sqlx_migrations";
 
-pub static mut ENVIRONMENT_CONSTANTS: Option<
-    crate::startup::environment_constants::EnvironmentConstants,
-> = None;
+pub async fn set_user_subscription_level(
+    pool: &sqlx::Pool<sqlx::Postgres>,
+    user_id: i64,
+    level: &str,
+) -> Result<(), AuthError> {
+    let query = format!(
+        "UPDATE {} SET subscription = $1 WHERE user_id = $2",
+        USERS_TABLE
+    );
 
-fn quote_string_value(value: &str) -> String {
-    format!("\'{}\'", value)
-}
+    sqlx::query(&query)
+        .bind(level)
+        .bind(user_id)
+        .execute(pool)
+        .await?;
 
-pub async fn check_database_connection() -> Result<(), Box<dyn std::error::Error>> {
-    match crate::db::create_pool().await {
-        Ok(_) => Ok(()),
-        _ => {
-            log_error("Cannot connect to the database");
-            std::process::abort();
-        }
-    }
+    Ok(())
 }
 
-async fn create_secret_access_key_table(
+pub async fn get_user_subscription_level(
     pool: &sqlx::Pool<sqlx::Postgres>,
-) -> Result<(), Box<dyn std::error::Error>> {
+    user_id: i64,
+) -> Result<String, AuthError> {
     let query = format!(
-        r"CREATE TABLE IF NOT EXISTS {} (
-user_id BIGINT PRIMARY KEY,
-secret_key VARCHAR(255) NOT NULL)",
-        SECRET_ACCESS_KEY_TABLE
+        "SELECT subscription::text FROM {} WHERE user_id = $1",
+        USERS_TABLE
     );
+    let row: (String,) = sqlx::query_as(&query).bind(user_id).fetch_one(pool).await?;
+    Ok(row.0)
+}
 
+async fn drop_user_table(pool: &sqlx::Pool<sqlx::Postgres>) -> Result<(), AuthError> {
+    let query = format!("DROP TABLE IF EXISTS {}", USERS_TABLE);
     sqlx::query(&query).execute(pool).await?;
     Ok(())
 }
 
-async fn create_secret_refresh_key_table(
-    pool: &sqlx::Pool<sqlx::Postgres>,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let query = format!(
-        r"CREATE TABLE IF NOT EXISTS {} (
-user_id BIGINT PRIMARY KEY,
-secret_refresh_key VARCHAR(255) NOT NULL)",
-        SECRET_REFRESH_KEY_TABLE
-    );
+async fn drop_level_subscription_type(pool: &sqlx::Pool<sqlx::Postgres>) -> Result<(), AuthError> {
+    let query = format!("DROP TYPE IF EXISTS {}", SUBSCRIPTION_LEVEL_TYPE);
     sqlx::query(&query).execute(pool).await?;
     Ok(())
 }
 
-async fn create_session_table(
-    pool: &sqlx::Pool<sqlx::Postgres>,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let query = format!(
-        r"CREATE TABLE IF NOT EXISTS {} (
-session_uuid varchar(36) PRIMARY KEY,
-user_id BIGINT NOT NULL, 
-session_id BIGSERIAL UNIQUE,
-log_date TIMESTAMP NOT NULL DEFAULT current_timestamp)",
-        SESSION_TABLE
-    );
-    sqlx::query(&query).execute(pool).await?;
+async fn drop_user_backend(pool: &sqlx::Pool<sqlx::Postgres>) -> Result<(), AuthError> {
+    drop_user_table(pool).await?;
+    drop_level_subscription_type(pool).await?;
     Ok(())
 }
 
-async fn create_user_level_enum_type(
-    pool: &sqlx::Pool<sqlx::Postgres>,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let query = format!(
-        r"
-        DO $$ BEGIN
-            CREATE TYPE {} AS ENUM (
-            'non-premium',
-            'basic-premium',
-            'normal-premium',
-            'enterprise-premium');
-        EXCEPTION
-            WHEN duplicate_object THEN null;
-        END $$;",
-        SUBSCRIPTION_LEVEL_TYPE
-    );
+async fn drop_secret_access_key_table(pool: &sqlx::Pool<sqlx::Postgres>) -> Result<(), AuthError> {
+    let query = format!("DROP TABLE IF EXISTS {}", SECRET_ACCESS_KEY_TABLE);
     sqlx::query(&query).execute(pool).await?;
     Ok(())
 }
 
-async fn create_user_backend(
-    pool: &sqlx::Pool<sqlx::Postgres>,
-) -> Result<(), Box<dyn std::error::Error>> {
-    create_user_level_enum_type(pool).await?;
-    create_user_table(pool).await?;
+async fn drop_session_table(pool: &sqlx::Pool<sqlx::Postgres>) -> Result<(), AuthError> {
+    let query = format!("DROP TABLE IF EXISTS {}", SESSION_TABLE);
+    sqlx::query(&query).execute(pool).await?;
     Ok(())
 }
 
-async fn create_user_table(
-    pool: &sqlx::Pool<sqlx::Postgres>,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let query = format!(
-        r"CREATE TABLE IF NOT EXISTS {} (
-        user_id BIGSERIAL PRIMARY KEY, 
-        username VARCHAR(50) NOT NULL UNIQUE,
-        password VARCHAR(256) NOT NULL,
-        email VARCHAR(50) NOT NULL,
-        subscription subscription_level NOT NULL DEFAULT 'non-premium'
-        );",
-        USERS_TABLE
-    );
+async fn drop_secret_refresh_key_table(pool: &sqlx::Pool<sqlx::Postgres>) -> Result<(), AuthError> {
+    let query = format!("DROP TABLE IF EXISTS {}", SECRET_REFRESH_KEY_TABLE);
     sqlx::query(&query).execute(pool).await?;
     Ok(())
 }
 
-// TODO: [SPIR-101] this function will be used in this task
-#[allow(dead_code)]
-pub async fn set_user_subscription_level(
-    pool: &sqlx::Pool<sqlx::Postgres>,
-    user_id: i64,
-    level: &str,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let query = format!(
-        "UPDATE {} SET subscription = $1 WHERE user_id = $2",
-        USERS_TABLE
-    );
-
-    sqlx::query(&query)
-        .bind(level)
-        .bind(user_id)
-        .execute(pool)
-        .await?;
+async fn drop_signing_key_table(pool: &sqlx::Pool<sqlx::Postgres>) -> Result<(), AuthError> {
+    let query = format!("DROP TABLE IF EXISTS {}", SIGNING_KEY_TABLE);
+    sqlx::query(&query).execute(pool).await?;
+    Ok(())
+}
 
+async fn drop_refresh_token_table(pool: &sqlx::Pool<sqlx::Postgres>) -> Result<(), AuthError> {
+    let query = format!("DROP TABLE IF EXISTS {}", REFRESH_TOKEN_TABLE);
+    sqlx::query(&query).execute(pool).await?;
     Ok(())
 }
 
-async fn drop_user_table(
-    pool: &sqlx::Pool<sqlx::Postgres>,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let query = format!("DROP TABLE IF EXISTS {}", USERS_TABLE);
+async fn drop_revoked_token_table(pool: &sqlx::Pool<sqlx::Postgres>) -> Result<(), AuthError> {
+    let query = format!("DROP TABLE IF EXISTS {}", REVOKED_TOKEN_TABLE);
     sqlx::query(&query).execute(pool).await?;
     Ok(())
 }
 
-async fn drop_level_subscription_type(
-    pool: &sqlx::Pool<sqlx::Postgres>,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let query = format!("DROP TYPE IF EXISTS {}", SUBSCRIPTION_LEVEL_TYPE);
+async fn drop_client_table(pool: &sqlx::Pool<sqlx::Postgres>) -> Result<(), AuthError> {
+    let query = format!("DROP TABLE IF EXISTS {}", CLIENT_TABLE);
     sqlx::query(&query).execute(pool).await?;
     Ok(())
 }
 
-async fn drop_user_backend(
-    pool: &sqlx::Pool<sqlx::Postgres>,
-) -> Result<(), Box<dyn std::error::Error>> {
-    drop_user_table(pool).await?;
-    drop_level_subscription_type(pool).await?;
+async fn drop_user_role_table(pool: &sqlx::Pool<sqlx::Postgres>) -> Result<(), AuthError> {
+    let query = format!("DROP TABLE IF EXISTS {}", USER_ROLE_TABLE);
+    sqlx::query(&query).execute(pool).await?;
     Ok(())
 }
 
-async fn drop_secret_access_key_table(
-    pool: &sqlx::Pool<sqlx::Postgres>,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let query = format!("DROP TABLE IF EXISTS {}", SECRET_ACCESS_KEY_TABLE);
+async fn drop_authorization_code_table(pool: &sqlx::Pool<sqlx::Postgres>) -> Result<(), AuthError> {
+    let query = format!("DROP TABLE IF EXISTS {}", AUTHORIZATION_CODE_TABLE);
     sqlx::query(&query).execute(pool).await?;
     Ok(())
 }
 
-async fn drop_session_table(
-    pool: &sqlx::Pool<sqlx::Postgres>,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let query = format!("DROP TABLE IF EXISTS {}", SESSION_TABLE);
+async fn drop_consent_table(pool: &sqlx::Pool<sqlx::Postgres>) -> Result<(), AuthError> {
+    let query = format!("DROP TABLE IF EXISTS {}", CONSENT_TABLE);
     sqlx::query(&query).execute(pool).await?;
     Ok(())
 }
 
-async fn drop_secret_refresh_key_table(
-    pool: &sqlx::Pool<sqlx::Postgres>,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let query = format!("DROP TABLE IF EXISTS {}", SECRET_REFRESH_KEY_TABLE);
+async fn drop_migrations_table(pool: &sqlx::Pool<sqlx::Postgres>) -> Result<(), AuthError> {
+    let query = format!("DROP TABLE IF EXISTS {}", MIGRATIONS_TABLE);
     sqlx::query(&query).execute(pool).await?;
     Ok(())
 }
 
-pub async fn clear_database() -> Result<(), Box<dyn std::error::Error>> {
-    let pool = create_pool().await?;
+pub async fn clear_database(pool: &sqlx::Pool<sqlx::Postgres>) -> Result<(), AuthError> {
     log_warn("starting database clean up...");
-    drop_user_backend(&pool).await?;
-    create_user_backend(&pool).await?;
-    drop_secret_access_key_table(&pool).await?;
-    create_secret_access_key_table(&pool).await?;
-    drop_secret_refresh_key_table(&pool).await?;
-    create_secret_refresh_key_table(&pool).await?;
-    drop_session_table(&pool).await?;
-    create_session_table(&pool).await?;
+    drop_user_backend(pool).await?;
+    drop_secret_access_key_table(pool).await?;
+    drop_secret_refresh_key_table(pool).await?;
+    drop_session_table(pool).await?;
+    drop_signing_key_table(pool).await?;
+    drop_refresh_token_table(pool).await?;
+    drop_revoked_token_table(pool).await?;
+    drop_client_table(pool).await?;
+    drop_user_role_table(pool).await?;
+    drop_authorization_code_table(pool).await?;
+    drop_consent_table(pool).await?;
+    drop_migrations_table(pool).await?;
+    crate::migrations::runner::run_pending_migrations(
+        pool,
+        &crate::migrations::runner::POSTGRES_MIGRATOR,
+    )
+    .await
+    .map_err(|e| AuthError::Storage(e.to_string()))?;
     log_warn("database clean up done.");
 
     Ok(())
@@ -205,12 +170,9 @@ pub async fn clear_database() -> Result<(), Box<dyn std::error::Error>> {
 pub async fn does_user_id_exists(
     pool: &sqlx::Pool<sqlx::Postgres>,
     user_id: i64,
-) -> Result<bool, Box<dyn std::error::Error>> {
-    let query = format!(
-        "SELECT COUNT(*) FROM {} WHERE user_id={}",
-        USERS_TABLE, user_id
-    );
-    let row: (i64,) = sqlx::query_as(&query).fetch_one(pool).await?;
+) -> Result<bool, AuthError> {
+    let query = format!("SELECT COUNT(*) FROM {} WHERE user_id = $1", USERS_TABLE);
+    let row: (i64,) = sqlx::query_as(&query).bind(user_id).fetch_one(pool).await?;
 
     Ok(row.0 == 1)
 }
@@ -218,50 +180,51 @@ pub async fn does_user_id_exists(
 pub async fn does_username_exists(
     pool: &sqlx::Pool<sqlx::Postgres>,
     username: &str,
-) -> Result<bool, Box<dyn std::error::Error>> {
-    let query = format!(
-        "SELECT COUNT(*) FROM {} WHERE username={}",
-        USERS_TABLE,
-        quote_string_value(username)
-    );
-    let row: (i64,) = sqlx::query_as(&query).fetch_one(pool).await?;
+) -> Result<bool, AuthError> {
+    let query = format!("SELECT COUNT(*) FROM {} WHERE username = $1", USERS_TABLE);
+    let row: (i64,) = sqlx::query_as(&query)
+        .bind(username)
+        .fetch_one(pool)
+        .await?;
     Ok(row.0 == 1)
 }
 
 pub async fn get_secret_access_key(
     user_id: i64,
     pool: &sqlx::Pool<sqlx::Postgres>,
-) -> Result<String, Box<dyn std::error::Error>> {
+) -> Result<String, AuthError> {
     let query = format!(
-        "SELECT secret_key FROM {} WHERE user_id={}",
-        SECRET_ACCESS_KEY_TABLE, user_id
+        "SELECT secret_key FROM {} WHERE user_id = $1",
+        SECRET_ACCESS_KEY_TABLE
     );
-    let row: (String,) = sqlx::query_as(&query).fetch_one(pool).await?;
+    let row: (String,) = sqlx::query_as(&query).bind(user_id).fetch_one(pool).await?;
     Ok(row.0)
 }
 
 pub async fn get_secret_refresh_key(
     user_id: i64,
     pool: &sqlx::Pool<sqlx::Postgres>,
-) -> Result<String, Box<dyn std::error::Error>> {
+) -> Result<String, AuthError> {
     let query = format!(
-        "SELECT secret_refresh_key FROM {} WHERE user_id={}",
-        SECRET_REFRESH_KEY_TABLE, user_id
+        "SELECT secret_refresh_key FROM {} WHERE user_id = $1",
+        SECRET_REFRESH_KEY_TABLE
     );
-    let row: (String,) = sqlx::query_as(&query).fetch_one(pool).await?;
+    let row: (String,) = sqlx::query_as(&query).bind(user_id).fetch_one(pool).await?;
     Ok(row.0)
 }
 
 pub async fn get_user_from_db(
     username: &str,
     pool: &sqlx::Pool<sqlx::Postgres>,
-) -> Result<UserInfo, Box<dyn std::error::Error>> {
+) -> Result<UserInfo, AuthError> {
     let query = format!(
-        "SELECT user_id, password, email FROM {} WHERE username={}",
-        USERS_TABLE,
-        quote_string_value(username)
+        "SELECT user_id, password, email FROM {} WHERE username = $1",
+        USERS_TABLE
     );
-    let row: (i64, String, String) = sqlx::query_as(&query).fetch_one(pool).await?;
+    let row: (i64, String, String) = sqlx::query_as(&query)
+        .bind(username)
+        .fetch_one(pool)
+        .await?;
 
     let user = UserInfo {
         user_id: row.0,
@@ -275,13 +238,14 @@ pub async fn get_user_from_db(
 pub async fn get_user_from_db_with_user_id(
     user_id: i64,
     pool: &sqlx::Pool<sqlx::Postgres>,
-) -> Result<UserInfo, Box<dyn std::error::Error>> {
+) -> Result<UserInfo, AuthError> {
     let query = format!(
-        "SELECT username, password, email FROM {} WHERE user_id={}",
-        USERS_TABLE, user_id
+        "SELECT username, password, email FROM {} WHERE user_id = $1",
+        USERS_TABLE
     );
 
-    let row: (String, String, String) = sqlx::query_as(&query).fetch_one(pool).await?;
+    let row: (String, String, String) =
+        sqlx::query_as(&query).bind(user_id).fetch_one(pool).await?;
 
     let user = UserInfo {
         user_id,
@@ -292,183 +256,652 @@ pub async fn get_user_from_db_with_user_id(
     Ok(user)
 }
 
-pub async fn create_pool() -> Result<sqlx::Pool<sqlx::Postgres>, sqlx::Error> {
-    unsafe {
-        match &ENVIRONMENT_CONSTANTS {
-            Some(constants) => {
-                // TODO: [SPIR-63] Database address should be evaluated only once
-                let database_url = format!(
-                    "postgres://{}:{}@{}:{}/{}",
-                    constants.database_user.clone(),
-                    constants.database_password.clone(),
-                    constants.database_address.clone(),
-                    constants.database_port.clone(),
-                    constants.database_name.clone()
-                );
-
-                crate::logging::log::log_info(&format!(
-                    "Connecting to database at address: {}",
-                    database_url
-                ));
-                PgPoolOptions::new()
-                    .max_connections(MAX_DB_CONNECTIONS)
-                    .connect(&database_url)
-                    .await
-            }
-            _ => {
-                log_error("We lost access to environemnt variables");
-                std::process::abort();
-            }
-        }
-    }
-}
+pub async fn create_pool(
+    constants: &EnvironmentConstants,
+) -> Result<sqlx::Pool<sqlx::Postgres>, sqlx::Error> {
+    let database_url = format!(
+        "postgres://{}:{}@{}:{}/{}",
+        constants.database_user,
+        constants.database_password,
+        constants.database_address,
+        constants.database_port,
+        constants.database_name
+    );
 
-pub async fn store_secret_access_key(
+    log_info(&format!(
+        "Connecting to database at: {}:{}/{} (max connections: {})",
+        constants.database_address,
+        constants.database_port,
+        constants.database_name,
+        constants.database_max_connections
+    ));
+    PgPoolOptions::new()
+        .max_connections(constants.database_max_connections)
+        .min_connections(constants.database_min_connections)
+        // sqlx 0.5 uses the connect timeout as the deadline for acquiring a pooled connection
+        .connect_timeout(Duration::from_secs(constants.database_acquire_timeout))
+        .idle_timeout(Duration::from_secs(constants.database_idle_timeout))
+        .connect(&database_url)
+        .await
+}
+
+pub async fn store_secret_access_key<'e, E>(
     user_id: i64,
     secret_key: &str,
-    pool: &sqlx::Pool<sqlx::Postgres>,
-) -> Result<(), Box<dyn std::error::Error>> {
+    executor: E,
+) -> Result<(), AuthError>
+where
+    E: sqlx::Executor<'e, Database = sqlx::Postgres>,
+{
     let query = format!(
-        "INSERT INTO {} (user_id, secret_key) VALUES ({}, {})",
-        SECRET_ACCESS_KEY_TABLE,
-        user_id,
-        quote_string_value(secret_key)
+        "INSERT INTO {} (user_id, secret_key) VALUES ($1, $2)",
+        SECRET_ACCESS_KEY_TABLE
     );
-    sqlx::query(&query).execute(pool).await?;
+    sqlx::query(&query)
+        .bind(user_id)
+        .bind(secret_key)
+        .execute(executor)
+        .await?;
     Ok(())
 }
 
-pub async fn store_secret_refresh_key(
+pub async fn store_secret_refresh_key<'e, E>(
     user_id: i64,
     secret_refresh_key: &str,
-    pool: &sqlx::Pool<sqlx::Postgres>,
-) -> Result<(), Box<dyn std::error::Error>> {
+    executor: E,
+) -> Result<(), AuthError>
+where
+    E: sqlx::Executor<'e, Database = sqlx::Postgres>,
+{
     let query = format!(
-        "INSERT INTO {} (user_id, secret_refresh_key) VALUES ({}, {})",
-        SECRET_REFRESH_KEY_TABLE,
-        user_id,
-        quote_string_value(secret_refresh_key)
+        "INSERT INTO {} (user_id, secret_refresh_key) VALUES ($1, $2)",
+        SECRET_REFRESH_KEY_TABLE
     );
 
-    sqlx::query(&query).execute(pool).await?;
+    sqlx::query(&query)
+        .bind(user_id)
+        .bind(secret_refresh_key)
+        .execute(executor)
+        .await?;
     Ok(())
 }
 
-#[derive(Debug)]
-pub struct SessionCreationError;
-
-impl std::fmt::Display for SessionCreationError {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "Session was not created!")
-    }
-}
-
-impl std::error::Error for SessionCreationError {}
-
 pub async fn create_session(
-    user: &UserInfo,
+    new_session: &NewSession<'_>,
     pool: &sqlx::Pool<sqlx::Postgres>,
-) -> Result<String, Box<dyn std::error::Error>> {
+) -> Result<String, AuthError> {
     let session_uuid = Uuid::new_v4();
-    match store_session_info(user.user_id, &session_uuid, pool).await {
+    match store_session_info(new_session, &session_uuid, pool).await {
         Ok(_) => Ok(session_uuid.to_string()),
         Err(e) => {
             log_error(&format!(
                 "There was an error with session creation.\nReason: {}",
                 e
             ));
-            Err(Box::new(SessionCreationError))
+            Err(e)
         }
     }
 }
 
-pub async fn get_session_uuid(
-    user_id: i64,
+pub async fn get_session_user_id(
+    session_uuid: &str,
     pool: &sqlx::Pool<sqlx::Postgres>,
-) -> Result<String, Box<dyn std::error::Error>> {
+) -> Result<i64, AuthError> {
     let query = format!(
-        "SELECT session_uuid FROM {} WHERE user_id={} ORDER BY session_id DESC",
-        SESSION_TABLE, user_id
+        "SELECT user_id FROM {} WHERE session_uuid = $1",
+        SESSION_TABLE
     );
-    let row: (String,) = sqlx::query_as(&query).fetch_one(pool).await?;
+
+    let row: (i64,) = sqlx::query_as(&query)
+        .bind(session_uuid)
+        .fetch_one(pool)
+        .await?;
     Ok(row.0)
 }
 
-pub async fn get_session_user_id(
-    session_uuid: &str,
+pub async fn store_session_info(
+    new_session: &NewSession<'_>,
+    session_uuid: &Uuid,
     pool: &sqlx::Pool<sqlx::Postgres>,
-) -> Result<i64, Box<dyn std::error::Error>> {
+) -> Result<(), AuthError> {
     let query = format!(
-        "SELECT user_id FROM {} WHERE session_uuid = {}",
-        SESSION_TABLE,
-        quote_string_value(session_uuid)
+        "INSERT INTO {} (user_id, session_uuid, created_at, last_used_at, expires_at, \
+         idle_timeout, ip_address, user_agent, client_id, scope, auth_time) \
+         VALUES ($1, $2, $3, $3, $4, $5, $6, $7, $8, $9, $10)",
+        SESSION_TABLE
     );
 
-    let row: (i64,) = sqlx::query_as(&query).fetch_one(pool).await?;
-    Ok(row.0)
+    sqlx::query(&query)
+        .bind(new_session.user_id)
+        .bind(session_uuid.to_string())
+        .bind(new_session.created_at)
+        .bind(new_session.expires_at)
+        .bind(new_session.idle_timeout)
+        .bind(new_session.ip_address)
+        .bind(new_session.user_agent)
+        .bind(new_session.client_id)
+        .bind(new_session.scope)
+        .bind(new_session.auth_time)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+pub async fn get_user_sessions(
+    user_id: i64,
+    pool: &sqlx::Pool<sqlx::Postgres>,
+) -> Result<Vec<StoredSession>, AuthError> {
+    let query = format!(
+        "SELECT session_id, session_uuid, user_id, created_at, last_used_at, expires_at, \
+         idle_timeout, ip_address, user_agent, client_id, scope, auth_time FROM {} WHERE user_id = $1 ORDER BY session_id",
+        SESSION_TABLE
+    );
+    Ok(sqlx::query_as(&query).bind(user_id).fetch_all(pool).await?)
 }
 
-pub async fn get_user_id_with_session_uuid(
+pub async fn get_session(
     session_uuid: &str,
-) -> Result<i64, Box<dyn std::error::Error>> {
-    let pool = crate::db::create_pool().await?;
-    get_session_user_id(session_uuid, &pool).await
+    pool: &sqlx::Pool<sqlx::Postgres>,
+) -> Result<StoredSession, AuthError> {
+    let query = format!(
+        "SELECT session_id, session_uuid, user_id, created_at, last_used_at, expires_at, \
+         idle_timeout, ip_address, user_agent, client_id, scope, auth_time FROM {} WHERE session_uuid = $1",
+        SESSION_TABLE
+    );
+    Ok(sqlx::query_as(&query)
+        .bind(session_uuid)
+        .fetch_one(pool)
+        .await?)
 }
 
-pub async fn store_session_info(
-    user_id: i64,
-    session_uuid: &Uuid,
+pub async fn purge_expired_sessions(
+    now: i64,
     pool: &sqlx::Pool<sqlx::Postgres>,
-) -> Result<(), Box<dyn std::error::Error>> {
+) -> Result<u64, AuthError> {
+    let mut transaction = pool.begin().await?;
     let query = format!(
-        "INSERT INTO {} (user_id, session_uuid) VALUES ({}, {})",
-        SESSION_TABLE,
-        user_id,
-        quote_string_value(&session_uuid.to_string())
+        "DELETE FROM {} WHERE expires_at <= $1 OR last_used_at + idle_timeout <= $1",
+        SESSION_TABLE
+    );
+    let purged = sqlx::query(&query)
+        .bind(now)
+        .execute(&mut transaction)
+        .await?
+        .rows_affected();
+    let query = format!(
+        "DELETE FROM {0} WHERE expires_at <= $1 \
+         OR NOT EXISTS (SELECT 1 FROM {1} WHERE {1}.session_uuid = {0}.session_uuid)",
+        REFRESH_TOKEN_TABLE, SESSION_TABLE
     );
+    sqlx::query(&query)
+        .bind(now)
+        .execute(&mut transaction)
+        .await?;
+    let query = format!("DELETE FROM {} WHERE expires_at <= $1", REVOKED_TOKEN_TABLE);
+    sqlx::query(&query)
+        .bind(now)
+        .execute(&mut transaction)
+        .await?;
+    let query = format!(
+        "DELETE FROM {} WHERE expires_at <= $1",
+        AUTHORIZATION_CODE_TABLE
+    );
+    sqlx::query(&query)
+        .bind(now)
+        .execute(&mut transaction)
+        .await?;
+    transaction.commit().await?;
+    Ok(purged)
+}
 
-    sqlx::query(&query).execute(pool).await?;
+pub async fn touch_session(
+    session_uuid: &str,
+    used_at: i64,
+    pool: &sqlx::Pool<sqlx::Postgres>,
+) -> Result<(), AuthError> {
+    let query = format!(
+        "UPDATE {} SET last_used_at = $1 WHERE session_uuid = $2",
+        SESSION_TABLE
+    );
+    sqlx::query(&query)
+        .bind(used_at)
+        .bind(session_uuid)
+        .execute(pool)
+        .await?;
     Ok(())
 }
 
-pub async fn store_new_user(
+pub async fn store_new_user<'e, E>(
     username: &str,
     password_hash: &str,
     email: &str,
-    pool: &sqlx::Pool<sqlx::Postgres>,
-) -> Result<(), Box<dyn std::error::Error>> {
+    executor: E,
+) -> Result<i64, AuthError>
+where
+    E: sqlx::Executor<'e, Database = sqlx::Postgres>,
+{
     let query = format!(
-        "INSERT INTO {} (username, password, email) VALUES ({}, {}, {})",
-        USERS_TABLE,
-        quote_string_value(username),
-        quote_string_value(password_hash),
-        quote_string_value(email)
+        "INSERT INTO {} (username, password, email) VALUES ($1, $2, $3) RETURNING user_id",
+        USERS_TABLE
     );
 
-    sqlx::query(&query).execute(pool).await?;
-    Ok(())
+    let row: (i64,) = sqlx::query_as(&query)
+        .bind(username)
+        .bind(password_hash)
+        .bind(email)
+        .fetch_one(executor)
+        .await?;
+    Ok(row.0)
 }
 
-pub async fn drop_session(session_uuid: &str) -> bool {
-    let pool = crate::db::create_pool().await.unwrap();
-    match proceed_drop_session(session_uuid, &pool).await {
-        Ok(_) => true,
-        Err(e) => {
-            log_error(&e.to_string());
-            false
-        }
-    }
+// The user row and both secret keys are written in a single transaction,
+// so a failure half way through leaves no account that can never log in
+pub async fn store_new_user_with_keys(
+    new_user: &NewUser<'_>,
+    pool: &sqlx::Pool<sqlx::Postgres>,
+) -> Result<i64, AuthError> {
+    let mut transaction = pool.begin().await?;
+    let user_id = store_new_user(
+        new_user.username,
+        new_user.password_hash,
+        new_user.email,
+        &mut transaction,
+    )
+    .await?;
+    store_secret_access_key(user_id, new_user.secret_access_key, &mut transaction).await?;
+    store_secret_refresh_key(user_id, new_user.secret_refresh_key, &mut transaction).await?;
+    transaction.commit().await?;
+    Ok(user_id)
 }
 
 pub async fn proceed_drop_session(
     session_uuid: &str,
     pool: &sqlx::Pool<sqlx::Postgres>,
 ) -> Result<(), sqlx::error::Error> {
+    let query = format!("DELETE FROM {} WHERE session_uuid = $1", SESSION_TABLE);
+    sqlx::query(&query).bind(session_uuid).execute(pool).await?;
+    Ok(())
+}
+
+pub async fn store_refresh_token(
+    refresh_token: &StoredRefreshToken,
+    pool: &sqlx::Pool<sqlx::Postgres>,
+) -> Result<(), AuthError> {
     let query = format!(
-        "DELETE FROM {} WHERE session_uuid={}",
-        SESSION_TABLE,
-        quote_string_value(session_uuid)
+        "INSERT INTO {} (token_id, family_id, session_uuid, user_id, expires_at, used_at, \
+         revoked_at) VALUES ($1, $2, $3, $4, $5, $6, $7)",
+        REFRESH_TOKEN_TABLE
     );
-    sqlx::query(&query).execute(pool).await?;
+    sqlx::query(&query)
+        .bind(&refresh_token.token_id)
+        .bind(&refresh_token.family_id)
+        .bind(&refresh_token.session_uuid)
+        .bind(refresh_token.user_id)
+        .bind(refresh_token.expires_at)
+        .bind(refresh_token.used_at)
+        .bind(refresh_token.revoked_at)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+pub async fn get_refresh_token(
+    token_id: &str,
+    pool: &sqlx::Pool<sqlx::Postgres>,
+) -> Result<StoredRefreshToken, AuthError> {
+    let query = format!(
+        "SELECT token_id, family_id, session_uuid, user_id, expires_at, used_at, revoked_at \
+         FROM {} WHERE token_id = $1",
+        REFRESH_TOKEN_TABLE
+    );
+    Ok(sqlx::query_as(&query)
+        .bind(token_id)
+        .fetch_one(pool)
+        .await?)
+}
+
+// The condition is checked by the UPDATE itself, so two concurrent refreshes can not
+// both use the same token
+pub async fn use_refresh_token(
+    token_id: &str,
+    used_at: i64,
+    pool: &sqlx::Pool<sqlx::Postgres>,
+) -> Result<bool, AuthError> {
+    let query = format!(
+        "UPDATE {} SET used_at = $1 \
+         WHERE token_id = $2 AND used_at IS NULL AND revoked_at IS NULL",
+        REFRESH_TOKEN_TABLE
+    );
+    let result = sqlx::query(&query)
+        .bind(used_at)
+        .bind(token_id)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected() == 1)
+}
+
+pub async fn revoke_refresh_token_family(
+    family_id: &str,
+    revoked_at: i64,
+    pool: &sqlx::Pool<sqlx::Postgres>,
+) -> Result<(), AuthError> {
+    let query = format!(
+        "UPDATE {} SET revoked_at = $1 WHERE family_id = $2 AND revoked_at IS NULL",
+        REFRESH_TOKEN_TABLE
+    );
+    sqlx::query(&query)
+        .bind(revoked_at)
+        .bind(family_id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+pub async fn delete_refresh_token_family(
+    family_id: &str,
+    pool: &sqlx::Pool<sqlx::Postgres>,
+) -> Result<(), AuthError> {
+    let query = format!("DELETE FROM {} WHERE family_id = $1", REFRESH_TOKEN_TABLE);
+    sqlx::query(&query).bind(family_id).execute(pool).await?;
+    Ok(())
+}
+
+pub async fn revoke_access_token(
+    jti: &str,
+    expires_at: i64,
+    pool: &sqlx::Pool<sqlx::Postgres>,
+) -> Result<(), AuthError> {
+    let query = format!(
+        "INSERT INTO {} (jti, expires_at) VALUES ($1, $2) ON CONFLICT (jti) DO NOTHING",
+        REVOKED_TOKEN_TABLE
+    );
+    sqlx::query(&query)
+        .bind(jti)
+        .bind(expires_at)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+pub async fn is_access_token_revoked(
+    jti: &str,
+    pool: &sqlx::Pool<sqlx::Postgres>,
+) -> Result<bool, AuthError> {
+    let query = format!(
+        "SELECT EXISTS (SELECT 1 FROM {} WHERE jti = $1)",
+        REVOKED_TOKEN_TABLE
+    );
+    Ok(sqlx::query_scalar(&query).bind(jti).fetch_one(pool).await?)
+}
+
+pub async fn store_signing_key(
+    signing_key: &StoredSigningKey,
+    pool: &sqlx::Pool<sqlx::Postgres>,
+) -> Result<(), AuthError> {
+    let query = format!(
+        "INSERT INTO {} (key_id, algorithm, private_key, created_at, activates_at, retires_at) \
+         VALUES ($1, $2, $3, $4, $5, $6)",
+        SIGNING_KEY_TABLE
+    );
+    sqlx::query(&query)
+        .bind(&signing_key.key_id)
+        .bind(&signing_key.algorithm)
+        .bind(&signing_key.private_key)
+        .bind(signing_key.created_at)
+        .bind(signing_key.activates_at)
+        .bind(signing_key.retires_at)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+pub async fn get_signing_keys(
+    pool: &sqlx::Pool<sqlx::Postgres>,
+) -> Result<Vec<StoredSigningKey>, AuthError> {
+    let query = format!(
+        "SELECT key_id, algorithm, private_key, created_at, activates_at, retires_at \
+         FROM {} ORDER BY created_at",
+        SIGNING_KEY_TABLE
+    );
+    Ok(sqlx::query_as(&query).fetch_all(pool).await?)
+}
+
+pub async fn set_signing_key_activation(
+    key_id: &str,
+    activates_at: i64,
+    pool: &sqlx::Pool<sqlx::Postgres>,
+) -> Result<(), AuthError> {
+    let query = format!(
+        "UPDATE {} SET activates_at = $1 WHERE key_id = $2",
+        SIGNING_KEY_TABLE
+    );
+    let result = sqlx::query(&query)
+        .bind(activates_at)
+        .bind(key_id)
+        .execute(pool)
+        .await?;
+    if result.rows_affected() == 0 {
+        return Err(AuthError::RecordNotFound);
+    }
+    Ok(())
+}
+
+pub async fn set_signing_key_retirement(
+    key_id: &str,
+    retires_at: i64,
+    pool: &sqlx::Pool<sqlx::Postgres>,
+) -> Result<(), AuthError> {
+    let query = format!(
+        "UPDATE {} SET retires_at = $1 WHERE key_id = $2",
+        SIGNING_KEY_TABLE
+    );
+    let result = sqlx::query(&query)
+        .bind(retires_at)
+        .bind(key_id)
+        .execute(pool)
+        .await?;
+    if result.rows_affected() == 0 {
+        return Err(AuthError::RecordNotFound);
+    }
+    Ok(())
+}
+
+pub async fn store_client(
+    client: &StoredClient,
+    pool: &sqlx::Pool<sqlx::Postgres>,
+) -> Result<(), AuthError> {
+    let query = format!(
+        "INSERT INTO {} (client_id, client_secret, name, created_at, redirect_uris, allowed_scopes, \
+         resource_server) VALUES ($1, $2, $3, $4, $5, $6, $7)",
+        CLIENT_TABLE
+    );
+    sqlx::query(&query)
+        .bind(&client.client_id)
+        .bind(&client.client_secret)
+        .bind(&client.name)
+        .bind(client.created_at)
+        .bind(&client.redirect_uris)
+        .bind(&client.allowed_scopes)
+        .bind(client.resource_server)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+pub async fn get_client(
+    client_id: &str,
+    pool: &sqlx::Pool<sqlx::Postgres>,
+) -> Result<StoredClient, AuthError> {
+    let query = format!(
+        "SELECT client_id, client_secret, name, created_at, redirect_uris, allowed_scopes, resource_server FROM {} WHERE client_id = $1",
+        CLIENT_TABLE
+    );
+    Ok(sqlx::query_as(&query)
+        .bind(client_id)
+        .fetch_one(pool)
+        .await?)
+}
+
+pub async fn get_clients(
+    pool: &sqlx::Pool<sqlx::Postgres>,
+) -> Result<Vec<StoredClient>, AuthError> {
+    let query = format!(
+        "SELECT client_id, client_secret, name, created_at, redirect_uris, allowed_scopes, resource_server FROM {} ORDER BY created_at",
+        CLIENT_TABLE
+    );
+    Ok(sqlx::query_as(&query).fetch_all(pool).await?)
+}
+
+pub async fn delete_client(
+    client_id: &str,
+    pool: &sqlx::Pool<sqlx::Postgres>,
+) -> Result<(), AuthError> {
+    let query = format!("DELETE FROM {} WHERE client_id = $1", CLIENT_TABLE);
+    let result = sqlx::query(&query).bind(client_id).execute(pool).await?;
+    if result.rows_affected() == 0 {
+        return Err(AuthError::RecordNotFound);
+    }
+    Ok(())
+}
+
+pub async fn get_user_roles(
+    user_id: i64,
+    pool: &sqlx::Pool<sqlx::Postgres>,
+) -> Result<Vec<String>, AuthError> {
+    let query = format!(
+        "SELECT role FROM {} WHERE user_id = $1 ORDER BY role",
+        USER_ROLE_TABLE
+    );
+    let rows: Vec<(String,)> = sqlx::query_as(&query).bind(user_id).fetch_all(pool).await?;
+    Ok(rows.into_iter().map(|row| row.0).collect())
+}
+
+pub async fn grant_user_role(
+    user_id: i64,
+    role: &str,
+    pool: &sqlx::Pool<sqlx::Postgres>,
+) -> Result<(), AuthError> {
+    let query = format!(
+        "INSERT INTO {} (user_id, role) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+        USER_ROLE_TABLE
+    );
+    sqlx::query(&query)
+        .bind(user_id)
+        .bind(role)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+pub async fn revoke_user_role(
+    user_id: i64,
+    role: &str,
+    pool: &sqlx::Pool<sqlx::Postgres>,
+) -> Result<(), AuthError> {
+    let query = format!(
+        "DELETE FROM {} WHERE user_id = $1 AND role = $2",
+        USER_ROLE_TABLE
+    );
+    let result = sqlx::query(&query)
+        .bind(user_id)
+        .bind(role)
+        .execute(pool)
+        .await?;
+    if result.rows_affected() == 0 {
+        return Err(AuthError::RecordNotFound);
+    }
+    Ok(())
+}
+
+pub async fn store_authorization_code(
+    authorization_code: &StoredAuthorizationCode,
+    pool: &sqlx::Pool<sqlx::Postgres>,
+) -> Result<(), AuthError> {
+    let query = format!(
+        "INSERT INTO {} (code, client_id, user_id, redirect_uri, scope, code_challenge, \
+         nonce, auth_time, expires_at, used_at) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)",
+        AUTHORIZATION_CODE_TABLE
+    );
+    sqlx::query(&query)
+        .bind(&authorization_code.code)
+        .bind(&authorization_code.client_id)
+        .bind(authorization_code.user_id)
+        .bind(&authorization_code.redirect_uri)
+        .bind(&authorization_code.scope)
+        .bind(&authorization_code.code_challenge)
+        .bind(&authorization_code.nonce)
+        .bind(authorization_code.auth_time)
+        .bind(authorization_code.expires_at)
+        .bind(authorization_code.used_at)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+pub async fn get_authorization_code(
+    code: &str,
+    pool: &sqlx::Pool<sqlx::Postgres>,
+) -> Result<StoredAuthorizationCode, AuthError> {
+    let query = format!(
+        "SELECT code, client_id, user_id, redirect_uri, scope, code_challenge, nonce, \
+         auth_time, expires_at, used_at FROM {} WHERE code = $1",
+        AUTHORIZATION_CODE_TABLE
+    );
+    Ok(sqlx::query_as(&query).bind(code).fetch_one(pool).await?)
+}
+
+pub async fn use_authorization_code(
+    code: &str,
+    used_at: i64,
+    pool: &sqlx::Pool<sqlx::Postgres>,
+) -> Result<bool, AuthError> {
+    let query = format!(
+        "UPDATE {} SET used_at = $1 WHERE code = $2 AND used_at IS NULL",
+        AUTHORIZATION_CODE_TABLE
+    );
+    let result = sqlx::query(&query)
+        .bind(used_at)
+        .bind(code)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected() == 1)
+}
+
+pub async fn get_consented_scope(
+    user_id: i64,
+    client_id: &str,
+    pool: &sqlx::Pool<sqlx::Postgres>,
+) -> Result<Option<String>, AuthError> {
+    let query = format!(
+        "SELECT scope FROM {} WHERE user_id = $1 AND client_id = $2",
+        CONSENT_TABLE
+    );
+    let row: Option<(String,)> = sqlx::query_as(&query)
+        .bind(user_id)
+        .bind(client_id)
+        .fetch_optional(pool)
+        .await?;
+    Ok(row.map(|row| row.0))
+}
+
+pub async fn store_consent(
+    user_id: i64,
+    client_id: &str,
+    scope: &str,
+    granted_at: i64,
+    pool: &sqlx::Pool<sqlx::Postgres>,
+) -> Result<(), AuthError> {
+    let query = format!(
+        "INSERT INTO {} (user_id, client_id, scope, granted_at) VALUES ($1, $2, $3, $4) \
+         ON CONFLICT (user_id, client_id) DO UPDATE SET scope = $3, granted_at = $4",
+        CONSENT_TABLE
+    );
+    sqlx::query(&query)
+        .bind(user_id)
+        .bind(client_id)
+        .bind(scope)
+        .bind(granted_at)
+        .execute(pool)
+        .await?;
     Ok(())
 }
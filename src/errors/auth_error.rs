@@ -0,0 +1,213 @@
+use core::fmt;
+
+use actix_web::http::StatusCode;
+use actix_web::{HttpResponse, ResponseError};
+
+use crate::errors::problem_details::{
+    ProblemDetails, PROBLEM_JSON_CONTENT_TYPE, PROBLEM_TYPE_PREFIX,
+};
+use crate::logging::log::log_error;
+
+#[derive(Debug)]
+pub enum AuthError {
+    // Client errors
+    InvalidRequestBody(String),
+    MissingCookie(&'static str),
+    MissingCredentials,
+    InvalidCredentials,
+    InvalidToken,
+    ExpiredAccessToken,
+    ExpiredRefreshToken,
+    RefreshTokenReused,
+    TokenRevoked,
+    SessionNotFound,
+    SessionExpired,
+    SessionLimitReached,
+    InsufficientPermissions(String),
+    InvalidClient,
+    InvalidRedirectUri(String),
+    InvalidScope(String),
+    UsernameTaken,
+    InvalidSubscriptionLevel(String),
+    RecordNotFound,
+    DuplicateRecord,
+
+    // Server errors, their details are logged but never sent to the client
+    PasswordHashing(String),
+    TokenEncoding(String),
+    Storage(String),
+    Upstream(String),
+    Internal(String),
+}
+
+impl AuthError {
+    // Stable, machine readable identifier of the failure
+    pub fn code(&self) -> &'static str {
+        match self {
+            AuthError::InvalidRequestBody(_) => "invalid_request_body",
+            AuthError::MissingCookie(_) => "missing_cookie",
+            AuthError::MissingCredentials => "missing_credentials",
+            AuthError::InvalidCredentials => "invalid_credentials",
+            AuthError::InvalidToken => "invalid_token",
+            AuthError::ExpiredAccessToken => "access_token_expired",
+            AuthError::ExpiredRefreshToken => "refresh_token_expired",
+            AuthError::RefreshTokenReused => "refresh_token_reused",
+            AuthError::TokenRevoked => "token_revoked",
+            AuthError::SessionNotFound => "session_not_found",
+            AuthError::SessionExpired => "session_expired",
+            AuthError::SessionLimitReached => "session_limit_reached",
+            AuthError::InsufficientPermissions(_) => "insufficient_permissions",
+            AuthError::InvalidClient => "invalid_client",
+            AuthError::InvalidRedirectUri(_) => "invalid_redirect_uri",
+            AuthError::InvalidScope(_) => "invalid_scope",
+            AuthError::UsernameTaken => "username_taken",
+            AuthError::InvalidSubscriptionLevel(_) => "invalid_subscription_level",
+            AuthError::RecordNotFound => "record_not_found",
+            AuthError::DuplicateRecord => "duplicate_record",
+            AuthError::PasswordHashing(_) => "password_hashing_failed",
+            AuthError::TokenEncoding(_) => "token_encoding_failed",
+            AuthError::Storage(_) => "storage_error",
+            AuthError::Upstream(_) => "upstream_request_failed",
+            AuthError::Internal(_) => "internal_error",
+        }
+    }
+
+    pub fn title(&self) -> &'static str {
+        match self {
+            AuthError::InvalidRequestBody(_) => "Request body is not valid",
+            AuthError::MissingCookie(_) => "Required cookie is missing",
+            AuthError::MissingCredentials => "Authentication is required",
+            AuthError::InvalidCredentials => "Invalid username or password",
+            AuthError::InvalidToken => "Token is not valid",
+            AuthError::ExpiredAccessToken => "Access token has expired",
+            AuthError::ExpiredRefreshToken => "Refresh token has expired",
+            AuthError::RefreshTokenReused => "Refresh token was already used",
+            AuthError::TokenRevoked => "Token was revoked",
+            AuthError::SessionNotFound => "Session does not exist",
+            AuthError::SessionExpired => "Session has expired",
+            AuthError::SessionLimitReached => "Too many active sessions",
+            AuthError::InsufficientPermissions(_) => "Insufficient permissions",
+            AuthError::InvalidClient => "Client authentication failed",
+            AuthError::InvalidRedirectUri(_) => "Redirect uri is not valid",
+            AuthError::InvalidScope(_) => "Scope is not valid",
+            AuthError::UsernameTaken => "Username already exists",
+            AuthError::InvalidSubscriptionLevel(_) => "Unknown subscription level",
+            AuthError::RecordNotFound => "Requested record was not found",
+            AuthError::DuplicateRecord => "Record already exists",
+            AuthError::PasswordHashing(_) => "Password could not be processed",
+            AuthError::TokenEncoding(_) => "Token could not be created",
+            AuthError::Storage(_) => "Storage is unavailable",
+            AuthError::Upstream(_) => "Upstream request failed",
+            AuthError::Internal(_) => "Internal server error",
+        }
+    }
+}
+
+impl fmt::Display for AuthError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AuthError::InvalidRequestBody(reason) => write!(f, "{}: {}", self.title(), reason),
+            AuthError::MissingCookie(name) => write!(f, "Value in cookie {} not found", name),
+            AuthError::InvalidSubscriptionLevel(level) => {
+                write!(f, "{}: {}", self.title(), level)
+            }
+            AuthError::InsufficientPermissions(requirement) => {
+                write!(f, "{}: {}", self.title(), requirement)
+            }
+            AuthError::InvalidRedirectUri(reason) | AuthError::InvalidScope(reason) => {
+                write!(f, "{}: {}", self.title(), reason)
+            }
+            AuthError::PasswordHashing(reason)
+            | AuthError::TokenEncoding(reason)
+            | AuthError::Storage(reason)
+            | AuthError::Upstream(reason)
+            | AuthError::Internal(reason) => write!(f, "{}: {}", self.title(), reason),
+            _ => write!(f, "{}", self.title()),
+        }
+    }
+}
+
+impl std::error::Error for AuthError {}
+
+impl ResponseError for AuthError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            AuthError::InvalidRequestBody(_)
+            | AuthError::MissingCookie(_)
+            | AuthError::InvalidSubscriptionLevel(_)
+            | AuthError::InvalidRedirectUri(_)
+            | AuthError::InvalidScope(_) => StatusCode::BAD_REQUEST,
+            AuthError::MissingCredentials
+            | AuthError::InvalidCredentials
+            | AuthError::InvalidToken
+            | AuthError::ExpiredAccessToken
+            | AuthError::ExpiredRefreshToken
+            | AuthError::RefreshTokenReused
+            | AuthError::TokenRevoked
+            | AuthError::SessionNotFound
+            | AuthError::SessionExpired
+            | AuthError::InvalidClient => StatusCode::UNAUTHORIZED,
+            AuthError::SessionLimitReached | AuthError::InsufficientPermissions(_) => {
+                StatusCode::FORBIDDEN
+            }
+            AuthError::RecordNotFound => StatusCode::NOT_FOUND,
+            AuthError::UsernameTaken | AuthError::DuplicateRecord => StatusCode::CONFLICT,
+            AuthError::Upstream(_) => StatusCode::BAD_GATEWAY,
+            AuthError::PasswordHashing(_)
+            | AuthError::TokenEncoding(_)
+            | AuthError::Storage(_)
+            | AuthError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let status = self.status_code();
+        let detail = if status.is_server_error() {
+            log_error(&self.to_string());
+            self.title().to_string()
+        } else {
+            self.to_string()
+        };
+
+        HttpResponse::build(status)
+            .content_type(PROBLEM_JSON_CONTENT_TYPE)
+            .json(ProblemDetails {
+                problem_type: format!("{}{}", PROBLEM_TYPE_PREFIX, self.code()),
+                title: self.title().to_string(),
+                status: status.as_u16(),
+                detail,
+                code: self.code().to_string(),
+            })
+    }
+}
+
+impl From<sqlx::Error> for AuthError {
+    fn from(error: sqlx::Error) -> Self {
+        match error {
+            sqlx::Error::RowNotFound => AuthError::RecordNotFound,
+            sqlx::Error::Database(ref database_error)
+                if database_error.code().as_deref() == Some(UNIQUE_VIOLATION_POSTGRES)
+                    || database_error.code().as_deref() == Some(UNIQUE_VIOLATION_SQLITE) =>
+            {
+                AuthError::DuplicateRecord
+            }
+            _ => AuthError::Storage(error.to_string()),
+        }
+    }
+}
+
+impl From<jsonwebtoken::errors::Error> for AuthError {
+    fn from(error: jsonwebtoken::errors::Error) -> Self {
+        AuthError::TokenEncoding(error.to_string())
+    }
+}
+
+impl From<argon2::Error> for AuthError {
+    fn from(error: argon2::Error) -> Self {
+        AuthError::PasswordHashing(error.to_string())
+    }
+}
+
+// SQLSTATE of Postgres and extended result code of SQLite reported on a UNIQUE constraint
+const UNIQUE_VIOLATION_POSTGRES: &str = "23505";
+const UNIQUE_VIOLATION_SQLITE: &str = "2067";
@@ -0,0 +1,3 @@
+pub mod auth_error;
+pub mod oauth_error;
+pub mod problem_details;
@@ -0,0 +1,110 @@
+use core::fmt;
+
+use actix_web::http::{header, StatusCode};
+use actix_web::{HttpResponse, ResponseError};
+use serde::{Deserialize, Serialize};
+
+use crate::errors::auth_error::AuthError;
+use crate::logging::log::log_error;
+
+// Error codes of RFC 6749, OAuth clients branch on them instead of on problem details
+pub const INVALID_REQUEST: &str = "invalid_request";
+pub const INVALID_CLIENT: &str = "invalid_client";
+pub const INVALID_GRANT: &str = "invalid_grant";
+pub const UNAUTHORIZED_CLIENT: &str = "unauthorized_client";
+pub const UNSUPPORTED_GRANT_TYPE: &str = "unsupported_grant_type";
+pub const UNSUPPORTED_RESPONSE_TYPE: &str = "unsupported_response_type";
+pub const INVALID_SCOPE: &str = "invalid_scope";
+pub const ACCESS_DENIED: &str = "access_denied";
+pub const SERVER_ERROR: &str = "server_error";
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OAuthErrorBody {
+    pub error: String,
+    pub error_description: String,
+}
+
+#[derive(Debug)]
+pub struct OAuthError {
+    pub error: &'static str,
+    pub description: String,
+}
+
+impl OAuthError {
+    pub fn new(error: &'static str, description: impl Into<String>) -> OAuthError {
+        OAuthError {
+            error,
+            description: description.into(),
+        }
+    }
+
+    pub fn invalid_request(description: impl Into<String>) -> OAuthError {
+        OAuthError::new(INVALID_REQUEST, description)
+    }
+
+    pub fn invalid_grant(description: impl Into<String>) -> OAuthError {
+        OAuthError::new(INVALID_GRANT, description)
+    }
+}
+
+impl fmt::Display for OAuthError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.error, self.description)
+    }
+}
+
+impl std::error::Error for OAuthError {}
+
+impl ResponseError for OAuthError {
+    fn status_code(&self) -> StatusCode {
+        match self.error {
+            INVALID_CLIENT => StatusCode::UNAUTHORIZED,
+            SERVER_ERROR => StatusCode::INTERNAL_SERVER_ERROR,
+            _ => StatusCode::BAD_REQUEST,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let mut response = HttpResponse::build(self.status_code());
+        if self.error == INVALID_CLIENT {
+            response.insert_header((header::WWW_AUTHENTICATE, "Basic"));
+        }
+        response
+            .insert_header((header::CACHE_CONTROL, "no-store"))
+            .json(OAuthErrorBody {
+                error: self.error.to_string(),
+                error_description: self.description.clone(),
+            })
+    }
+}
+
+// Failures of the shared token and session code, a token or session that does not hold up
+// is a grant the client can not use anymore
+impl From<AuthError> for OAuthError {
+    fn from(error: AuthError) -> Self {
+        match error {
+            AuthError::InvalidClient => OAuthError::new(INVALID_CLIENT, error.title()),
+            AuthError::InvalidRequestBody(_) | AuthError::MissingCredentials => {
+                OAuthError::invalid_request(error.to_string())
+            }
+            AuthError::InvalidToken
+            | AuthError::ExpiredAccessToken
+            | AuthError::ExpiredRefreshToken
+            | AuthError::RefreshTokenReused
+            | AuthError::TokenRevoked
+            | AuthError::SessionNotFound
+            | AuthError::SessionExpired
+            | AuthError::SessionLimitReached
+            | AuthError::RecordNotFound => OAuthError::invalid_grant(error.title()),
+            AuthError::InvalidScope(_) => OAuthError::new(INVALID_SCOPE, error.to_string()),
+            AuthError::InsufficientPermissions(_) => {
+                OAuthError::new(UNAUTHORIZED_CLIENT, error.to_string())
+            }
+            error if error.status_code().is_server_error() => {
+                log_error(&error.to_string());
+                OAuthError::new(SERVER_ERROR, error.title())
+            }
+            error => OAuthError::invalid_request(error.to_string()),
+        }
+    }
+}
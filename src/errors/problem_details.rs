@@ -0,0 +1,15 @@
+use serde::{Deserialize, Serialize};
+
+pub const PROBLEM_JSON_CONTENT_TYPE: &str = "application/problem+json";
+pub const PROBLEM_TYPE_PREFIX: &str = "urn:auth-server:problem:";
+
+// RFC 7807 body, `code` is an extension member clients can branch on
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ProblemDetails {
+    #[serde(rename = "type")]
+    pub problem_type: String,
+    pub title: String,
+    pub status: u16,
+    pub detail: String,
+    pub code: String,
+}
@@ -0,0 +1,207 @@
+use std::io;
+
+use actix_web::body::SizedStream;
+use actix_web::http::header::{self, HeaderName, HeaderValue};
+use actix_web::{web, HttpRequest, HttpResponse};
+use futures_util::StreamExt;
+
+use crate::auth::forward_auth::ForwardAuthConfig;
+use crate::auth::guard::authenticated_user::AuthenticatedUser;
+use crate::errors::auth_error::AuthError;
+use crate::gateway::routes::GatewayRoute;
+use crate::logging::log::log_warn;
+use crate::storage::Storage;
+
+// RFC 9110 7.6.1, they describe a single connection and are never forwarded
+const HOP_BY_HOP_HEADERS: [&str; 8] = [
+    "connection",
+    "keep-alive",
+    "proxy-authenticate",
+    "proxy-authorization",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+];
+
+// Credentials of this server, the upstream gets the identity headers instead
+const AUTH_COOKIES: [&str; 3] = ["token", "refresh_token", "session"];
+
+const REQUEST_BODY_BUFFER: usize = 16;
+
+// Headers named in `Connection` are hop-by-hop as well
+fn connection_options<'a>(values: impl Iterator<Item = &'a HeaderValue>) -> Vec<String> {
+    values
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|option| option.trim().to_ascii_lowercase())
+        .collect()
+}
+
+fn is_hop_by_hop(name: &HeaderName, connection_options: &[String]) -> bool {
+    HOP_BY_HOP_HEADERS.contains(&name.as_str())
+        || connection_options
+            .iter()
+            .any(|option| option == name.as_str())
+}
+
+fn without_auth_cookies(value: &HeaderValue) -> Option<HeaderValue> {
+    let cookies: Vec<&str> = value
+        .to_str()
+        .ok()?
+        .split(';')
+        .map(str::trim)
+        .filter(|cookie| {
+            let name = cookie.split('=').next().unwrap_or_default();
+            !cookie.is_empty() && !AUTH_COOKIES.contains(&name)
+        })
+        .collect();
+    if cookies.is_empty() {
+        return None;
+    }
+    HeaderValue::from_str(&cookies.join("; ")).ok()
+}
+
+fn is_bearer_authorization(value: &HeaderValue) -> bool {
+    value
+        .to_str()
+        .ok()
+        .and_then(|value| value.split_once(' '))
+        .is_some_and(|(scheme, _)| scheme.eq_ignore_ascii_case("Bearer"))
+}
+
+// Identity headers sent by the caller are dropped, only the gateway may set them
+fn upstream_headers(
+    req: &HttpRequest,
+    user: &AuthenticatedUser,
+    subscription_level: &str,
+    identity: &ForwardAuthConfig,
+) -> reqwest::header::HeaderMap {
+    let identity_headers = [
+        &identity.user_header,
+        &identity.user_id_header,
+        &identity.subscription_header,
+    ];
+    let connection = connection_options(req.headers().get_all(header::CONNECTION));
+    let mut headers = reqwest::header::HeaderMap::new();
+    for (name, value) in req.headers() {
+        if is_hop_by_hop(name, &connection)
+            || *name == header::HOST
+            || identity_headers.contains(&name)
+        {
+            continue;
+        }
+        if *name == header::AUTHORIZATION && is_bearer_authorization(value) {
+            continue;
+        }
+        if *name == header::COOKIE {
+            if let Some(value) = without_auth_cookies(value) {
+                headers.append(name.clone(), value);
+            }
+            continue;
+        }
+        headers.append(name.clone(), value.clone());
+    }
+
+    let identity_values = [
+        user.username.clone(),
+        user.user_id.to_string(),
+        subscription_level.to_string(),
+    ];
+    for (name, value) in identity_headers.into_iter().zip(identity_values) {
+        if let Ok(value) = HeaderValue::from_str(&value) {
+            headers.insert(name.clone(), value);
+        }
+    }
+
+    let connection_info = req.connection_info();
+    let forwarded_for = match (
+        req.headers()
+            .get("x-forwarded-for")
+            .and_then(|value| value.to_str().ok()),
+        connection_info.peer_addr(),
+    ) {
+        (Some(forwarded_for), Some(peer)) => Some(format!("{}, {}", forwarded_for, peer)),
+        (forwarded_for, peer) => forwarded_for.or(peer).map(str::to_string),
+    };
+    let forwarded = [
+        ("x-forwarded-for", forwarded_for),
+        ("x-forwarded-host", Some(connection_info.host().to_string())),
+        (
+            "x-forwarded-proto",
+            Some(connection_info.scheme().to_string()),
+        ),
+    ];
+    for (name, value) in forwarded {
+        if let Some(value) = value.and_then(|value| HeaderValue::from_str(&value).ok()) {
+            headers.insert(HeaderName::from_static(name), value);
+        }
+    }
+    headers
+}
+
+fn has_body(req: &HttpRequest) -> bool {
+    req.headers().contains_key(header::TRANSFER_ENCODING)
+        || req
+            .headers()
+            .get(header::CONTENT_LENGTH)
+            .is_some_and(|length| length != "0")
+}
+
+// The payload can not leave the worker thread, so it is handed to the client chunk by chunk
+fn streamed_body(mut payload: web::Payload) -> reqwest::Body {
+    let (sender, receiver) = tokio::sync::mpsc::channel(REQUEST_BODY_BUFFER);
+    actix_web::rt::spawn(async move {
+        while let Some(chunk) = payload.next().await {
+            let chunk = chunk.map_err(|e| io::Error::other(e.to_string()));
+            if sender.send(chunk).await.is_err() {
+                break;
+            }
+        }
+    });
+    reqwest::Body::wrap_stream(futures_util::stream::unfold(
+        receiver,
+        |mut receiver| async move { receiver.recv().await.map(|chunk| (chunk, receiver)) },
+    ))
+}
+
+// Wrapped in `RequireAuth` by the gateway, so the caller is already authenticated here
+pub async fn forward(
+    req: HttpRequest,
+    payload: web::Payload,
+    user: AuthenticatedUser,
+    route: web::Data<GatewayRoute>,
+    client: web::Data<reqwest::Client>,
+    storage: web::Data<dyn Storage>,
+    identity: web::Data<ForwardAuthConfig>,
+) -> Result<HttpResponse, AuthError> {
+    let subscription_level = storage.get_user_subscription_level(user.user_id).await?;
+    let url = route.upstream_url(req.path(), req.query_string());
+    let mut request = client
+        .request(req.method().clone(), &url)
+        .headers(upstream_headers(
+            &req,
+            &user,
+            &subscription_level,
+            &identity,
+        ));
+    if has_body(&req) {
+        request = request.body(streamed_body(payload));
+    }
+    let response = request.send().await.map_err(|e| {
+        log_warn(&format!("gateway request to {} failed: {}", url, e));
+        AuthError::Upstream(format!("{} can not be reached: {}", route.upstream, e))
+    })?;
+
+    let connection = connection_options(response.headers().get_all(header::CONNECTION).iter());
+    let mut builder = HttpResponse::build(response.status());
+    for (name, value) in response.headers() {
+        if !is_hop_by_hop(name, &connection) && *name != header::CONTENT_LENGTH {
+            builder.append_header((name.clone(), value.clone()));
+        }
+    }
+    Ok(match response.content_length() {
+        Some(length) => builder.body(SizedStream::new(length, response.bytes_stream())),
+        None => builder.streaming(response.bytes_stream()),
+    })
+}
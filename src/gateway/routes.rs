@@ -0,0 +1,111 @@
+use std::time::Duration;
+
+use actix_web::web;
+use url::Url;
+
+use crate::auth::guard::require_auth::RequireAuth;
+use crate::gateway::proxy::forward;
+use crate::startup::environment_constants::EnvironmentConstants;
+
+// Paths served by the server itself, a gateway route below them would never be reached
+const RESERVED_PREFIXES: [&str; 5] = ["/auth", "/oauth", "/ping", "/.well-known", "/xml-api"];
+
+// Requests below `prefix` are forwarded to `upstream`, with the prefix replaced by the path
+// of the upstream url
+#[derive(Clone, Debug)]
+pub struct GatewayRoute {
+    pub prefix: String,
+    pub upstream: Url,
+}
+
+impl GatewayRoute {
+    pub fn upstream_url(&self, path: &str, query: &str) -> String {
+        let rest = path.strip_prefix(self.prefix.as_str()).unwrap_or(path);
+        let mut url = format!("{}{}", self.upstream.as_str().trim_end_matches('/'), rest);
+        if !query.is_empty() {
+            url.push('?');
+            url.push_str(query);
+        }
+        url
+    }
+}
+
+fn parse_gateway_route(route: &str) -> Result<GatewayRoute, String> {
+    let invalid = |reason: &str| format!("invalid gateway route {}, {}", route, reason);
+    let (prefix, upstream) = route
+        .split_once('=')
+        .ok_or_else(|| invalid("expected /prefix=http://upstream"))?;
+    let prefix = prefix.trim().trim_end_matches('/');
+    if !prefix.starts_with('/') {
+        return Err(invalid(
+            "the prefix has to start with / and can not be / alone",
+        ));
+    }
+    if RESERVED_PREFIXES
+        .iter()
+        .any(|reserved| prefix == *reserved || prefix.starts_with(&format!("{}/", reserved)))
+    {
+        return Err(invalid("the prefix is served by the auth server itself"));
+    }
+    let upstream = Url::parse(upstream.trim()).map_err(|e| invalid(&e.to_string()))?;
+    if !matches!(upstream.scheme(), "http" | "https") {
+        return Err(invalid("the upstream has to be an http or https url"));
+    }
+    Ok(GatewayRoute {
+        prefix: prefix.to_string(),
+        upstream,
+    })
+}
+
+// Routes are `prefix=upstream` pairs separated by commas
+pub fn parse_gateway_routes(routes: &str) -> Result<Vec<GatewayRoute>, String> {
+    routes
+        .split(',')
+        .map(str::trim)
+        .filter(|route| !route.is_empty())
+        .map(parse_gateway_route)
+        .collect()
+}
+
+// Protects applications without any authentication of their own. Every route is a scope
+// that requires a valid token, the upstream gets the identity of the caller in headers.
+#[derive(Clone)]
+pub struct Gateway {
+    pub routes: Vec<GatewayRoute>,
+    client: reqwest::Client,
+}
+
+impl Gateway {
+    pub fn new(routes: Vec<GatewayRoute>, connect_timeout: Duration) -> Result<Gateway, String> {
+        // redirects are the business of the caller, the upstream may point them elsewhere
+        let client = reqwest::Client::builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .connect_timeout(connect_timeout)
+            .build()
+            .map_err(|e| e.to_string())?;
+        Ok(Gateway { routes, client })
+    }
+
+    // Invalid routes fail the startup, like the forward authentication rules
+    pub fn from_environment(env_constants: &EnvironmentConstants) -> Result<Gateway, String> {
+        Gateway::new(
+            parse_gateway_routes(&env_constants.gateway_routes)?,
+            Duration::from_secs(env_constants.gateway_connect_timeout),
+        )
+    }
+
+    // Registered after the endpoints of the server, longer prefixes first so they win
+    pub fn configure(&self, cfg: &mut web::ServiceConfig) {
+        let mut routes = self.routes.clone();
+        routes.sort_by_key(|route| std::cmp::Reverse(route.prefix.len()));
+        for route in routes {
+            cfg.service(
+                web::scope(&route.prefix.clone())
+                    .app_data(web::Data::new(route))
+                    .app_data(web::Data::new(self.client.clone()))
+                    .wrap(RequireAuth::new())
+                    .default_service(web::to(forward)),
+            );
+        }
+    }
+}
@@ -1,6 +1,10 @@
 pub mod auth;
 pub mod db;
+pub mod errors;
+pub mod gateway;
 pub mod logging;
+pub mod migrations;
 pub mod startup;
+pub mod storage;
 pub mod utils;
 pub mod xml_request;
@@ -2,13 +2,32 @@ use actix_session::SessionExt;
 use actix_web::http::KeepAlive;
 use actix_web::{dev::ServiceRequest, middleware::Logger, web, App, HttpServer};
 
-use auth_server::auth::api_requests::{login, logout, ping, refresh_token, register};
-use auth_server::logging::log::{log_error, log_info};
+use auth_server::auth::api_requests::{
+    authorize, discovery, introspect, jwks, login, logout, ping, refresh_token, register, revoke,
+    sessions, token, userinfo, verify,
+};
+use auth_server::auth::client;
+use auth_server::auth::forward_auth::ForwardAuthConfig;
+use auth_server::auth::oauth::config::OAuthConfig;
+use auth_server::auth::session::{self, SessionPolicy};
+use auth_server::auth::token::key_encryption::KeyEncryption;
+use auth_server::auth::token::keyring;
+use auth_server::auth::token::revocation::RevocationList;
+use auth_server::auth::token::signing_key::{self, load_token_signer, TokenSigner};
+use auth_server::errors::auth_error::AuthError;
+use auth_server::errors::oauth_error::OAuthError;
+use auth_server::gateway::routes::Gateway;
+use auth_server::logging::log::{log_error, log_info, log_warn};
+use auth_server::migrations;
 use auth_server::startup::environment_constants::EnvironmentConstants;
+use auth_server::storage::sqlite::{create_sqlite_pool, SqliteStorage};
+use auth_server::storage::{self, memory::MemoryStorage, postgres::PostgresStorage, Storage};
 use auth_server::xml_request;
-use clap::{App as ClapApp, Arg};
+use clap::{App as ClapApp, Arg, ArgMatches};
 use colored::*;
+use sqlx::migrate::{Migrate, Migrator};
 use std::io::Write;
+use std::sync::Arc;
 
 fn initialize_logger() {
     // Initalize env_logger
@@ -37,8 +56,8 @@ fn initialize_logger() {
         .init();
 }
 
-async fn handle_flag_arguments() {
-    let flag_matches = ClapApp::new("Authentication server")
+fn parse_flag_arguments() -> ArgMatches {
+    ClapApp::new("Authentication server")
         .arg(
             Arg::with_name("clear_database")
                 .short('c')
@@ -51,30 +70,554 @@ async fn handle_flag_arguments() {
                 .long("debug")
                 .help("registers admin user in the database"),
         )
-        .get_matches();
+        .subcommand(
+            ClapApp::new("migrate")
+                .about("manages database schema migrations")
+                .subcommand_required(true)
+                .arg_required_else_help(true)
+                .subcommand(ClapApp::new("run").about("applies all pending migrations"))
+                .subcommand(
+                    ClapApp::new("up")
+                        .about("applies pending migrations up to and including VERSION")
+                        .arg(
+                            Arg::with_name("version")
+                                .value_name("VERSION")
+                                .required(true)
+                                .takes_value(true),
+                        ),
+                )
+                .subcommand(ClapApp::new("status").about("reports applied and pending migrations")),
+        )
+        .subcommand(
+            ClapApp::new("keys")
+                .about("manages the keyring that signs access tokens")
+                .subcommand_required(true)
+                .arg_required_else_help(true)
+                .subcommand(
+                    ClapApp::new("generate")
+                        .about("generates a pending signing key, published but not used yet")
+                        .arg(
+                            Arg::with_name("algorithm")
+                                .long("algorithm")
+                                .value_name("ALGORITHM")
+                                .takes_value(true)
+                                .help("RS256, ES256 or EdDSA (default: JWT_SIGNING_ALGORITHM)"),
+                        )
+                        .arg(
+                            Arg::with_name("activate")
+                                .long("activate")
+                                .help("signs new tokens with the key right away"),
+                        ),
+                )
+                .subcommand(
+                    ClapApp::new("promote")
+                        .about("signs every new token with KEY_ID")
+                        .arg(
+                            Arg::with_name("key_id")
+                                .value_name("KEY_ID")
+                                .required(true)
+                                .takes_value(true),
+                        ),
+                )
+                .subcommand(
+                    ClapApp::new("retire")
+                        .about("stops accepting tokens signed with KEY_ID")
+                        .arg(
+                            Arg::with_name("key_id")
+                                .value_name("KEY_ID")
+                                .required(true)
+                                .takes_value(true),
+                        )
+                        .arg(
+                            Arg::with_name("at")
+                                .long("at")
+                                .value_name("UNIX_TIMESTAMP")
+                                .takes_value(true)
+                                .help("retires the key later instead of now"),
+                        ),
+                )
+                .subcommand(ClapApp::new("list").about("lists every signing key")),
+        )
+        .subcommand(
+            ClapApp::new("clients")
+                .about("manages the clients allowed to introspect tokens and to act for users")
+                .subcommand_required(true)
+                .arg_required_else_help(true)
+                .subcommand(
+                    ClapApp::new("create")
+                        .about("registers a client and prints its id and secret")
+                        .arg(
+                            Arg::with_name("name")
+                                .value_name("NAME")
+                                .required(true)
+                                .takes_value(true),
+                        )
+                        .arg(
+                            Arg::with_name("redirect_uri")
+                                .long("redirect-uri")
+                                .value_name("URI")
+                                .takes_value(true)
+                                .multiple_occurrences(true)
+                                .help("allows the authorization code flow to return to URI"),
+                        )
+                        .arg(
+                            Arg::with_name("scope")
+                                .long("scope")
+                                .value_name("SCOPE")
+                                .takes_value(true)
+                                .multiple_occurrences(true)
+                                .help("allows the client credentials grant to request SCOPE"),
+                        )
+                        .arg(
+                            Arg::with_name("resource_server")
+                                .long("resource-server")
+                                .help("allows the client to introspect tokens"),
+                        ),
+                )
+                .subcommand(
+                    ClapApp::new("delete")
+                        .about("removes the client CLIENT_ID")
+                        .arg(
+                            Arg::with_name("client_id")
+                                .value_name("CLIENT_ID")
+                                .required(true)
+                                .takes_value(true),
+                        ),
+                )
+                .subcommand(ClapApp::new("list").about("lists every client")),
+        )
+        .subcommand(
+            ClapApp::new("roles")
+                .about("manages the roles checked by guarded endpoints")
+                .subcommand_required(true)
+                .arg_required_else_help(true)
+                .subcommand(
+                    ClapApp::new("grant")
+                        .about("grants ROLE to USERNAME")
+                        .arg(
+                            Arg::with_name("username")
+                                .value_name("USERNAME")
+                                .required(true)
+                                .takes_value(true),
+                        )
+                        .arg(
+                            Arg::with_name("role")
+                                .value_name("ROLE")
+                                .required(true)
+                                .takes_value(true),
+                        ),
+                )
+                .subcommand(
+                    ClapApp::new("revoke")
+                        .about("takes ROLE away from USERNAME")
+                        .arg(
+                            Arg::with_name("username")
+                                .value_name("USERNAME")
+                                .required(true)
+                                .takes_value(true),
+                        )
+                        .arg(
+                            Arg::with_name("role")
+                                .value_name("ROLE")
+                                .required(true)
+                                .takes_value(true),
+                        ),
+                )
+                .subcommand(
+                    ClapApp::new("list")
+                        .about("lists the roles of USERNAME")
+                        .arg(
+                            Arg::with_name("username")
+                                .value_name("USERNAME")
+                                .required(true)
+                                .takes_value(true),
+                        ),
+                ),
+        )
+        .get_matches()
+}
 
-    log_info("Gathered initial flags:");
-    let clear_database_flag = flag_matches.is_present("clear_database");
-    let debug_flag = flag_matches.is_present("debug");
-    log_info(&format!("clear database:\t{}", clear_database_flag));
+async fn handle_migrate_subcommand<DB>(
+    migrate_matches: &ArgMatches,
+    pool: &sqlx::Pool<DB>,
+    migrator: &Migrator,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    DB: sqlx::Database,
+    DB::Connection: Migrate,
+{
+    match migrate_matches.subcommand() {
+        Some(("run", _)) => {
+            let applied = migrations::runner::run_pending_migrations(pool, migrator).await?;
+            log_info(&format!("Applied {} migration(s)", applied));
+        }
+        Some(("up", up_matches)) => {
+            let target_version: i64 = up_matches.value_of("version").unwrap_or_default().parse()?;
+            let applied =
+                migrations::runner::migrate_to_version(pool, migrator, target_version).await?;
+            log_info(&format!(
+                "Applied {} migration(s), schema is at version {}",
+                applied, target_version
+            ));
+        }
+        Some(("status", _)) => {
+            for status in migrations::runner::get_migration_status(pool, migrator).await? {
+                println!("{}", status);
+            }
+        }
+        _ => unreachable!("clap requires a migrate subcommand"),
+    }
+    Ok(())
+}
 
-    // Flag execution
-    if clear_database_flag {
-        match auth_server::db::clear_database().await {
-            Ok(_) => {
-                log_info("Database clear SUCCESS");
+async fn run_migrate_subcommand(
+    env_constants: &EnvironmentConstants,
+    migrate_matches: &ArgMatches,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match env_constants.storage_backend.as_str() {
+        storage::POSTGRES_BACKEND => {
+            let pool = create_database_pool(env_constants).await;
+            handle_migrate_subcommand(
+                migrate_matches,
+                &pool,
+                &migrations::runner::POSTGRES_MIGRATOR,
+            )
+            .await
+        }
+        storage::SQLITE_BACKEND => {
+            let pool = create_sqlite_database_pool(env_constants).await;
+            handle_migrate_subcommand(migrate_matches, &pool, &migrations::runner::SQLITE_MIGRATOR)
+                .await
+        }
+        backend => Err(format!("Storage backend {} has no migrations", backend).into()),
+    }
+}
+
+fn format_timestamp(timestamp: Option<i64>) -> String {
+    timestamp
+        .and_then(|timestamp| chrono::DateTime::<chrono::Utc>::from_timestamp(timestamp, 0))
+        .map(|date| date.format("%Y-%m-%d %H:%M:%S").to_string())
+        .unwrap_or_else(|| "-".to_string())
+}
+
+async fn run_keys_subcommand(
+    env_constants: &EnvironmentConstants,
+    keys_matches: &ArgMatches,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if env_constants.storage_backend == storage::MEMORY_BACKEND {
+        return Err("the memory backend does not share its keyring with a running server".into());
+    }
+    let storage = create_storage(env_constants, false).await;
+    let storage = storage.as_ref();
+
+    match keys_matches.subcommand() {
+        Some(("generate", generate_matches)) => {
+            let algorithm = signing_key::parse_asymmetric_algorithm(
+                generate_matches
+                    .value_of("algorithm")
+                    .unwrap_or(&env_constants.jwt_signing_algorithm),
+            )?;
+            let encryption = KeyEncryption::from_environment(env_constants)?;
+            let stored_key = keyring::generate_signing_key(
+                storage,
+                &encryption,
+                algorithm,
+                generate_matches.is_present("activate"),
+            )
+            .await?;
+            log_info(&format!(
+                "Generated {} signing key {}",
+                stored_key.algorithm, stored_key.key_id
+            ));
+        }
+        Some(("promote", promote_matches)) => {
+            let key_id = promote_matches.value_of("key_id").unwrap_or_default();
+            keyring::promote_signing_key(storage, key_id).await?;
+            log_info(&format!("Signing key {} signs new tokens", key_id));
+        }
+        Some(("retire", retire_matches)) => {
+            let key_id = retire_matches.value_of("key_id").unwrap_or_default();
+            let retires_at = match retire_matches.value_of("at") {
+                Some(at) => at.parse()?,
+                None => chrono::Utc::now().timestamp(),
+            };
+            keyring::retire_signing_key(storage, key_id, retires_at).await?;
+            log_info(&format!(
+                "Signing key {} retires at {}",
+                key_id,
+                format_timestamp(Some(retires_at))
+            ));
+        }
+        Some(("list", _)) => {
+            for stored_key in storage.get_signing_keys().await? {
+                println!(
+                    "{}\t{}\tcreated {}\tactive since {}\tretired at {}",
+                    stored_key.key_id,
+                    stored_key.algorithm,
+                    format_timestamp(Some(stored_key.created_at)),
+                    format_timestamp(stored_key.activates_at),
+                    format_timestamp(stored_key.retires_at)
+                );
             }
-            Err(e) => {
-                log_error(&format!("Could not clear the database.\nReason {}", e));
+        }
+        _ => unreachable!("clap requires a keys subcommand"),
+    }
+    Ok(())
+}
+
+async fn run_clients_subcommand(
+    env_constants: &EnvironmentConstants,
+    clients_matches: &ArgMatches,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if env_constants.storage_backend == storage::MEMORY_BACKEND {
+        return Err("the memory backend does not share its clients with a running server".into());
+    }
+    let storage = create_storage(env_constants, false).await;
+    let storage = storage.as_ref();
+
+    match clients_matches.subcommand() {
+        Some(("create", create_matches)) => {
+            let name = create_matches.value_of("name").unwrap_or_default();
+            let redirect_uris: Vec<String> = create_matches
+                .values_of("redirect_uri")
+                .map(|uris| uris.map(str::to_string).collect())
+                .unwrap_or_default();
+            let allowed_scopes: Vec<String> = create_matches
+                .values_of("scope")
+                .map(|scopes| scopes.map(str::to_string).collect())
+                .unwrap_or_default();
+            let resource_server = create_matches.is_present("resource_server");
+            let (stored_client, client_secret) = client::create_client(
+                storage,
+                name,
+                &redirect_uris,
+                &allowed_scopes,
+                resource_server,
+            )
+            .await?;
+            log_info(&format!("Created client {}", stored_client.name));
+            // the secret can not be recovered later, only its hash is stored
+            println!("client_id\t{}", stored_client.client_id);
+            println!("client_secret\t{}", client_secret);
+        }
+        Some(("delete", delete_matches)) => {
+            let client_id = delete_matches.value_of("client_id").unwrap_or_default();
+            storage.delete_client(client_id).await?;
+            log_info(&format!("Deleted client {}", client_id));
+        }
+        Some(("list", _)) => {
+            for stored_client in storage.get_clients().await? {
+                println!(
+                    "{}\t{}\tcreated {}\t{}\t{}{}",
+                    stored_client.client_id,
+                    stored_client.name,
+                    format_timestamp(Some(stored_client.created_at)),
+                    stored_client.redirect_uris,
+                    stored_client.allowed_scopes,
+                    if stored_client.resource_server {
+                        "\tresource server"
+                    } else {
+                        ""
+                    }
+                );
+            }
+        }
+        _ => unreachable!("clap requires a clients subcommand"),
+    }
+    Ok(())
+}
+
+async fn run_roles_subcommand(
+    env_constants: &EnvironmentConstants,
+    roles_matches: &ArgMatches,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if env_constants.storage_backend == storage::MEMORY_BACKEND {
+        return Err("the memory backend does not share its roles with a running server".into());
+    }
+    let storage = create_storage(env_constants, false).await;
+    let storage = storage.as_ref();
+
+    let (command, command_matches) = roles_matches
+        .subcommand()
+        .expect("clap requires a roles subcommand");
+    let username = command_matches.value_of("username").unwrap_or_default();
+    let user = storage.get_user(username).await?;
+    match command {
+        "grant" => {
+            let role = command_matches.value_of("role").unwrap_or_default();
+            storage.grant_user_role(user.user_id, role).await?;
+            log_info(&format!("Granted role {} to {}", role, username));
+        }
+        "revoke" => {
+            let role = command_matches.value_of("role").unwrap_or_default();
+            storage.revoke_user_role(user.user_id, role).await?;
+            log_info(&format!("Revoked role {} of {}", role, username));
+        }
+        "list" => {
+            for role in storage.get_user_roles(user.user_id).await? {
+                println!("{}", role);
+            }
+        }
+        _ => unreachable!("clap requires a roles subcommand"),
+    }
+    Ok(())
+}
+
+async fn create_database_pool(env_constants: &EnvironmentConstants) -> sqlx::Pool<sqlx::Postgres> {
+    match auth_server::db::create_pool(env_constants).await {
+        Ok(pool) => pool,
+        Err(e) => {
+            log_error(&format!("Cannot connect to the database.\nReason {}", e));
+            std::process::abort();
+        }
+    }
+}
+
+async fn create_sqlite_database_pool(
+    env_constants: &EnvironmentConstants,
+) -> sqlx::Pool<sqlx::Sqlite> {
+    match create_sqlite_pool(env_constants).await {
+        Ok(pool) => pool,
+        Err(e) => {
+            log_error(&format!("Cannot open the SQLite database.\nReason {}", e));
+            std::process::abort();
+        }
+    }
+}
+
+async fn apply_pending_migrations<DB>(pool: &sqlx::Pool<DB>, migrator: &Migrator)
+where
+    DB: sqlx::Database,
+    DB::Connection: Migrate,
+{
+    match migrations::runner::run_pending_migrations(pool, migrator).await {
+        Ok(applied) => {
+            log_info(&format!(
+                "Database schema is up to date ({} applied)",
+                applied
+            ));
+        }
+        Err(e) => {
+            log_error(&format!("Could not migrate the database.\nReason {}", e));
+            std::process::abort();
+        }
+    }
+}
+
+async fn clear_database(pool: &sqlx::Pool<sqlx::Postgres>) {
+    match auth_server::db::clear_database(pool).await {
+        Ok(_) => {
+            log_info("Database clear SUCCESS");
+        }
+        Err(e) => {
+            log_error(&format!("Could not clear the database.\nReason {}", e));
+        }
+    }
+}
+
+// Only the server start clears the database, the admin commands work on the data as it is
+async fn create_storage(env_constants: &EnvironmentConstants, clear: bool) -> Arc<dyn Storage> {
+    log_info(&format!(
+        "storage backend:\t{}",
+        env_constants.storage_backend
+    ));
+    match env_constants.storage_backend.as_str() {
+        storage::POSTGRES_BACKEND => {
+            let pool = create_database_pool(env_constants).await;
+            apply_pending_migrations(&pool, &migrations::runner::POSTGRES_MIGRATOR).await;
+            if clear {
+                clear_database(&pool).await;
+            }
+            Arc::new(PostgresStorage::new(pool))
+        }
+        storage::SQLITE_BACKEND => {
+            if clear {
+                log_warn("clear database is only supported by the postgres backend");
             }
+            let pool = create_sqlite_database_pool(env_constants).await;
+            apply_pending_migrations(&pool, &migrations::runner::SQLITE_MIGRATOR).await;
+            Arc::new(SqliteStorage::new(pool))
+        }
+        storage::MEMORY_BACKEND => {
+            log_warn("In-memory storage is used, all data is lost when the server stops");
+            Arc::new(MemoryStorage::new())
+        }
+        unknown_backend => {
+            log_error(&format!("Unknown storage backend: {}", unknown_backend));
+            std::process::abort();
         }
     }
+}
+
+fn create_forward_auth_config(env_constants: &EnvironmentConstants) -> ForwardAuthConfig {
+    match ForwardAuthConfig::from_environment(env_constants) {
+        Ok(forward_auth) => forward_auth,
+        Err(e) => {
+            log_error(&format!(
+                "Cannot configure forward authentication.\nReason {}",
+                e
+            ));
+            std::process::abort();
+        }
+    }
+}
+
+fn create_gateway(env_constants: &EnvironmentConstants) -> Gateway {
+    match Gateway::from_environment(env_constants) {
+        Ok(gateway) => {
+            for route in &gateway.routes {
+                log_info(&format!(
+                    "gateway forwards {} to {}",
+                    route.prefix, route.upstream
+                ));
+            }
+            gateway
+        }
+        Err(e) => {
+            log_error(&format!("Cannot configure the gateway.\nReason {}", e));
+            std::process::abort();
+        }
+    }
+}
+
+async fn create_token_signer(
+    env_constants: &EnvironmentConstants,
+    storage: &dyn Storage,
+) -> TokenSigner {
+    match load_token_signer(env_constants, storage).await {
+        Ok(TokenSigner::PerUserSecret) => {
+            log_info("access tokens are signed with per-user secrets");
+            TokenSigner::PerUserSecret
+        }
+        Ok(TokenSigner::Keyring(keyring)) => {
+            log_info(&format!(
+                "access tokens are signed by the keyring, {} key(s) published",
+                keyring.jwk_set().keys.len()
+            ));
+            TokenSigner::Keyring(keyring)
+        }
+        Err(e) => {
+            log_error(&format!(
+                "Cannot load the token signing keys.\nReason {}",
+                e
+            ));
+            std::process::abort();
+        }
+    }
+}
 
+async fn handle_flag_arguments(flag_matches: &ArgMatches, storage: &dyn Storage) {
+    log_info("Gathered initial flags:");
+    let clear_database_flag = flag_matches.is_present("clear_database");
+    let debug_flag = flag_matches.is_present("debug");
+    log_info(&format!("clear database:\t{}", clear_database_flag));
+
+    // Flag execution
     if debug_flag {
         const ADMIN_LOGIN: &str = "Zenek";
         const ADMIN_PASSWORD: &str = "Super";
         const ADMIN_EMAIL: &str = "lol@kekw.com";
-        match register::create_user(ADMIN_LOGIN, ADMIN_PASSWORD, ADMIN_EMAIL).await {
+        match register::create_user(ADMIN_LOGIN, ADMIN_PASSWORD, ADMIN_EMAIL, storage).await {
             Ok(_) => {
                 log_info(&format!(
                     "User {} was created with password: {}",
@@ -97,11 +640,57 @@ fn send_server_is_ready_event(env_constants: &EnvironmentConstants) {
     ));
 }
 
-async fn perform_startup_sequence(env_constants: &EnvironmentConstants) {
-    initialize_logger();
-    auth_server::db::check_database_connection().await.unwrap();
-    handle_flag_arguments().await;
+async fn perform_startup_sequence(
+    env_constants: &EnvironmentConstants,
+    flag_matches: &ArgMatches,
+) -> (Arc<dyn Storage>, TokenSigner) {
+    let storage = create_storage(env_constants, flag_matches.is_present("clear_database")).await;
+    handle_flag_arguments(flag_matches, storage.as_ref()).await;
+    let signer = create_token_signer(env_constants, storage.as_ref()).await;
     send_server_is_ready_event(env_constants);
+    (storage, signer)
+}
+
+fn spawn_keyring_refresh(
+    env_constants: &EnvironmentConstants,
+    signer: web::Data<TokenSigner>,
+    storage: web::Data<dyn Storage>,
+) {
+    let interval = match env_constants.keyring_refresh_interval {
+        0 => {
+            log_warn(&format!(
+                "keyring refresh interval 0 is not positive, {} is used instead",
+                keyring::DEFAULT_REFRESH_INTERVAL
+            ));
+            keyring::DEFAULT_REFRESH_INTERVAL
+        }
+        interval => interval,
+    };
+    let period = std::time::Duration::from_secs(interval);
+    actix_rt::spawn(async move {
+        if let TokenSigner::Keyring(keyring) = signer.get_ref() {
+            keyring
+                .refresh_periodically(storage.get_ref(), period)
+                .await;
+        }
+    });
+}
+
+fn spawn_session_sweeper(env_constants: &EnvironmentConstants, storage: web::Data<dyn Storage>) {
+    let interval = match env_constants.session_sweep_interval {
+        0 => {
+            log_warn(&format!(
+                "session sweep interval 0 is not positive, {} is used instead",
+                session::DEFAULT_SESSION_SWEEP_INTERVAL
+            ));
+            session::DEFAULT_SESSION_SWEEP_INTERVAL
+        }
+        interval => interval,
+    };
+    let period = std::time::Duration::from_secs(interval);
+    actix_rt::spawn(async move {
+        session::sweep_expired_sessions_periodically(storage.get_ref(), period).await;
+    });
 }
 
 async fn index() -> actix_web::HttpResponse {
@@ -116,11 +705,62 @@ async fn main() -> std::io::Result<()> {
     let environment_constants: EnvironmentConstants =
         auth_server::startup::environment_constants::get_environment_constants();
 
-    unsafe {
-        auth_server::db::ENVIRONMENT_CONSTANTS = Some(environment_constants.clone());
+    initialize_logger();
+    let flag_matches = parse_flag_arguments();
+
+    // the flags prepare the server start, an admin command must never clear the database
+    if let Some((command, _)) = flag_matches.subcommand() {
+        if flag_matches.is_present("clear_database") || flag_matches.is_present("debug") {
+            log_error(&format!(
+                "--clear_database and --debug can not be combined with the {} command",
+                command
+            ));
+            std::process::exit(2);
+        }
     }
 
-    perform_startup_sequence(&environment_constants).await;
+    if let Some(("migrate", migrate_matches)) = flag_matches.subcommand() {
+        if let Err(e) = run_migrate_subcommand(&environment_constants, migrate_matches).await {
+            log_error(&format!("Migration command failed.\nReason {}", e));
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    if let Some(("keys", keys_matches)) = flag_matches.subcommand() {
+        if let Err(e) = run_keys_subcommand(&environment_constants, keys_matches).await {
+            log_error(&format!("Keys command failed.\nReason {}", e));
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    if let Some(("clients", clients_matches)) = flag_matches.subcommand() {
+        if let Err(e) = run_clients_subcommand(&environment_constants, clients_matches).await {
+            log_error(&format!("Clients command failed.\nReason {}", e));
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    if let Some(("roles", roles_matches)) = flag_matches.subcommand() {
+        if let Err(e) = run_roles_subcommand(&environment_constants, roles_matches).await {
+            log_error(&format!("Roles command failed.\nReason {}", e));
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    let (storage, signer) = perform_startup_sequence(&environment_constants, &flag_matches).await;
+    let storage: web::Data<dyn Storage> = web::Data::from(storage);
+    let signer = web::Data::new(signer);
+    let session_policy = web::Data::new(SessionPolicy::from_environment(&environment_constants));
+    let revocations = web::Data::new(RevocationList::from_environment(&environment_constants));
+    let forward_auth = web::Data::new(create_forward_auth_config(&environment_constants));
+    let oauth = web::Data::new(OAuthConfig::from_environment(&environment_constants));
+    let gateway = create_gateway(&environment_constants);
+    spawn_keyring_refresh(&environment_constants, signer.clone(), storage.clone());
+    spawn_session_sweeper(&environment_constants, storage.clone());
 
     let limiter = web::Data::new(
         actix_limitation::Limiter::builder("redis://127.0.0.1")
@@ -140,19 +780,68 @@ async fn main() -> std::io::Result<()> {
             .wrap(Logger::new("request acquired:\tIP:\t\t%a\nREQUEST TYPE:\t%r\nPROCESS TIME:\t%T\nURL:\t\t%U\nRESPONSE:\t%s\nAGENT:\t\t%{User-Agent}i"))
             .wrap(actix_limitation::RateLimiter::default())
             .app_data(limiter.clone())
+            .app_data(storage.clone())
+            .app_data(signer.clone())
+            .app_data(session_policy.clone())
+            .app_data(revocations.clone())
+            .app_data(forward_auth.clone())
+            .app_data(oauth.clone())
+            .app_data(web::JsonConfig::default().error_handler(|e, _req| {
+                AuthError::InvalidRequestBody(e.to_string()).into()
+            }))
+            .app_data(web::FormConfig::default().error_handler(|e, _req| {
+                AuthError::InvalidRequestBody(e.to_string()).into()
+            }))
+            // path parameters are record ids, one that does not parse can not exist
+            .app_data(
+                web::PathConfig::default().error_handler(|_e, _req| AuthError::RecordNotFound.into()),
+            )
             .route("/ping", web::get().to(ping::ping))
             .route("/", web::get().to(index))
+            .route("/.well-known/jwks.json", web::get().to(jwks::jwks))
+            .route(
+                "/.well-known/openid-configuration",
+                web::get().to(discovery::openid_configuration),
+            )
             .service(
                 web::scope("/auth")
                     .route("/register", web::post().to(register::register))
                     .route("/login", web::post().to(login::login))
                     .route("/logout", web::get().to(logout::logout))
-                    .route("/refresh", web::post().to(refresh_token::refresh_token)),
+                    .route("/refresh", web::post().to(refresh_token::refresh_token))
+                    .route("/revoke", web::post().to(revoke::revoke))
+                    .route("/introspect", web::post().to(introspect::introspect))
+                    .route("/verify", web::get().to(verify::verify))
+                    .route("/sessions", web::get().to(sessions::list_sessions))
+                    .route(
+                        "/sessions/revoke-others",
+                        web::post().to(sessions::revoke_other_sessions),
+                    )
+                    .route(
+                        "/sessions/{session_id}",
+                        web::delete().to(sessions::revoke_session),
+                    ),
+            )
+            // OAuth clients expect the errors of RFC 6749 instead of problem details
+            .service(
+                web::scope("/oauth")
+                    .app_data(web::QueryConfig::default().error_handler(|e, _req| {
+                        OAuthError::invalid_request(e.to_string()).into()
+                    }))
+                    .app_data(web::FormConfig::default().error_handler(|e, _req| {
+                        OAuthError::invalid_request(e.to_string()).into()
+                    }))
+                    .route("/authorize", web::get().to(authorize::authorize))
+                    .route("/authorize", web::post().to(authorize::authorize_consent))
+                    .route("/token", web::post().to(token::token))
+                    .route("/userinfo", web::get().to(userinfo::userinfo))
+                    .route("/userinfo", web::post().to(userinfo::userinfo)),
             )
             .service(web::scope("/xml-api").route(
                 "/send_xml",
                 web::post().to(xml_request::xml_private_api::send_xml),
             ))
+            .configure(|cfg| gateway.configure(cfg))
     })
     .keep_alive(KeepAlive::Timeout(std::time::Duration::from_secs(
         environment_constants.connection_timeout,
@@ -0,0 +1,12 @@
+use core::fmt;
+
+#[derive(Debug)]
+pub struct UnknownMigrationVersion(pub i64);
+
+impl fmt::Display for UnknownMigrationVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "There is no migration with version {}", self.0)
+    }
+}
+
+impl std::error::Error for UnknownMigrationVersion {}
@@ -0,0 +1,158 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+use sqlx::migrate::{Migrate, MigrateError, Migrator};
+
+use crate::logging::log::log_info;
+use crate::migrations::errors::UnknownMigrationVersion;
+use crate::migrations::status::{MigrationState, MigrationStatus};
+
+pub static POSTGRES_MIGRATOR: Migrator = sqlx::migrate!("./migrations/postgres");
+pub static SQLITE_MIGRATOR: Migrator = sqlx::migrate!("./migrations/sqlite");
+
+pub fn latest_migration_version(migrator: &Migrator) -> Option<i64> {
+    migrator.iter().map(|migration| migration.version).max()
+}
+
+async fn list_applied_checksums<C: Migrate + ?Sized>(
+    conn: &mut C,
+) -> Result<HashMap<i64, Cow<'static, [u8]>>, MigrateError> {
+    conn.ensure_migrations_table().await?;
+    Ok(conn
+        .list_applied_migrations()
+        .await?
+        .into_iter()
+        .map(|applied| (applied.version, applied.checksum))
+        .collect())
+}
+
+async fn apply_migrations_locked<C: Migrate + ?Sized>(
+    conn: &mut C,
+    migrator: &Migrator,
+    target_version: i64,
+) -> Result<usize, MigrateError> {
+    if let Some(version) = conn.dirty_version().await? {
+        return Err(MigrateError::Dirty(version));
+    }
+
+    let applied_checksums = list_applied_checksums(conn).await?;
+    if let Some(version) = applied_checksums
+        .keys()
+        .find(|version| !migrator.iter().any(|m| m.version == **version))
+    {
+        return Err(MigrateError::VersionMissing(*version));
+    }
+
+    let mut applied_count = 0;
+    for migration in migrator
+        .iter()
+        .filter(|m| !m.migration_type.is_down_migration() && m.version <= target_version)
+    {
+        match applied_checksums.get(&migration.version) {
+            Some(checksum) if *checksum != migration.checksum => {
+                return Err(MigrateError::VersionMismatch(migration.version));
+            }
+            Some(_) => {}
+            None => {
+                log_info(&format!(
+                    "applying migration {} ({})",
+                    migration.version, migration.description
+                ));
+                conn.apply(migration).await?;
+                applied_count += 1;
+            }
+        }
+    }
+    Ok(applied_count)
+}
+
+async fn apply_migrations_up_to<DB>(
+    pool: &sqlx::Pool<DB>,
+    migrator: &Migrator,
+    target_version: i64,
+) -> Result<usize, Box<dyn std::error::Error>>
+where
+    DB: sqlx::Database,
+    DB::Connection: Migrate,
+{
+    let mut conn = pool.acquire().await?;
+
+    // database wide lock, so replicas starting at the same time do not race each other
+    conn.ensure_migrations_table().await?;
+    conn.lock().await?;
+    let result = apply_migrations_locked(&mut *conn, migrator, target_version).await;
+    conn.unlock().await?;
+
+    Ok(result?)
+}
+
+pub async fn run_pending_migrations<DB>(
+    pool: &sqlx::Pool<DB>,
+    migrator: &Migrator,
+) -> Result<usize, Box<dyn std::error::Error>>
+where
+    DB: sqlx::Database,
+    DB::Connection: Migrate,
+{
+    match latest_migration_version(migrator) {
+        Some(latest_version) => apply_migrations_up_to(pool, migrator, latest_version).await,
+        None => Ok(0),
+    }
+}
+
+pub async fn migrate_to_version<DB>(
+    pool: &sqlx::Pool<DB>,
+    migrator: &Migrator,
+    target_version: i64,
+) -> Result<usize, Box<dyn std::error::Error>>
+where
+    DB: sqlx::Database,
+    DB::Connection: Migrate,
+{
+    if !migrator.iter().any(|m| m.version == target_version) {
+        return Err(Box::new(UnknownMigrationVersion(target_version)));
+    }
+    apply_migrations_up_to(pool, migrator, target_version).await
+}
+
+pub async fn get_migration_status<DB>(
+    pool: &sqlx::Pool<DB>,
+    migrator: &Migrator,
+) -> Result<Vec<MigrationStatus>, Box<dyn std::error::Error>>
+where
+    DB: sqlx::Database,
+    DB::Connection: Migrate,
+{
+    let mut conn = pool.acquire().await?;
+    let applied_checksums = list_applied_checksums(&mut *conn).await?;
+
+    let mut statuses: Vec<MigrationStatus> = migrator
+        .iter()
+        .filter(|m| !m.migration_type.is_down_migration())
+        .map(|migration| {
+            let state = match applied_checksums.get(&migration.version) {
+                Some(checksum) if *checksum == migration.checksum => MigrationState::Applied,
+                Some(_) => MigrationState::Modified,
+                None => MigrationState::Pending,
+            };
+            MigrationStatus {
+                version: migration.version,
+                description: migration.description.to_string(),
+                state,
+            }
+        })
+        .collect();
+
+    statuses.extend(
+        applied_checksums
+            .keys()
+            .filter(|version| !migrator.iter().any(|m| m.version == **version))
+            .map(|version| MigrationStatus {
+                version: *version,
+                description: String::new(),
+                state: MigrationState::Missing,
+            }),
+    );
+    statuses.sort_by_key(|status| status.version);
+    Ok(statuses)
+}
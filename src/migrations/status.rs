@@ -0,0 +1,40 @@
+use core::fmt;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum MigrationState {
+    Applied,
+    Pending,
+    // Applied to the database, but the embedded SQL has changed since
+    Modified,
+    // Applied to the database, but unknown to this build
+    Missing,
+}
+
+#[derive(Debug)]
+pub struct MigrationStatus {
+    pub version: i64,
+    pub description: String,
+    pub state: MigrationState,
+}
+
+impl fmt::Display for MigrationState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let state = match self {
+            MigrationState::Applied => "applied",
+            MigrationState::Pending => "pending",
+            MigrationState::Modified => "MODIFIED",
+            MigrationState::Missing => "MISSING",
+        };
+        write!(f, "{}", state)
+    }
+}
+
+impl fmt::Display for MigrationStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:>4}\t{:<8}\t{}",
+            self.version, self.state, self.description
+        )
+    }
+}
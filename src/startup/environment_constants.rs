@@ -5,11 +5,43 @@ pub const DEFAULT_DATABASE_ADDRESS: &str = "127.0.0.1";
 pub struct EnvironmentConstants {
     pub address: String,
     pub port: String,
+    pub storage_backend: String,
+    pub sqlite_database_path: String,
     pub database_address: String,
     pub database_port: String,
     pub database_name: String,
     pub database_user: String,
     pub database_password: String,
+    pub database_max_connections: u32,
+    pub database_min_connections: u32,
+    pub database_acquire_timeout: u64,
+    pub database_idle_timeout: u64,
+    pub jwt_signing_algorithm: String,
+    pub jwt_private_key_path: String,
+    pub jwt_key_id: String,
+    pub keyring_refresh_interval: u64,
+    pub signing_key_encryption_key: String,
+    pub signing_keys_unencrypted: bool,
+    pub session_idle_timeout: i64,
+    pub session_absolute_lifetime: i64,
+    pub session_sweep_interval: u64,
+    pub session_limit_non_premium: usize,
+    pub session_limit_basic_premium: usize,
+    pub session_limit_normal_premium: usize,
+    pub session_limit_enterprise_premium: usize,
+    pub session_limit_policy: String,
+    pub revocation_cache_ttl: i64,
+    pub forward_auth_user_header: String,
+    pub forward_auth_user_id_header: String,
+    pub forward_auth_subscription_header: String,
+    pub forward_auth_login_url: String,
+    pub forward_auth_path_rules: String,
+    pub gateway_routes: String,
+    pub gateway_connect_timeout: u64,
+    pub oauth_login_url: String,
+    pub oauth_code_lifetime: i64,
+    pub oauth_client_token_lifetime: i64,
+    pub oidc_issuer: String,
     pub request_throttling_limit: usize,
     pub connection_timeout: u64,
     pub client_timeout: u64,
@@ -20,6 +52,10 @@ pub fn get_environment_constants() -> EnvironmentConstants {
     let address =
         std::env::var("AUTH_SERVER_ADDRESS").unwrap_or_else(|_| DEFAULT_SERVER_ADDRESS.to_string());
     let port = std::env::var("AUTH_SERVER_PORT").unwrap_or_else(|_| "8080".to_string());
+    let storage_backend = std::env::var("STORAGE_BACKEND")
+        .unwrap_or_else(|_| crate::storage::POSTGRES_BACKEND.to_string());
+    let sqlite_database_path =
+        std::env::var("SQLITE_DATABASE_PATH").unwrap_or_else(|_| "auth_server.db".to_string());
     let database_address =
         std::env::var("DATABASE_ADDRESS").unwrap_or_else(|_| DEFAULT_DATABASE_ADDRESS.to_string());
     let database_user = std::env::var("DATABASE_USER").unwrap_or_else(|_| "Jasiu".to_string());
@@ -29,6 +65,109 @@ pub fn get_environment_constants() -> EnvironmentConstants {
     let database_name =
         std::env::var("DATABASE_NAME").unwrap_or_else(|_| "user_database".to_string());
 
+    // Connection pool shared by every worker
+    let database_max_connections: u32 = std::env::var("DATABASE_MAX_CONNECTIONS")
+        .unwrap_or_else(|_| "10".to_string())
+        .parse()
+        .unwrap_or(10);
+    let database_min_connections: u32 = std::env::var("DATABASE_MIN_CONNECTIONS")
+        .unwrap_or_else(|_| "0".to_string())
+        .parse()
+        .unwrap_or(0);
+    let database_acquire_timeout: u64 = std::env::var("DATABASE_ACQUIRE_TIMEOUT")
+        .unwrap_or_else(|_| "30".to_string())
+        .parse()
+        .unwrap_or(30);
+    let database_idle_timeout: u64 = std::env::var("DATABASE_IDLE_TIMEOUT")
+        .unwrap_or_else(|_| "600".to_string())
+        .parse()
+        .unwrap_or(600);
+
+    // Access token signing, HS256 keeps the per-user secrets
+    let jwt_signing_algorithm =
+        std::env::var("JWT_SIGNING_ALGORITHM").unwrap_or_else(|_| "HS256".to_string());
+    let jwt_private_key_path = std::env::var("JWT_PRIVATE_KEY_PATH").unwrap_or_default();
+    let jwt_key_id = std::env::var("JWT_KEY_ID").unwrap_or_default();
+    let keyring_refresh_interval: u64 = std::env::var("KEYRING_REFRESH_INTERVAL")
+        .unwrap_or_else(|_| "60".to_string())
+        .parse()
+        .unwrap_or(60);
+    // Private keys of the keyring are encrypted at rest, plaintext has to be asked for
+    let signing_key_encryption_key =
+        std::env::var("SIGNING_KEY_ENCRYPTION_KEY").unwrap_or_default();
+    let signing_keys_unencrypted =
+        std::env::var("SIGNING_KEYS_UNENCRYPTED").is_ok_and(|value| value == "true");
+
+    // Session lifetime, both timeouts are in seconds
+    let session_idle_timeout: i64 = std::env::var("SESSION_IDLE_TIMEOUT")
+        .unwrap_or_else(|_| "86400".to_string())
+        .parse()
+        .unwrap_or(86400);
+    let session_absolute_lifetime: i64 = std::env::var("SESSION_ABSOLUTE_LIFETIME")
+        .unwrap_or_else(|_| "2592000".to_string())
+        .parse()
+        .unwrap_or(2592000);
+    let session_sweep_interval: u64 = std::env::var("SESSION_SWEEP_INTERVAL")
+        .unwrap_or_else(|_| "600".to_string())
+        .parse()
+        .unwrap_or(600);
+
+    // Concurrent sessions per user of each subscription tier, 0 means unlimited
+    let session_limit_non_premium: usize = std::env::var("SESSION_LIMIT_NON_PREMIUM")
+        .unwrap_or_else(|_| "0".to_string())
+        .parse()
+        .unwrap_or(0);
+    let session_limit_basic_premium: usize = std::env::var("SESSION_LIMIT_BASIC_PREMIUM")
+        .unwrap_or_else(|_| "0".to_string())
+        .parse()
+        .unwrap_or(0);
+    let session_limit_normal_premium: usize = std::env::var("SESSION_LIMIT_NORMAL_PREMIUM")
+        .unwrap_or_else(|_| "0".to_string())
+        .parse()
+        .unwrap_or(0);
+    let session_limit_enterprise_premium: usize = std::env::var("SESSION_LIMIT_ENTERPRISE_PREMIUM")
+        .unwrap_or_else(|_| "0".to_string())
+        .parse()
+        .unwrap_or(0);
+    let session_limit_policy =
+        std::env::var("SESSION_LIMIT_POLICY").unwrap_or_else(|_| "reject".to_string());
+
+    // Seconds a token that is not revoked is trusted without asking the storage again
+    let revocation_cache_ttl: i64 = std::env::var("REVOCATION_CACHE_TTL")
+        .unwrap_or_else(|_| "30".to_string())
+        .parse()
+        .unwrap_or(30);
+
+    // Forward authentication for reverse proxies, the rules are `prefix=level` pairs
+    // separated by commas
+    let forward_auth_user_header =
+        std::env::var("FORWARD_AUTH_USER_HEADER").unwrap_or_else(|_| "X-Auth-User".to_string());
+    let forward_auth_user_id_header = std::env::var("FORWARD_AUTH_USER_ID_HEADER")
+        .unwrap_or_else(|_| "X-Auth-User-Id".to_string());
+    let forward_auth_subscription_header = std::env::var("FORWARD_AUTH_SUBSCRIPTION_HEADER")
+        .unwrap_or_else(|_| "X-Auth-Subscription".to_string());
+    let forward_auth_login_url = std::env::var("FORWARD_AUTH_LOGIN_URL").unwrap_or_default();
+    let forward_auth_path_rules = std::env::var("FORWARD_AUTH_PATH_RULES").unwrap_or_default();
+
+    // Gateway mode, `prefix=upstream` pairs separated by commas
+    let gateway_routes = std::env::var("GATEWAY_ROUTES").unwrap_or_default();
+    let gateway_connect_timeout: u64 = std::env::var("GATEWAY_CONNECT_TIMEOUT")
+        .unwrap_or_else(|_| "10".to_string())
+        .parse()
+        .unwrap_or(10);
+
+    // OAuth authorization server, users without a session are sent to the login url
+    let oauth_login_url = std::env::var("OAUTH_LOGIN_URL").unwrap_or_default();
+    let oauth_code_lifetime: i64 = std::env::var("OAUTH_CODE_LIFETIME")
+        .unwrap_or_else(|_| "60".to_string())
+        .parse()
+        .unwrap_or(60);
+    let oauth_client_token_lifetime: i64 = std::env::var("OAUTH_CLIENT_TOKEN_LIFETIME")
+        .unwrap_or_else(|_| "300".to_string())
+        .parse()
+        .unwrap_or(300);
+    let oidc_issuer = std::env::var("OIDC_ISSUER").unwrap_or_default();
+
     // Rate limiting / Synchronous request prevention
     let request_throttling_limit: usize = std::env::var("REQUEST_THROTTLING_LIMIT")
         .unwrap_or_else(|_| "100".to_string())
@@ -51,11 +190,43 @@ pub fn get_environment_constants() -> EnvironmentConstants {
     EnvironmentConstants {
         address,
         port,
+        storage_backend,
+        sqlite_database_path,
         database_address,
         database_port,
         database_name,
         database_user,
         database_password,
+        database_max_connections,
+        database_min_connections,
+        database_acquire_timeout,
+        database_idle_timeout,
+        jwt_signing_algorithm,
+        jwt_private_key_path,
+        jwt_key_id,
+        keyring_refresh_interval,
+        signing_key_encryption_key,
+        signing_keys_unencrypted,
+        session_idle_timeout,
+        session_absolute_lifetime,
+        session_sweep_interval,
+        session_limit_non_premium,
+        session_limit_basic_premium,
+        session_limit_normal_premium,
+        session_limit_enterprise_premium,
+        session_limit_policy,
+        revocation_cache_ttl,
+        forward_auth_user_header,
+        forward_auth_user_id_header,
+        forward_auth_subscription_header,
+        forward_auth_login_url,
+        forward_auth_path_rules,
+        gateway_routes,
+        gateway_connect_timeout,
+        oauth_login_url,
+        oauth_code_lifetime,
+        oauth_client_token_lifetime,
+        oidc_issuer,
         request_throttling_limit,
         connection_timeout,
         client_timeout,
@@ -0,0 +1,516 @@
+use std::collections::HashMap;
+use std::sync::{Mutex, MutexGuard};
+
+use async_trait::async_trait;
+use uuid::Uuid;
+
+use crate::auth::user::UserInfo;
+use crate::errors::auth_error::AuthError;
+use crate::storage::{
+    ClientStore, KeyStore, NewSession, NewUser, SessionStore, StoredAuthorizationCode,
+    StoredClient, StoredRefreshToken, StoredSession, StoredSigningKey, UserStore,
+    DEFAULT_SUBSCRIPTION_LEVEL, SUBSCRIPTION_LEVELS,
+};
+
+struct StoredUser {
+    username: String,
+    password: String,
+    email: String,
+    subscription: String,
+    roles: Vec<String>,
+}
+
+#[derive(Default)]
+struct MemoryState {
+    users: HashMap<i64, StoredUser>,
+    last_user_id: i64,
+    sessions: HashMap<String, StoredSession>,
+    last_session_id: i64,
+    secret_access_keys: HashMap<i64, String>,
+    secret_refresh_keys: HashMap<i64, String>,
+    signing_keys: Vec<StoredSigningKey>,
+    refresh_tokens: HashMap<String, StoredRefreshToken>,
+    revoked_tokens: HashMap<String, i64>,
+    clients: Vec<StoredClient>,
+    authorization_codes: HashMap<String, StoredAuthorizationCode>,
+    consents: HashMap<(i64, String), String>,
+}
+
+// Keeps everything in the process memory, nothing survives a restart
+#[derive(Default)]
+pub struct MemoryStorage {
+    state: Mutex<MemoryState>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, MemoryState> {
+        // the state is never left half-modified, so a poisoned lock is still usable
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl MemoryState {
+    fn to_user_info(&self, user_id: i64) -> Option<UserInfo> {
+        self.users.get(&user_id).map(|user| UserInfo {
+            user_id,
+            username: user.username.clone(),
+            password: user.password.clone(),
+            email: user.email.clone(),
+        })
+    }
+}
+
+#[async_trait]
+impl UserStore for MemoryStorage {
+    async fn does_user_id_exists(&self, user_id: i64) -> Result<bool, AuthError> {
+        Ok(self.lock().users.contains_key(&user_id))
+    }
+
+    async fn does_username_exists(&self, username: &str) -> Result<bool, AuthError> {
+        Ok(self
+            .lock()
+            .users
+            .values()
+            .any(|user| user.username == username))
+    }
+
+    async fn get_user(&self, username: &str) -> Result<UserInfo, AuthError> {
+        let state = self.lock();
+        state
+            .users
+            .iter()
+            .find(|(_, user)| user.username == username)
+            .and_then(|(user_id, _)| state.to_user_info(*user_id))
+            .ok_or(AuthError::RecordNotFound)
+    }
+
+    async fn get_user_with_user_id(&self, user_id: i64) -> Result<UserInfo, AuthError> {
+        match self.lock().to_user_info(user_id) {
+            Some(user) => Ok(user),
+            None => Err(AuthError::RecordNotFound),
+        }
+    }
+
+    async fn store_new_user(&self, new_user: &NewUser<'_>) -> Result<i64, AuthError> {
+        // every check happens before the first write, the lock makes the whole insert atomic
+        let mut state = self.lock();
+        if state
+            .users
+            .values()
+            .any(|user| user.username == new_user.username)
+        {
+            return Err(AuthError::DuplicateRecord);
+        }
+
+        state.last_user_id += 1;
+        let user_id = state.last_user_id;
+        state.users.insert(
+            user_id,
+            StoredUser {
+                username: new_user.username.to_string(),
+                password: new_user.password_hash.to_string(),
+                email: new_user.email.to_string(),
+                subscription: DEFAULT_SUBSCRIPTION_LEVEL.to_string(),
+                roles: Vec::new(),
+            },
+        );
+        state
+            .secret_access_keys
+            .insert(user_id, new_user.secret_access_key.to_string());
+        state
+            .secret_refresh_keys
+            .insert(user_id, new_user.secret_refresh_key.to_string());
+        Ok(user_id)
+    }
+
+    async fn set_user_subscription_level(
+        &self,
+        user_id: i64,
+        level: &str,
+    ) -> Result<(), AuthError> {
+        if !SUBSCRIPTION_LEVELS.contains(&level) {
+            return Err(AuthError::InvalidSubscriptionLevel(level.to_string()));
+        }
+        if let Some(user) = self.lock().users.get_mut(&user_id) {
+            user.subscription = level.to_string();
+        }
+        Ok(())
+    }
+
+    async fn get_user_subscription_level(&self, user_id: i64) -> Result<String, AuthError> {
+        match self.lock().users.get(&user_id) {
+            Some(user) => Ok(user.subscription.clone()),
+            None => Err(AuthError::RecordNotFound),
+        }
+    }
+
+    async fn get_user_roles(&self, user_id: i64) -> Result<Vec<String>, AuthError> {
+        match self.lock().users.get(&user_id) {
+            Some(user) => Ok(user.roles.clone()),
+            None => Err(AuthError::RecordNotFound),
+        }
+    }
+
+    async fn grant_user_role(&self, user_id: i64, role: &str) -> Result<(), AuthError> {
+        let mut state = self.lock();
+        let user = state
+            .users
+            .get_mut(&user_id)
+            .ok_or(AuthError::RecordNotFound)?;
+        if !user.roles.iter().any(|granted| granted == role) {
+            user.roles.push(role.to_string());
+            user.roles.sort();
+        }
+        Ok(())
+    }
+
+    async fn revoke_user_role(&self, user_id: i64, role: &str) -> Result<(), AuthError> {
+        let mut state = self.lock();
+        let user = state
+            .users
+            .get_mut(&user_id)
+            .ok_or(AuthError::RecordNotFound)?;
+        let role_count = user.roles.len();
+        user.roles.retain(|granted| granted != role);
+        if user.roles.len() == role_count {
+            return Err(AuthError::RecordNotFound);
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl SessionStore for MemoryStorage {
+    async fn create_session(&self, new_session: &NewSession<'_>) -> Result<String, AuthError> {
+        let session_uuid = Uuid::new_v4().to_string();
+        let mut state = self.lock();
+        state.last_session_id += 1;
+        let session_id = state.last_session_id;
+        state.sessions.insert(
+            session_uuid.clone(),
+            StoredSession {
+                session_id,
+                session_uuid: session_uuid.clone(),
+                user_id: new_session.user_id,
+                created_at: new_session.created_at,
+                last_used_at: new_session.created_at,
+                expires_at: new_session.expires_at,
+                idle_timeout: new_session.idle_timeout,
+                ip_address: new_session.ip_address.map(str::to_string),
+                user_agent: new_session.user_agent.map(str::to_string),
+                client_id: new_session.client_id.map(str::to_string),
+                scope: new_session.scope.map(str::to_string),
+                auth_time: new_session.auth_time,
+            },
+        );
+        Ok(session_uuid)
+    }
+
+    async fn get_session_user_id(&self, session_uuid: &str) -> Result<i64, AuthError> {
+        match self.lock().sessions.get(session_uuid) {
+            Some(session) => Ok(session.user_id),
+            None => Err(AuthError::RecordNotFound),
+        }
+    }
+
+    async fn get_session(&self, session_uuid: &str) -> Result<StoredSession, AuthError> {
+        match self.lock().sessions.get(session_uuid) {
+            Some(session) => Ok(session.clone()),
+            None => Err(AuthError::RecordNotFound),
+        }
+    }
+
+    async fn get_user_sessions(&self, user_id: i64) -> Result<Vec<StoredSession>, AuthError> {
+        let mut sessions: Vec<StoredSession> = self
+            .lock()
+            .sessions
+            .values()
+            .filter(|session| session.user_id == user_id)
+            .cloned()
+            .collect();
+        sessions.sort_by_key(|session| session.session_id);
+        Ok(sessions)
+    }
+
+    async fn touch_session(&self, session_uuid: &str, used_at: i64) -> Result<(), AuthError> {
+        if let Some(session) = self.lock().sessions.get_mut(session_uuid) {
+            session.last_used_at = used_at;
+        }
+        Ok(())
+    }
+
+    async fn drop_session(&self, session_uuid: &str) -> Result<(), AuthError> {
+        self.lock().sessions.remove(session_uuid);
+        Ok(())
+    }
+
+    async fn purge_expired_sessions(&self, now: i64) -> Result<u64, AuthError> {
+        let mut state = self.lock();
+        let session_count = state.sessions.len();
+        state.sessions.retain(|_, session| !session.is_expired(now));
+        let purged = (session_count - state.sessions.len()) as u64;
+
+        let MemoryState {
+            sessions,
+            refresh_tokens,
+            ..
+        } = &mut *state;
+        refresh_tokens.retain(|_, refresh_token| {
+            refresh_token.expires_at > now && sessions.contains_key(&refresh_token.session_uuid)
+        });
+        state
+            .revoked_tokens
+            .retain(|_, expires_at| *expires_at > now);
+        state
+            .authorization_codes
+            .retain(|_, authorization_code| authorization_code.expires_at > now);
+        Ok(purged)
+    }
+
+    async fn store_refresh_token(
+        &self,
+        refresh_token: &StoredRefreshToken,
+    ) -> Result<(), AuthError> {
+        let mut state = self.lock();
+        if state.refresh_tokens.contains_key(&refresh_token.token_id) {
+            return Err(AuthError::DuplicateRecord);
+        }
+        state
+            .refresh_tokens
+            .insert(refresh_token.token_id.clone(), refresh_token.clone());
+        Ok(())
+    }
+
+    async fn get_refresh_token(&self, token_id: &str) -> Result<StoredRefreshToken, AuthError> {
+        match self.lock().refresh_tokens.get(token_id) {
+            Some(refresh_token) => Ok(refresh_token.clone()),
+            None => Err(AuthError::RecordNotFound),
+        }
+    }
+
+    async fn use_refresh_token(&self, token_id: &str, used_at: i64) -> Result<bool, AuthError> {
+        match self.lock().refresh_tokens.get_mut(token_id) {
+            Some(refresh_token)
+                if refresh_token.used_at.is_none() && refresh_token.revoked_at.is_none() =>
+            {
+                refresh_token.used_at = Some(used_at);
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    async fn revoke_refresh_token_family(
+        &self,
+        family_id: &str,
+        revoked_at: i64,
+    ) -> Result<(), AuthError> {
+        self.lock()
+            .refresh_tokens
+            .values_mut()
+            .filter(|refresh_token| {
+                refresh_token.family_id == family_id && refresh_token.revoked_at.is_none()
+            })
+            .for_each(|refresh_token| refresh_token.revoked_at = Some(revoked_at));
+        Ok(())
+    }
+
+    async fn delete_refresh_token_family(&self, family_id: &str) -> Result<(), AuthError> {
+        self.lock()
+            .refresh_tokens
+            .retain(|_, refresh_token| refresh_token.family_id != family_id);
+        Ok(())
+    }
+
+    async fn revoke_access_token(&self, jti: &str, expires_at: i64) -> Result<(), AuthError> {
+        self.lock()
+            .revoked_tokens
+            .entry(jti.to_string())
+            .or_insert(expires_at);
+        Ok(())
+    }
+
+    async fn is_access_token_revoked(&self, jti: &str) -> Result<bool, AuthError> {
+        Ok(self.lock().revoked_tokens.contains_key(jti))
+    }
+}
+
+#[async_trait]
+impl KeyStore for MemoryStorage {
+    async fn get_secret_access_key(&self, user_id: i64) -> Result<String, AuthError> {
+        match self.lock().secret_access_keys.get(&user_id) {
+            Some(secret_key) => Ok(secret_key.clone()),
+            None => Err(AuthError::RecordNotFound),
+        }
+    }
+
+    async fn get_secret_refresh_key(&self, user_id: i64) -> Result<String, AuthError> {
+        match self.lock().secret_refresh_keys.get(&user_id) {
+            Some(secret_refresh_key) => Ok(secret_refresh_key.clone()),
+            None => Err(AuthError::RecordNotFound),
+        }
+    }
+
+    async fn store_signing_key(&self, signing_key: &StoredSigningKey) -> Result<(), AuthError> {
+        let mut state = self.lock();
+        if state
+            .signing_keys
+            .iter()
+            .any(|stored| stored.key_id == signing_key.key_id)
+        {
+            return Err(AuthError::DuplicateRecord);
+        }
+        state.signing_keys.push(signing_key.clone());
+        Ok(())
+    }
+
+    async fn get_signing_keys(&self) -> Result<Vec<StoredSigningKey>, AuthError> {
+        Ok(self.lock().signing_keys.clone())
+    }
+
+    async fn set_signing_key_activation(
+        &self,
+        key_id: &str,
+        activates_at: i64,
+    ) -> Result<(), AuthError> {
+        match self
+            .lock()
+            .signing_keys
+            .iter_mut()
+            .find(|stored| stored.key_id == key_id)
+        {
+            Some(stored) => {
+                stored.activates_at = Some(activates_at);
+                Ok(())
+            }
+            None => Err(AuthError::RecordNotFound),
+        }
+    }
+
+    async fn set_signing_key_retirement(
+        &self,
+        key_id: &str,
+        retires_at: i64,
+    ) -> Result<(), AuthError> {
+        match self
+            .lock()
+            .signing_keys
+            .iter_mut()
+            .find(|stored| stored.key_id == key_id)
+        {
+            Some(stored) => {
+                stored.retires_at = Some(retires_at);
+                Ok(())
+            }
+            None => Err(AuthError::RecordNotFound),
+        }
+    }
+}
+
+#[async_trait]
+impl ClientStore for MemoryStorage {
+    async fn store_client(&self, client: &StoredClient) -> Result<(), AuthError> {
+        let mut state = self.lock();
+        if state
+            .clients
+            .iter()
+            .any(|stored| stored.client_id == client.client_id)
+        {
+            return Err(AuthError::DuplicateRecord);
+        }
+        state.clients.push(client.clone());
+        Ok(())
+    }
+
+    async fn get_client(&self, client_id: &str) -> Result<StoredClient, AuthError> {
+        self.lock()
+            .clients
+            .iter()
+            .find(|stored| stored.client_id == client_id)
+            .cloned()
+            .ok_or(AuthError::RecordNotFound)
+    }
+
+    async fn get_clients(&self) -> Result<Vec<StoredClient>, AuthError> {
+        Ok(self.lock().clients.clone())
+    }
+
+    async fn delete_client(&self, client_id: &str) -> Result<(), AuthError> {
+        let mut state = self.lock();
+        let client_count = state.clients.len();
+        state.clients.retain(|stored| stored.client_id != client_id);
+        if state.clients.len() == client_count {
+            return Err(AuthError::RecordNotFound);
+        }
+        Ok(())
+    }
+
+    async fn store_authorization_code(
+        &self,
+        authorization_code: &StoredAuthorizationCode,
+    ) -> Result<(), AuthError> {
+        let mut state = self.lock();
+        if state
+            .authorization_codes
+            .contains_key(&authorization_code.code)
+        {
+            return Err(AuthError::DuplicateRecord);
+        }
+        state
+            .authorization_codes
+            .insert(authorization_code.code.clone(), authorization_code.clone());
+        Ok(())
+    }
+
+    async fn get_authorization_code(
+        &self,
+        code: &str,
+    ) -> Result<StoredAuthorizationCode, AuthError> {
+        self.lock()
+            .authorization_codes
+            .get(code)
+            .cloned()
+            .ok_or(AuthError::RecordNotFound)
+    }
+
+    async fn use_authorization_code(&self, code: &str, used_at: i64) -> Result<bool, AuthError> {
+        match self.lock().authorization_codes.get_mut(code) {
+            Some(authorization_code) if authorization_code.used_at.is_none() => {
+                authorization_code.used_at = Some(used_at);
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    async fn get_consented_scope(
+        &self,
+        user_id: i64,
+        client_id: &str,
+    ) -> Result<Option<String>, AuthError> {
+        Ok(self
+            .lock()
+            .consents
+            .get(&(user_id, client_id.to_string()))
+            .cloned())
+    }
+
+    async fn store_consent(
+        &self,
+        user_id: i64,
+        client_id: &str,
+        scope: &str,
+        _granted_at: i64,
+    ) -> Result<(), AuthError> {
+        self.lock()
+            .consents
+            .insert((user_id, client_id.to_string()), scope.to_string());
+        Ok(())
+    }
+}
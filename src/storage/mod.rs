@@ -0,0 +1,277 @@
+pub mod memory;
+pub mod postgres;
+pub mod sqlite;
+
+use async_trait::async_trait;
+
+use crate::auth::user::UserInfo;
+use crate::errors::auth_error::AuthError;
+
+pub const POSTGRES_BACKEND: &str = "postgres";
+pub const SQLITE_BACKEND: &str = "sqlite";
+pub const MEMORY_BACKEND: &str = "memory";
+
+// Values of the `subscription_level` enum, from the lowest tier to the highest
+pub const SUBSCRIPTION_LEVELS: [&str; 4] = [
+    "non-premium",
+    "basic-premium",
+    "normal-premium",
+    "enterprise-premium",
+];
+pub const DEFAULT_SUBSCRIPTION_LEVEL: &str = SUBSCRIPTION_LEVELS[0];
+
+// Whether `level` is `required` or a higher tier, unknown levels meet nothing
+pub fn meets_subscription_level(level: &str, required: &str) -> bool {
+    let rank = |level: &str| SUBSCRIPTION_LEVELS.iter().position(|known| *known == level);
+    match (rank(level), rank(required)) {
+        (Some(level), Some(required)) => level >= required,
+        _ => false,
+    }
+}
+
+// Everything written when an account is registered
+pub struct NewUser<'a> {
+    pub username: &'a str,
+    pub password_hash: &'a str,
+    pub email: &'a str,
+    pub secret_access_key: &'a str,
+    pub secret_refresh_key: &'a str,
+}
+
+// Everything recorded when a session is established, the metadata lets users recognise
+// their devices in the session management API
+pub struct NewSession<'a> {
+    pub user_id: i64,
+    pub created_at: i64,
+    pub expires_at: i64,
+    pub idle_timeout: i64,
+    pub ip_address: Option<&'a str>,
+    pub user_agent: Option<&'a str>,
+    pub client_id: Option<&'a str>,
+    pub scope: Option<&'a str>,
+    pub auth_time: Option<i64>,
+}
+
+// Session as persisted, `session_uuid` is the secret kept in the session cookie while
+// `session_id` identifies the session towards its owner. Sessions of OAuth clients carry
+// the client, the scope granted to it and the time the user logged in to grant it.
+#[derive(Clone, sqlx::FromRow)]
+pub struct StoredSession {
+    pub session_id: i64,
+    pub session_uuid: String,
+    pub user_id: i64,
+    pub created_at: i64,
+    pub last_used_at: i64,
+    pub expires_at: i64,
+    pub idle_timeout: i64,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub client_id: Option<String>,
+    pub scope: Option<String>,
+    pub auth_time: Option<i64>,
+}
+
+impl StoredSession {
+    pub fn is_expired(&self, now: i64) -> bool {
+        now >= self.expires_at || now >= self.last_used_at + self.idle_timeout
+    }
+}
+
+// Server wide signing key as persisted, timestamps are unix seconds
+#[derive(Clone, sqlx::FromRow)]
+pub struct StoredSigningKey {
+    pub key_id: String,
+    pub algorithm: String,
+    pub private_key: String,
+    pub created_at: i64,
+    pub activates_at: Option<i64>,
+    pub retires_at: Option<i64>,
+}
+
+// Refresh token as recorded when it was issued, timestamps are unix seconds
+#[derive(Clone, sqlx::FromRow)]
+pub struct StoredRefreshToken {
+    pub token_id: String,
+    pub family_id: String,
+    pub session_uuid: String,
+    pub user_id: i64,
+    pub expires_at: i64,
+    pub used_at: Option<i64>,
+    pub revoked_at: Option<i64>,
+}
+
+// Service authenticating with client credentials, `client_secret` holds the argon2 hash,
+// `redirect_uris` the space separated uris the authorization code flow may return to and
+// `allowed_scopes` the space separated scopes a machine client may request for itself.
+// Only a `resource_server` may introspect the tokens of others.
+#[derive(Clone, sqlx::FromRow)]
+pub struct StoredClient {
+    pub client_id: String,
+    pub client_secret: String,
+    pub name: String,
+    pub created_at: i64,
+    pub redirect_uris: String,
+    pub allowed_scopes: String,
+    pub resource_server: bool,
+}
+
+impl StoredClient {
+    pub fn has_redirect_uri(&self, redirect_uri: &str) -> bool {
+        self.redirect_uris
+            .split_whitespace()
+            .any(|registered| registered == redirect_uri)
+    }
+}
+
+// Authorization code as issued, `code_challenge` is the PKCE S256 challenge the code
+// verifier has to match, `nonce` and `auth_time` end up in the ID token
+#[derive(Clone, sqlx::FromRow)]
+pub struct StoredAuthorizationCode {
+    pub code: String,
+    pub client_id: String,
+    pub user_id: i64,
+    pub redirect_uri: String,
+    pub scope: Option<String>,
+    pub code_challenge: String,
+    pub nonce: Option<String>,
+    pub auth_time: i64,
+    pub expires_at: i64,
+    pub used_at: Option<i64>,
+}
+
+#[async_trait]
+pub trait UserStore {
+    async fn does_user_id_exists(&self, user_id: i64) -> Result<bool, AuthError>;
+
+    async fn does_username_exists(&self, username: &str) -> Result<bool, AuthError>;
+
+    async fn get_user(&self, username: &str) -> Result<UserInfo, AuthError>;
+
+    async fn get_user_with_user_id(&self, user_id: i64) -> Result<UserInfo, AuthError>;
+
+    // Stores the user together with its secret keys atomically, returns the new user id
+    async fn store_new_user(&self, new_user: &NewUser<'_>) -> Result<i64, AuthError>;
+
+    async fn set_user_subscription_level(&self, user_id: i64, level: &str)
+        -> Result<(), AuthError>;
+
+    async fn get_user_subscription_level(&self, user_id: i64) -> Result<String, AuthError>;
+
+    async fn get_user_roles(&self, user_id: i64) -> Result<Vec<String>, AuthError>;
+
+    // Granting a role the user already has changes nothing
+    async fn grant_user_role(&self, user_id: i64, role: &str) -> Result<(), AuthError>;
+
+    async fn revoke_user_role(&self, user_id: i64, role: &str) -> Result<(), AuthError>;
+}
+
+#[async_trait]
+pub trait SessionStore {
+    async fn create_session(&self, new_session: &NewSession<'_>) -> Result<String, AuthError>;
+
+    async fn get_session_user_id(&self, session_uuid: &str) -> Result<i64, AuthError>;
+
+    async fn get_session(&self, session_uuid: &str) -> Result<StoredSession, AuthError>;
+
+    async fn get_user_sessions(&self, user_id: i64) -> Result<Vec<StoredSession>, AuthError>;
+
+    async fn touch_session(&self, session_uuid: &str, used_at: i64) -> Result<(), AuthError>;
+
+    async fn drop_session(&self, session_uuid: &str) -> Result<(), AuthError>;
+
+    // Deletes sessions past either timeout together with refresh tokens that expired or
+    // lost their session, revocations of expired access tokens and expired authorization
+    // codes, returns the number of deleted sessions
+    async fn purge_expired_sessions(&self, now: i64) -> Result<u64, AuthError>;
+
+    async fn store_refresh_token(
+        &self,
+        refresh_token: &StoredRefreshToken,
+    ) -> Result<(), AuthError>;
+
+    async fn get_refresh_token(&self, token_id: &str) -> Result<StoredRefreshToken, AuthError>;
+
+    // Marks the token as used unless it was used or revoked before, returns whether it was
+    async fn use_refresh_token(&self, token_id: &str, used_at: i64) -> Result<bool, AuthError>;
+
+    async fn revoke_refresh_token_family(
+        &self,
+        family_id: &str,
+        revoked_at: i64,
+    ) -> Result<(), AuthError>;
+
+    async fn delete_refresh_token_family(&self, family_id: &str) -> Result<(), AuthError>;
+
+    // Revoking an already revoked token is not an error
+    async fn revoke_access_token(&self, jti: &str, expires_at: i64) -> Result<(), AuthError>;
+
+    async fn is_access_token_revoked(&self, jti: &str) -> Result<bool, AuthError>;
+}
+
+#[async_trait]
+pub trait KeyStore {
+    async fn get_secret_access_key(&self, user_id: i64) -> Result<String, AuthError>;
+
+    async fn get_secret_refresh_key(&self, user_id: i64) -> Result<String, AuthError>;
+
+    async fn store_signing_key(&self, signing_key: &StoredSigningKey) -> Result<(), AuthError>;
+
+    async fn get_signing_keys(&self) -> Result<Vec<StoredSigningKey>, AuthError>;
+
+    async fn set_signing_key_activation(
+        &self,
+        key_id: &str,
+        activates_at: i64,
+    ) -> Result<(), AuthError>;
+
+    async fn set_signing_key_retirement(
+        &self,
+        key_id: &str,
+        retires_at: i64,
+    ) -> Result<(), AuthError>;
+}
+
+#[async_trait]
+pub trait ClientStore {
+    async fn store_client(&self, client: &StoredClient) -> Result<(), AuthError>;
+
+    async fn get_client(&self, client_id: &str) -> Result<StoredClient, AuthError>;
+
+    async fn get_clients(&self) -> Result<Vec<StoredClient>, AuthError>;
+
+    async fn delete_client(&self, client_id: &str) -> Result<(), AuthError>;
+
+    async fn store_authorization_code(
+        &self,
+        authorization_code: &StoredAuthorizationCode,
+    ) -> Result<(), AuthError>;
+
+    async fn get_authorization_code(
+        &self,
+        code: &str,
+    ) -> Result<StoredAuthorizationCode, AuthError>;
+
+    // Marks the code as used, returns false when it already was
+    async fn use_authorization_code(&self, code: &str, used_at: i64) -> Result<bool, AuthError>;
+
+    // Scope the user consented to, `None` without any consent for the client
+    async fn get_consented_scope(
+        &self,
+        user_id: i64,
+        client_id: &str,
+    ) -> Result<Option<String>, AuthError>;
+
+    // Replaces an earlier consent of the user for the client
+    async fn store_consent(
+        &self,
+        user_id: i64,
+        client_id: &str,
+        scope: &str,
+        granted_at: i64,
+    ) -> Result<(), AuthError>;
+}
+
+// Everything the request handlers need from a persistence backend
+pub trait Storage: UserStore + SessionStore + KeyStore + ClientStore + Send + Sync {}
+
+impl<T: UserStore + SessionStore + KeyStore + ClientStore + Send + Sync> Storage for T {}
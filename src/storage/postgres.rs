@@ -0,0 +1,222 @@
+use async_trait::async_trait;
+
+use crate::auth::user::UserInfo;
+use crate::errors::auth_error::AuthError;
+use crate::storage::{
+    ClientStore, KeyStore, NewSession, NewUser, SessionStore, StoredAuthorizationCode,
+    StoredClient, StoredRefreshToken, StoredSession, StoredSigningKey, UserStore,
+};
+
+pub struct PostgresStorage {
+    pool: sqlx::Pool<sqlx::Postgres>,
+}
+
+impl PostgresStorage {
+    pub fn new(pool: sqlx::Pool<sqlx::Postgres>) -> Self {
+        PostgresStorage { pool }
+    }
+}
+
+#[async_trait]
+impl UserStore for PostgresStorage {
+    async fn does_user_id_exists(&self, user_id: i64) -> Result<bool, AuthError> {
+        crate::db::does_user_id_exists(&self.pool, user_id).await
+    }
+
+    async fn does_username_exists(&self, username: &str) -> Result<bool, AuthError> {
+        crate::db::does_username_exists(&self.pool, username).await
+    }
+
+    async fn get_user(&self, username: &str) -> Result<UserInfo, AuthError> {
+        crate::db::get_user_from_db(username, &self.pool).await
+    }
+
+    async fn get_user_with_user_id(&self, user_id: i64) -> Result<UserInfo, AuthError> {
+        crate::db::get_user_from_db_with_user_id(user_id, &self.pool).await
+    }
+
+    async fn store_new_user(&self, new_user: &NewUser<'_>) -> Result<i64, AuthError> {
+        crate::db::store_new_user_with_keys(new_user, &self.pool).await
+    }
+
+    async fn set_user_subscription_level(
+        &self,
+        user_id: i64,
+        level: &str,
+    ) -> Result<(), AuthError> {
+        crate::db::set_user_subscription_level(&self.pool, user_id, level).await
+    }
+
+    async fn get_user_subscription_level(&self, user_id: i64) -> Result<String, AuthError> {
+        crate::db::get_user_subscription_level(&self.pool, user_id).await
+    }
+
+    async fn get_user_roles(&self, user_id: i64) -> Result<Vec<String>, AuthError> {
+        crate::db::get_user_roles(user_id, &self.pool).await
+    }
+
+    async fn grant_user_role(&self, user_id: i64, role: &str) -> Result<(), AuthError> {
+        crate::db::grant_user_role(user_id, role, &self.pool).await
+    }
+
+    async fn revoke_user_role(&self, user_id: i64, role: &str) -> Result<(), AuthError> {
+        crate::db::revoke_user_role(user_id, role, &self.pool).await
+    }
+}
+
+#[async_trait]
+impl SessionStore for PostgresStorage {
+    async fn create_session(&self, new_session: &NewSession<'_>) -> Result<String, AuthError> {
+        crate::db::create_session(new_session, &self.pool).await
+    }
+
+    async fn get_session_user_id(&self, session_uuid: &str) -> Result<i64, AuthError> {
+        crate::db::get_session_user_id(session_uuid, &self.pool).await
+    }
+
+    async fn get_session(&self, session_uuid: &str) -> Result<StoredSession, AuthError> {
+        crate::db::get_session(session_uuid, &self.pool).await
+    }
+
+    async fn get_user_sessions(&self, user_id: i64) -> Result<Vec<StoredSession>, AuthError> {
+        crate::db::get_user_sessions(user_id, &self.pool).await
+    }
+
+    async fn touch_session(&self, session_uuid: &str, used_at: i64) -> Result<(), AuthError> {
+        crate::db::touch_session(session_uuid, used_at, &self.pool).await
+    }
+
+    async fn drop_session(&self, session_uuid: &str) -> Result<(), AuthError> {
+        crate::db::proceed_drop_session(session_uuid, &self.pool).await?;
+        Ok(())
+    }
+
+    async fn purge_expired_sessions(&self, now: i64) -> Result<u64, AuthError> {
+        crate::db::purge_expired_sessions(now, &self.pool).await
+    }
+
+    async fn store_refresh_token(
+        &self,
+        refresh_token: &StoredRefreshToken,
+    ) -> Result<(), AuthError> {
+        crate::db::store_refresh_token(refresh_token, &self.pool).await
+    }
+
+    async fn get_refresh_token(&self, token_id: &str) -> Result<StoredRefreshToken, AuthError> {
+        crate::db::get_refresh_token(token_id, &self.pool).await
+    }
+
+    async fn use_refresh_token(&self, token_id: &str, used_at: i64) -> Result<bool, AuthError> {
+        crate::db::use_refresh_token(token_id, used_at, &self.pool).await
+    }
+
+    async fn revoke_refresh_token_family(
+        &self,
+        family_id: &str,
+        revoked_at: i64,
+    ) -> Result<(), AuthError> {
+        crate::db::revoke_refresh_token_family(family_id, revoked_at, &self.pool).await
+    }
+
+    async fn delete_refresh_token_family(&self, family_id: &str) -> Result<(), AuthError> {
+        crate::db::delete_refresh_token_family(family_id, &self.pool).await
+    }
+
+    async fn revoke_access_token(&self, jti: &str, expires_at: i64) -> Result<(), AuthError> {
+        crate::db::revoke_access_token(jti, expires_at, &self.pool).await
+    }
+
+    async fn is_access_token_revoked(&self, jti: &str) -> Result<bool, AuthError> {
+        crate::db::is_access_token_revoked(jti, &self.pool).await
+    }
+}
+
+#[async_trait]
+impl KeyStore for PostgresStorage {
+    async fn get_secret_access_key(&self, user_id: i64) -> Result<String, AuthError> {
+        crate::db::get_secret_access_key(user_id, &self.pool).await
+    }
+
+    async fn get_secret_refresh_key(&self, user_id: i64) -> Result<String, AuthError> {
+        crate::db::get_secret_refresh_key(user_id, &self.pool).await
+    }
+
+    async fn store_signing_key(&self, signing_key: &StoredSigningKey) -> Result<(), AuthError> {
+        crate::db::store_signing_key(signing_key, &self.pool).await
+    }
+
+    async fn get_signing_keys(&self) -> Result<Vec<StoredSigningKey>, AuthError> {
+        crate::db::get_signing_keys(&self.pool).await
+    }
+
+    async fn set_signing_key_activation(
+        &self,
+        key_id: &str,
+        activates_at: i64,
+    ) -> Result<(), AuthError> {
+        crate::db::set_signing_key_activation(key_id, activates_at, &self.pool).await
+    }
+
+    async fn set_signing_key_retirement(
+        &self,
+        key_id: &str,
+        retires_at: i64,
+    ) -> Result<(), AuthError> {
+        crate::db::set_signing_key_retirement(key_id, retires_at, &self.pool).await
+    }
+}
+
+#[async_trait]
+impl ClientStore for PostgresStorage {
+    async fn store_client(&self, client: &StoredClient) -> Result<(), AuthError> {
+        crate::db::store_client(client, &self.pool).await
+    }
+
+    async fn get_client(&self, client_id: &str) -> Result<StoredClient, AuthError> {
+        crate::db::get_client(client_id, &self.pool).await
+    }
+
+    async fn get_clients(&self) -> Result<Vec<StoredClient>, AuthError> {
+        crate::db::get_clients(&self.pool).await
+    }
+
+    async fn delete_client(&self, client_id: &str) -> Result<(), AuthError> {
+        crate::db::delete_client(client_id, &self.pool).await
+    }
+
+    async fn store_authorization_code(
+        &self,
+        authorization_code: &StoredAuthorizationCode,
+    ) -> Result<(), AuthError> {
+        crate::db::store_authorization_code(authorization_code, &self.pool).await
+    }
+
+    async fn get_authorization_code(
+        &self,
+        code: &str,
+    ) -> Result<StoredAuthorizationCode, AuthError> {
+        crate::db::get_authorization_code(code, &self.pool).await
+    }
+
+    async fn use_authorization_code(&self, code: &str, used_at: i64) -> Result<bool, AuthError> {
+        crate::db::use_authorization_code(code, used_at, &self.pool).await
+    }
+
+    async fn get_consented_scope(
+        &self,
+        user_id: i64,
+        client_id: &str,
+    ) -> Result<Option<String>, AuthError> {
+        crate::db::get_consented_scope(user_id, client_id, &self.pool).await
+    }
+
+    async fn store_consent(
+        &self,
+        user_id: i64,
+        client_id: &str,
+        scope: &str,
+        granted_at: i64,
+    ) -> Result<(), AuthError> {
+        crate::db::store_consent(user_id, client_id, scope, granted_at, &self.pool).await
+    }
+}
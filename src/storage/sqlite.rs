@@ -0,0 +1,679 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions};
+use uuid::Uuid;
+
+use crate::auth::user::UserInfo;
+use crate::db::{
+    AUTHORIZATION_CODE_TABLE, CLIENT_TABLE, CONSENT_TABLE, REFRESH_TOKEN_TABLE,
+    REVOKED_TOKEN_TABLE, SECRET_ACCESS_KEY_TABLE, SECRET_REFRESH_KEY_TABLE, SESSION_TABLE,
+    SIGNING_KEY_TABLE, USERS_TABLE, USER_ROLE_TABLE,
+};
+use crate::errors::auth_error::AuthError;
+use crate::logging::log::log_info;
+use crate::startup::environment_constants::EnvironmentConstants;
+use crate::storage::{
+    ClientStore, KeyStore, NewSession, NewUser, SessionStore, StoredAuthorizationCode,
+    StoredClient, StoredRefreshToken, StoredSession, StoredSigningKey, UserStore,
+};
+
+pub async fn create_sqlite_pool(
+    constants: &EnvironmentConstants,
+) -> Result<sqlx::Pool<sqlx::Sqlite>, sqlx::Error> {
+    log_info(&format!(
+        "Opening SQLite database at: {}",
+        constants.sqlite_database_path
+    ));
+    let connect_options = SqliteConnectOptions::new()
+        .filename(&constants.sqlite_database_path)
+        .create_if_missing(true)
+        .journal_mode(SqliteJournalMode::Wal)
+        .busy_timeout(Duration::from_secs(constants.database_acquire_timeout));
+
+    SqlitePoolOptions::new()
+        .max_connections(constants.database_max_connections)
+        .min_connections(constants.database_min_connections)
+        .connect_timeout(Duration::from_secs(constants.database_acquire_timeout))
+        .idle_timeout(Duration::from_secs(constants.database_idle_timeout))
+        .connect_with(connect_options)
+        .await
+}
+
+pub struct SqliteStorage {
+    pool: sqlx::Pool<sqlx::Sqlite>,
+}
+
+impl SqliteStorage {
+    pub fn new(pool: sqlx::Pool<sqlx::Sqlite>) -> Self {
+        SqliteStorage { pool }
+    }
+}
+
+#[async_trait]
+impl UserStore for SqliteStorage {
+    async fn does_user_id_exists(&self, user_id: i64) -> Result<bool, AuthError> {
+        let query = format!("SELECT COUNT(*) FROM {} WHERE user_id = ?", USERS_TABLE);
+        let row: (i64,) = sqlx::query_as(&query)
+            .bind(user_id)
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(row.0 == 1)
+    }
+
+    async fn does_username_exists(&self, username: &str) -> Result<bool, AuthError> {
+        let query = format!("SELECT COUNT(*) FROM {} WHERE username = ?", USERS_TABLE);
+        let row: (i64,) = sqlx::query_as(&query)
+            .bind(username)
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(row.0 == 1)
+    }
+
+    async fn get_user(&self, username: &str) -> Result<UserInfo, AuthError> {
+        let query = format!(
+            "SELECT user_id, password, email FROM {} WHERE username = ?",
+            USERS_TABLE
+        );
+        let row: (i64, String, String) = sqlx::query_as(&query)
+            .bind(username)
+            .fetch_one(&self.pool)
+            .await?;
+
+        Ok(UserInfo {
+            user_id: row.0,
+            username: username.to_string(),
+            password: row.1,
+            email: row.2,
+        })
+    }
+
+    async fn get_user_with_user_id(&self, user_id: i64) -> Result<UserInfo, AuthError> {
+        let query = format!(
+            "SELECT username, password, email FROM {} WHERE user_id = ?",
+            USERS_TABLE
+        );
+        let row: (String, String, String) = sqlx::query_as(&query)
+            .bind(user_id)
+            .fetch_one(&self.pool)
+            .await?;
+
+        Ok(UserInfo {
+            user_id,
+            username: row.0,
+            password: row.1,
+            email: row.2,
+        })
+    }
+
+    async fn store_new_user(&self, new_user: &NewUser<'_>) -> Result<i64, AuthError> {
+        // dropping the transaction on an early return rolls every insert back
+        let mut transaction = self.pool.begin().await?;
+
+        let query = format!(
+            "INSERT INTO {} (username, password, email) VALUES (?, ?, ?)",
+            USERS_TABLE
+        );
+        let user_id = sqlx::query(&query)
+            .bind(new_user.username)
+            .bind(new_user.password_hash)
+            .bind(new_user.email)
+            .execute(&mut transaction)
+            .await?
+            .last_insert_rowid();
+
+        let query = format!(
+            "INSERT INTO {} (user_id, secret_key) VALUES (?, ?)",
+            SECRET_ACCESS_KEY_TABLE
+        );
+        sqlx::query(&query)
+            .bind(user_id)
+            .bind(new_user.secret_access_key)
+            .execute(&mut transaction)
+            .await?;
+
+        let query = format!(
+            "INSERT INTO {} (user_id, secret_refresh_key) VALUES (?, ?)",
+            SECRET_REFRESH_KEY_TABLE
+        );
+        sqlx::query(&query)
+            .bind(user_id)
+            .bind(new_user.secret_refresh_key)
+            .execute(&mut transaction)
+            .await?;
+
+        transaction.commit().await?;
+        Ok(user_id)
+    }
+
+    async fn set_user_subscription_level(
+        &self,
+        user_id: i64,
+        level: &str,
+    ) -> Result<(), AuthError> {
+        let query = format!(
+            "UPDATE {} SET subscription = ? WHERE user_id = ?",
+            USERS_TABLE
+        );
+        sqlx::query(&query)
+            .bind(level)
+            .bind(user_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn get_user_subscription_level(&self, user_id: i64) -> Result<String, AuthError> {
+        let query = format!("SELECT subscription FROM {} WHERE user_id = ?", USERS_TABLE);
+        let row: (String,) = sqlx::query_as(&query)
+            .bind(user_id)
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(row.0)
+    }
+
+    async fn get_user_roles(&self, user_id: i64) -> Result<Vec<String>, AuthError> {
+        let query = format!(
+            "SELECT role FROM {} WHERE user_id = ? ORDER BY role",
+            USER_ROLE_TABLE
+        );
+        let rows: Vec<(String,)> = sqlx::query_as(&query)
+            .bind(user_id)
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(rows.into_iter().map(|row| row.0).collect())
+    }
+
+    async fn grant_user_role(&self, user_id: i64, role: &str) -> Result<(), AuthError> {
+        let query = format!(
+            "INSERT OR IGNORE INTO {} (user_id, role) VALUES (?, ?)",
+            USER_ROLE_TABLE
+        );
+        sqlx::query(&query)
+            .bind(user_id)
+            .bind(role)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn revoke_user_role(&self, user_id: i64, role: &str) -> Result<(), AuthError> {
+        let query = format!(
+            "DELETE FROM {} WHERE user_id = ? AND role = ?",
+            USER_ROLE_TABLE
+        );
+        let result = sqlx::query(&query)
+            .bind(user_id)
+            .bind(role)
+            .execute(&self.pool)
+            .await?;
+        if result.rows_affected() == 0 {
+            return Err(AuthError::RecordNotFound);
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl SessionStore for SqliteStorage {
+    async fn create_session(&self, new_session: &NewSession<'_>) -> Result<String, AuthError> {
+        let session_uuid = Uuid::new_v4().to_string();
+        let query = format!(
+            "INSERT INTO {} (user_id, session_uuid, created_at, last_used_at, expires_at, \
+             idle_timeout, ip_address, user_agent, client_id, scope, auth_time) \
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            SESSION_TABLE
+        );
+        sqlx::query(&query)
+            .bind(new_session.user_id)
+            .bind(&session_uuid)
+            .bind(new_session.created_at)
+            .bind(new_session.created_at)
+            .bind(new_session.expires_at)
+            .bind(new_session.idle_timeout)
+            .bind(new_session.ip_address)
+            .bind(new_session.user_agent)
+            .bind(new_session.client_id)
+            .bind(new_session.scope)
+            .bind(new_session.auth_time)
+            .execute(&self.pool)
+            .await?;
+        Ok(session_uuid)
+    }
+
+    async fn get_session_user_id(&self, session_uuid: &str) -> Result<i64, AuthError> {
+        let query = format!(
+            "SELECT user_id FROM {} WHERE session_uuid = ?",
+            SESSION_TABLE
+        );
+        let row: (i64,) = sqlx::query_as(&query)
+            .bind(session_uuid)
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(row.0)
+    }
+
+    async fn get_session(&self, session_uuid: &str) -> Result<StoredSession, AuthError> {
+        let query = format!(
+            "SELECT session_id, session_uuid, user_id, created_at, last_used_at, expires_at, \
+             idle_timeout, ip_address, user_agent, client_id, scope, auth_time FROM {} WHERE session_uuid = ?",
+            SESSION_TABLE
+        );
+        Ok(sqlx::query_as(&query)
+            .bind(session_uuid)
+            .fetch_one(&self.pool)
+            .await?)
+    }
+
+    async fn get_user_sessions(&self, user_id: i64) -> Result<Vec<StoredSession>, AuthError> {
+        let query = format!(
+            "SELECT session_id, session_uuid, user_id, created_at, last_used_at, expires_at, \
+             idle_timeout, ip_address, user_agent, client_id, scope, auth_time FROM {} WHERE user_id = ? ORDER BY session_id",
+            SESSION_TABLE
+        );
+        Ok(sqlx::query_as(&query)
+            .bind(user_id)
+            .fetch_all(&self.pool)
+            .await?)
+    }
+
+    async fn touch_session(&self, session_uuid: &str, used_at: i64) -> Result<(), AuthError> {
+        let query = format!(
+            "UPDATE {} SET last_used_at = ? WHERE session_uuid = ?",
+            SESSION_TABLE
+        );
+        sqlx::query(&query)
+            .bind(used_at)
+            .bind(session_uuid)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn drop_session(&self, session_uuid: &str) -> Result<(), AuthError> {
+        let query = format!("DELETE FROM {} WHERE session_uuid = ?", SESSION_TABLE);
+        sqlx::query(&query)
+            .bind(session_uuid)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn purge_expired_sessions(&self, now: i64) -> Result<u64, AuthError> {
+        let mut transaction = self.pool.begin().await?;
+        let query = format!(
+            "DELETE FROM {} WHERE expires_at <= ? OR last_used_at + idle_timeout <= ?",
+            SESSION_TABLE
+        );
+        let purged = sqlx::query(&query)
+            .bind(now)
+            .bind(now)
+            .execute(&mut transaction)
+            .await?
+            .rows_affected();
+        let query = format!(
+            "DELETE FROM {0} WHERE expires_at <= ? \
+             OR NOT EXISTS (SELECT 1 FROM {1} WHERE {1}.session_uuid = {0}.session_uuid)",
+            REFRESH_TOKEN_TABLE, SESSION_TABLE
+        );
+        sqlx::query(&query)
+            .bind(now)
+            .execute(&mut transaction)
+            .await?;
+        let query = format!("DELETE FROM {} WHERE expires_at <= ?", REVOKED_TOKEN_TABLE);
+        sqlx::query(&query)
+            .bind(now)
+            .execute(&mut transaction)
+            .await?;
+        let query = format!(
+            "DELETE FROM {} WHERE expires_at <= ?",
+            AUTHORIZATION_CODE_TABLE
+        );
+        sqlx::query(&query)
+            .bind(now)
+            .execute(&mut transaction)
+            .await?;
+        transaction.commit().await?;
+        Ok(purged)
+    }
+
+    async fn store_refresh_token(
+        &self,
+        refresh_token: &StoredRefreshToken,
+    ) -> Result<(), AuthError> {
+        let query = format!(
+            "INSERT INTO {} (token_id, family_id, session_uuid, user_id, expires_at, used_at, \
+             revoked_at) VALUES (?, ?, ?, ?, ?, ?, ?)",
+            REFRESH_TOKEN_TABLE
+        );
+        sqlx::query(&query)
+            .bind(&refresh_token.token_id)
+            .bind(&refresh_token.family_id)
+            .bind(&refresh_token.session_uuid)
+            .bind(refresh_token.user_id)
+            .bind(refresh_token.expires_at)
+            .bind(refresh_token.used_at)
+            .bind(refresh_token.revoked_at)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn get_refresh_token(&self, token_id: &str) -> Result<StoredRefreshToken, AuthError> {
+        let query = format!(
+            "SELECT token_id, family_id, session_uuid, user_id, expires_at, used_at, revoked_at \
+             FROM {} WHERE token_id = ?",
+            REFRESH_TOKEN_TABLE
+        );
+        Ok(sqlx::query_as(&query)
+            .bind(token_id)
+            .fetch_one(&self.pool)
+            .await?)
+    }
+
+    async fn use_refresh_token(&self, token_id: &str, used_at: i64) -> Result<bool, AuthError> {
+        let query = format!(
+            "UPDATE {} SET used_at = ? \
+             WHERE token_id = ? AND used_at IS NULL AND revoked_at IS NULL",
+            REFRESH_TOKEN_TABLE
+        );
+        let result = sqlx::query(&query)
+            .bind(used_at)
+            .bind(token_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn revoke_refresh_token_family(
+        &self,
+        family_id: &str,
+        revoked_at: i64,
+    ) -> Result<(), AuthError> {
+        let query = format!(
+            "UPDATE {} SET revoked_at = ? WHERE family_id = ? AND revoked_at IS NULL",
+            REFRESH_TOKEN_TABLE
+        );
+        sqlx::query(&query)
+            .bind(revoked_at)
+            .bind(family_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn delete_refresh_token_family(&self, family_id: &str) -> Result<(), AuthError> {
+        let query = format!("DELETE FROM {} WHERE family_id = ?", REFRESH_TOKEN_TABLE);
+        sqlx::query(&query)
+            .bind(family_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn revoke_access_token(&self, jti: &str, expires_at: i64) -> Result<(), AuthError> {
+        let query = format!(
+            "INSERT OR IGNORE INTO {} (jti, expires_at) VALUES (?, ?)",
+            REVOKED_TOKEN_TABLE
+        );
+        sqlx::query(&query)
+            .bind(jti)
+            .bind(expires_at)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn is_access_token_revoked(&self, jti: &str) -> Result<bool, AuthError> {
+        let query = format!(
+            "SELECT EXISTS (SELECT 1 FROM {} WHERE jti = ?)",
+            REVOKED_TOKEN_TABLE
+        );
+        Ok(sqlx::query_scalar(&query)
+            .bind(jti)
+            .fetch_one(&self.pool)
+            .await?)
+    }
+}
+
+#[async_trait]
+impl KeyStore for SqliteStorage {
+    async fn get_secret_access_key(&self, user_id: i64) -> Result<String, AuthError> {
+        let query = format!(
+            "SELECT secret_key FROM {} WHERE user_id = ?",
+            SECRET_ACCESS_KEY_TABLE
+        );
+        let row: (String,) = sqlx::query_as(&query)
+            .bind(user_id)
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(row.0)
+    }
+
+    async fn get_secret_refresh_key(&self, user_id: i64) -> Result<String, AuthError> {
+        let query = format!(
+            "SELECT secret_refresh_key FROM {} WHERE user_id = ?",
+            SECRET_REFRESH_KEY_TABLE
+        );
+        let row: (String,) = sqlx::query_as(&query)
+            .bind(user_id)
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(row.0)
+    }
+
+    async fn store_signing_key(&self, signing_key: &StoredSigningKey) -> Result<(), AuthError> {
+        let query = format!(
+            "INSERT INTO {} (key_id, algorithm, private_key, created_at, activates_at, retires_at) \
+             VALUES (?, ?, ?, ?, ?, ?)",
+            SIGNING_KEY_TABLE
+        );
+        sqlx::query(&query)
+            .bind(&signing_key.key_id)
+            .bind(&signing_key.algorithm)
+            .bind(&signing_key.private_key)
+            .bind(signing_key.created_at)
+            .bind(signing_key.activates_at)
+            .bind(signing_key.retires_at)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn get_signing_keys(&self) -> Result<Vec<StoredSigningKey>, AuthError> {
+        let query = format!(
+            "SELECT key_id, algorithm, private_key, created_at, activates_at, retires_at \
+             FROM {} ORDER BY created_at",
+            SIGNING_KEY_TABLE
+        );
+        Ok(sqlx::query_as(&query).fetch_all(&self.pool).await?)
+    }
+
+    async fn set_signing_key_activation(
+        &self,
+        key_id: &str,
+        activates_at: i64,
+    ) -> Result<(), AuthError> {
+        let query = format!(
+            "UPDATE {} SET activates_at = ? WHERE key_id = ?",
+            SIGNING_KEY_TABLE
+        );
+        let result = sqlx::query(&query)
+            .bind(activates_at)
+            .bind(key_id)
+            .execute(&self.pool)
+            .await?;
+        if result.rows_affected() == 0 {
+            return Err(AuthError::RecordNotFound);
+        }
+        Ok(())
+    }
+
+    async fn set_signing_key_retirement(
+        &self,
+        key_id: &str,
+        retires_at: i64,
+    ) -> Result<(), AuthError> {
+        let query = format!(
+            "UPDATE {} SET retires_at = ? WHERE key_id = ?",
+            SIGNING_KEY_TABLE
+        );
+        let result = sqlx::query(&query)
+            .bind(retires_at)
+            .bind(key_id)
+            .execute(&self.pool)
+            .await?;
+        if result.rows_affected() == 0 {
+            return Err(AuthError::RecordNotFound);
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ClientStore for SqliteStorage {
+    async fn store_client(&self, client: &StoredClient) -> Result<(), AuthError> {
+        let query = format!(
+            "INSERT INTO {} (client_id, client_secret, name, created_at, redirect_uris, \
+             allowed_scopes, resource_server) VALUES (?, ?, ?, ?, ?, ?, ?)",
+            CLIENT_TABLE
+        );
+        sqlx::query(&query)
+            .bind(&client.client_id)
+            .bind(&client.client_secret)
+            .bind(&client.name)
+            .bind(client.created_at)
+            .bind(&client.redirect_uris)
+            .bind(&client.allowed_scopes)
+            .bind(client.resource_server)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn get_client(&self, client_id: &str) -> Result<StoredClient, AuthError> {
+        let query = format!(
+            "SELECT client_id, client_secret, name, created_at, redirect_uris, allowed_scopes, \
+             resource_server FROM {} WHERE client_id = ?",
+            CLIENT_TABLE
+        );
+        Ok(sqlx::query_as(&query)
+            .bind(client_id)
+            .fetch_one(&self.pool)
+            .await?)
+    }
+
+    async fn get_clients(&self) -> Result<Vec<StoredClient>, AuthError> {
+        let query = format!(
+            "SELECT client_id, client_secret, name, created_at, redirect_uris, allowed_scopes, \
+             resource_server FROM {} ORDER BY created_at",
+            CLIENT_TABLE
+        );
+        Ok(sqlx::query_as(&query).fetch_all(&self.pool).await?)
+    }
+
+    async fn delete_client(&self, client_id: &str) -> Result<(), AuthError> {
+        let query = format!("DELETE FROM {} WHERE client_id = ?", CLIENT_TABLE);
+        let result = sqlx::query(&query)
+            .bind(client_id)
+            .execute(&self.pool)
+            .await?;
+        if result.rows_affected() == 0 {
+            return Err(AuthError::RecordNotFound);
+        }
+        Ok(())
+    }
+
+    async fn store_authorization_code(
+        &self,
+        authorization_code: &StoredAuthorizationCode,
+    ) -> Result<(), AuthError> {
+        let query = format!(
+            "INSERT INTO {} (code, client_id, user_id, redirect_uri, scope, code_challenge, \
+             nonce, auth_time, expires_at, used_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            AUTHORIZATION_CODE_TABLE
+        );
+        sqlx::query(&query)
+            .bind(&authorization_code.code)
+            .bind(&authorization_code.client_id)
+            .bind(authorization_code.user_id)
+            .bind(&authorization_code.redirect_uri)
+            .bind(&authorization_code.scope)
+            .bind(&authorization_code.code_challenge)
+            .bind(&authorization_code.nonce)
+            .bind(authorization_code.auth_time)
+            .bind(authorization_code.expires_at)
+            .bind(authorization_code.used_at)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn get_authorization_code(
+        &self,
+        code: &str,
+    ) -> Result<StoredAuthorizationCode, AuthError> {
+        let query = format!(
+            "SELECT code, client_id, user_id, redirect_uri, scope, code_challenge, nonce, \
+             auth_time, expires_at, used_at FROM {} WHERE code = ?",
+            AUTHORIZATION_CODE_TABLE
+        );
+        Ok(sqlx::query_as(&query)
+            .bind(code)
+            .fetch_one(&self.pool)
+            .await?)
+    }
+
+    async fn use_authorization_code(&self, code: &str, used_at: i64) -> Result<bool, AuthError> {
+        let query = format!(
+            "UPDATE {} SET used_at = ? WHERE code = ? AND used_at IS NULL",
+            AUTHORIZATION_CODE_TABLE
+        );
+        let result = sqlx::query(&query)
+            .bind(used_at)
+            .bind(code)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn get_consented_scope(
+        &self,
+        user_id: i64,
+        client_id: &str,
+    ) -> Result<Option<String>, AuthError> {
+        let query = format!(
+            "SELECT scope FROM {} WHERE user_id = ? AND client_id = ?",
+            CONSENT_TABLE
+        );
+        let row: Option<(String,)> = sqlx::query_as(&query)
+            .bind(user_id)
+            .bind(client_id)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(row.map(|row| row.0))
+    }
+
+    async fn store_consent(
+        &self,
+        user_id: i64,
+        client_id: &str,
+        scope: &str,
+        granted_at: i64,
+    ) -> Result<(), AuthError> {
+        let query = format!(
+            "INSERT INTO {} (user_id, client_id, scope, granted_at) VALUES (?, ?, ?, ?) \
+             ON CONFLICT (user_id, client_id) DO UPDATE \
+             SET scope = excluded.scope, granted_at = excluded.granted_at",
+            CONSENT_TABLE
+        );
+        sqlx::query(&query)
+            .bind(user_id)
+            .bind(client_id)
+            .bind(scope)
+            .bind(granted_at)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,118 @@
+mod common;
+
+use actix_web::http::{header, StatusCode};
+use actix_web::test;
+
+use auth_server::auth::api_requests::sessions::SessionInfo;
+use auth_server::auth::token::delivery::TokenResponse;
+
+use common::{assert_problem, bearer, TestServer, USERNAME};
+
+async fn login(server: &TestServer) -> TokenResponse {
+    let request = test::TestRequest::post()
+        .uri("/auth/login")
+        .set_json(serde_json::json!({
+            "username": USERNAME,
+            "password": common::PASSWORD,
+            "token_delivery": "body",
+        }));
+    let response = server.send(request).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response.headers().get(header::SET_COOKIE).is_none());
+    assert_eq!(
+        response.headers().get(header::CACHE_CONTROL).unwrap(),
+        "no-store"
+    );
+    test::read_body_json(response).await
+}
+
+#[actix_web::test]
+async fn tokens_from_the_body_authenticate_without_cookies() {
+    let server = TestServer::registered().await;
+    let tokens = login(&server).await;
+    assert_eq!(tokens.token_type, "Bearer");
+    assert!(tokens.expires_in > 0);
+
+    let request = bearer(
+        test::TestRequest::get().uri("/auth/sessions"),
+        &tokens.access_token,
+    );
+    let response = server.send(request).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let sessions: Vec<SessionInfo> = test::read_body_json(response).await;
+    assert_eq!(sessions.len(), 1);
+    assert!(sessions[0].current);
+
+    let request = bearer(
+        test::TestRequest::get().uri("/auth/logout"),
+        &tokens.access_token,
+    );
+    assert_eq!(server.send(request).await.status(), StatusCode::OK);
+    let request = bearer(
+        test::TestRequest::get().uri("/auth/sessions"),
+        &tokens.access_token,
+    );
+    assert_problem(
+        server.send(request).await,
+        StatusCode::UNAUTHORIZED,
+        "session_not_found",
+    )
+    .await;
+}
+
+#[actix_web::test]
+async fn refresh_token_from_the_body_is_rotated() {
+    let server = TestServer::registered().await;
+    let tokens = login(&server).await;
+
+    let refresh = || {
+        test::TestRequest::post()
+            .uri("/auth/refresh")
+            .set_json(serde_json::json!({ "refresh_token": tokens.refresh_token }))
+    };
+    let response = server.send(refresh()).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response.headers().get(header::SET_COOKIE).is_none());
+    let refreshed: TokenResponse = test::read_body_json(response).await;
+    assert_ne!(refreshed.refresh_token, tokens.refresh_token);
+    let request = bearer(
+        test::TestRequest::get().uri("/auth/sessions"),
+        &refreshed.access_token,
+    );
+    assert_eq!(server.send(request).await.status(), StatusCode::OK);
+
+    assert_problem(
+        server.send(refresh()).await,
+        StatusCode::UNAUTHORIZED,
+        "refresh_token_reused",
+    )
+    .await;
+}
+
+#[actix_web::test]
+async fn malformed_bearer_tokens_are_rejected() {
+    let server = TestServer::registered().await;
+    let tokens = login(&server).await;
+
+    let mut forged = tokens.access_token.clone();
+    forged.push('x');
+    for token in ["garbage", forged.as_str()] {
+        let request = bearer(test::TestRequest::get().uri("/auth/sessions"), token);
+        assert_problem(
+            server.send(request).await,
+            StatusCode::UNAUTHORIZED,
+            "invalid_token",
+        )
+        .await;
+    }
+
+    let request = test::TestRequest::get()
+        .uri("/auth/sessions")
+        .insert_header((header::AUTHORIZATION, "Basic Zm9vOmJhcg=="));
+    assert_problem(
+        server.send(request).await,
+        StatusCode::BAD_REQUEST,
+        "missing_cookie",
+    )
+    .await;
+}
@@ -0,0 +1,175 @@
+mod common;
+
+use actix_web::dev::ServiceResponse;
+use actix_web::http::StatusCode;
+use actix_web::{test, web};
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::{decode, decode_header, Algorithm, DecodingKey, Validation};
+
+use auth_server::auth::client::create_client;
+use auth_server::auth::oauth::config::OAuthConfig;
+use auth_server::auth::token::client_token::ClientClaims;
+use auth_server::auth::token::constants::{ACCESS_TOKEN_AUDIENCE, CLIENT_TOKEN_TYPE};
+use auth_server::auth::token::delivery::ClientTokenResponse;
+use auth_server::auth::token::introspection::Introspection;
+use auth_server::auth::token::keyring::{generate_signing_key, Keyring};
+use auth_server::auth::token::signing_key::TokenSigner;
+use auth_server::errors::oauth_error::OAuthErrorBody;
+
+use common::{bearer, key_encryption, TestServer};
+
+async fn client_token(
+    server: &TestServer,
+    client: &(String, String),
+    scope: Option<&str>,
+) -> ServiceResponse {
+    let mut form = vec![
+        ("grant_type", "client_credentials"),
+        ("client_id", &client.0),
+        ("client_secret", &client.1),
+    ];
+    if let Some(scope) = scope {
+        form.push(("scope", scope));
+    }
+    let request = test::TestRequest::post().uri("/oauth/token").set_form(form);
+    server.send(request).await
+}
+
+async fn introspect(
+    server: &TestServer,
+    client: &(String, String),
+    access_token: &str,
+) -> Introspection {
+    let request = test::TestRequest::post().uri("/auth/introspect").set_form([
+        ("token", access_token),
+        ("client_id", &client.0),
+        ("client_secret", &client.1),
+    ]);
+    test::read_body_json(server.send(request).await).await
+}
+
+// The machine client may request reports scopes, tokens are signed with ES256 unless the
+// server keeps per-user secrets
+async fn setup(keyring: bool) -> (TestServer, (String, String)) {
+    let mut server = TestServer::new();
+    if keyring {
+        generate_signing_key(
+            server.storage.as_ref(),
+            &key_encryption(),
+            Algorithm::ES256,
+            true,
+        )
+        .await
+        .unwrap();
+        let keyring = Keyring::load(server.storage.as_ref(), key_encryption())
+            .await
+            .unwrap();
+        server.signer = web::Data::new(TokenSigner::Keyring(keyring));
+    }
+    let (client, client_secret) = create_client(
+        server.storage.as_ref(),
+        "nightly export",
+        &[],
+        &["reports:read".to_string(), "reports:write".to_string()],
+        true,
+    )
+    .await
+    .unwrap();
+    (server, (client.client_id, client_secret))
+}
+
+async fn error_of(response: ServiceResponse) -> String {
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let body: OAuthErrorBody = test::read_body_json(response).await;
+    body.error
+}
+
+#[actix_web::test]
+async fn client_tokens_name_the_client_instead_of_a_user() {
+    let (server, client) = setup(true).await;
+    let response = client_token(&server, &client, Some("reports:read")).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let body: serde_json::Value = test::read_body_json(response).await;
+    assert!(body.get("refresh_token").is_none());
+    let tokens: ClientTokenResponse = serde_json::from_value(body).unwrap();
+    assert_eq!(
+        tokens.expires_in,
+        OAuthConfig::default().client_token_lifetime
+    );
+    assert_eq!(tokens.scope.as_deref(), Some("reports:read"));
+
+    // resource servers verify the token offline with the published keys
+    let request = test::TestRequest::get().uri("/.well-known/jwks.json");
+    let jwks: JwkSet = test::read_body_json(server.send(request).await).await;
+    let header = decode_header(&tokens.access_token).unwrap();
+    assert_eq!(header.typ.as_deref(), Some(CLIENT_TOKEN_TYPE));
+    let decoding_key = DecodingKey::from_jwk(jwks.find(&header.kid.unwrap()).unwrap()).unwrap();
+    let mut validation = Validation::new(Algorithm::ES256);
+    validation.set_audience(&[ACCESS_TOKEN_AUDIENCE]);
+    let claims = decode::<ClientClaims>(&tokens.access_token, &decoding_key, &validation)
+        .unwrap()
+        .claims;
+    assert_eq!(claims.sub, client.0);
+    assert_eq!(claims.scope.as_deref(), Some("reports:read"));
+
+    let introspection = introspect(&server, &client, &tokens.access_token).await;
+    assert!(introspection.active);
+    assert_eq!(introspection.sub.as_deref(), Some(client.0.as_str()));
+    assert_eq!(introspection.client_id.as_deref(), Some(client.0.as_str()));
+    assert!(introspection.username.is_none());
+
+    // the token is no user, endpoints acting for users turn it away
+    let request = bearer(
+        test::TestRequest::get().uri("/auth/sessions"),
+        &tokens.access_token,
+    );
+    assert_eq!(
+        server.send(request).await.status(),
+        StatusCode::UNAUTHORIZED
+    );
+
+    let request = test::TestRequest::post()
+        .uri("/auth/revoke")
+        .set_form([("token", tokens.access_token.as_str())]);
+    assert_eq!(server.send(request).await.status(), StatusCode::OK);
+    assert!(
+        !introspect(&server, &client, &tokens.access_token)
+            .await
+            .active
+    );
+}
+
+#[actix_web::test]
+async fn machine_clients_are_limited_to_their_allowed_scopes() {
+    let (server, client) = setup(true).await;
+    let response = client_token(&server, &client, None).await;
+    let tokens: ClientTokenResponse = test::read_body_json(response).await;
+    assert_eq!(tokens.scope.as_deref(), Some("reports:read reports:write"));
+
+    let response = client_token(&server, &client, Some("reports:read users:delete")).await;
+    assert_eq!(error_of(response).await, "invalid_scope");
+
+    // clients registered without allowed scopes act for users only
+    let (other_client, other_secret) =
+        create_client(server.storage.as_ref(), "photos", &[], &[], true)
+            .await
+            .unwrap();
+    let other = (other_client.client_id, other_secret);
+    let response = client_token(&server, &other, None).await;
+    assert_eq!(error_of(response).await, "unauthorized_client");
+
+    // deleting the client ends its tokens before they expire
+    server.storage.delete_client(&client.0).await.unwrap();
+    assert!(
+        !introspect(&server, &other, &tokens.access_token)
+            .await
+            .active
+    );
+}
+
+#[actix_web::test]
+async fn client_credentials_need_published_keys() {
+    let (server, client) = setup(false).await;
+    let response = client_token(&server, &client, None).await;
+    assert_eq!(error_of(response).await, "unsupported_grant_type");
+}
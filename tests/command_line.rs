@@ -0,0 +1,21 @@
+use std::process::Command;
+
+// The check runs before any storage is opened, so no database is needed
+#[test]
+fn admin_commands_refuse_the_server_flags() {
+    for args in [
+        ["-c", "keys", "list"],
+        ["--clear_database", "clients", "list"],
+        ["-d", "migrate", "status"],
+    ] {
+        let output = Command::new(env!("CARGO_BIN_EXE_auth_server"))
+            .args(args)
+            .env("STORAGE_BACKEND", "postgres")
+            .env("DATABASE_ADDRESS", "unreachable.invalid")
+            .output()
+            .unwrap();
+        assert_eq!(output.status.code(), Some(2), "{:?}", args);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("can not be combined"), "{}", stderr);
+    }
+}
@@ -0,0 +1,274 @@
+// Fixtures shared by the integration tests. Every test binary compiles its own copy, so a
+// helper one of them does not use is not dead code.
+#![allow(dead_code)]
+
+use std::sync::Arc;
+
+use actix_web::cookie::Cookie;
+use actix_web::dev::ServiceResponse;
+use actix_web::http::{header, StatusCode};
+use actix_web::{test, web, App};
+use base64::Engine;
+use sqlx::sqlite::SqlitePoolOptions;
+
+use auth_server::auth::api_requests::{
+    authorize, discovery, introspect, jwks, login, logout, refresh_token, register, revoke,
+    sessions, token, userinfo, verify,
+};
+use auth_server::auth::forward_auth::ForwardAuthConfig;
+use auth_server::auth::oauth::config::OAuthConfig;
+use auth_server::auth::session::SessionPolicy;
+use auth_server::auth::token::delivery::TokenResponse;
+use auth_server::auth::token::key_encryption::KeyEncryption;
+use auth_server::auth::token::revocation::RevocationList;
+use auth_server::auth::token::signing_key::TokenSigner;
+use auth_server::errors::auth_error::AuthError;
+use auth_server::errors::oauth_error::OAuthError;
+use auth_server::errors::problem_details::{ProblemDetails, PROBLEM_JSON_CONTENT_TYPE};
+use auth_server::gateway::routes::Gateway;
+use auth_server::storage::memory::MemoryStorage;
+use auth_server::storage::Storage;
+
+pub const USERNAME: &str = "Zenek";
+pub const PASSWORD: &str = "password";
+
+// State of the server under test, the app is built around it again for every request, so
+// the storage, keys and revocations outlive the request that changed them
+pub struct TestServer {
+    pub storage: Arc<dyn Storage>,
+    pub signer: web::Data<TokenSigner>,
+    pub session_policy: web::Data<SessionPolicy>,
+    pub revocations: web::Data<RevocationList>,
+    pub forward_auth: web::Data<ForwardAuthConfig>,
+    pub oauth: web::Data<OAuthConfig>,
+    pub gateway: Option<Gateway>,
+}
+
+impl TestServer {
+    pub fn new() -> TestServer {
+        TestServer::with_storage(Arc::new(MemoryStorage::new()))
+    }
+
+    pub fn with_storage(storage: Arc<dyn Storage>) -> TestServer {
+        TestServer {
+            storage,
+            signer: web::Data::new(TokenSigner::PerUserSecret),
+            session_policy: web::Data::new(SessionPolicy::default()),
+            revocations: web::Data::new(RevocationList::default()),
+            forward_auth: web::Data::new(ForwardAuthConfig::default()),
+            oauth: web::Data::new(OAuthConfig::default()),
+            gateway: None,
+        }
+    }
+
+    // The routes of the server binary, without rate limiting
+    pub fn configure(&self, cfg: &mut web::ServiceConfig) {
+        cfg.app_data(web::Data::from(self.storage.clone()))
+            .app_data(self.signer.clone())
+            .app_data(self.session_policy.clone())
+            .app_data(self.revocations.clone())
+            .app_data(self.forward_auth.clone())
+            .app_data(self.oauth.clone())
+            .app_data(
+                web::JsonConfig::default()
+                    .error_handler(|e, _req| AuthError::InvalidRequestBody(e.to_string()).into()),
+            )
+            .app_data(
+                web::FormConfig::default()
+                    .error_handler(|e, _req| AuthError::InvalidRequestBody(e.to_string()).into()),
+            )
+            .app_data(
+                web::PathConfig::default()
+                    .error_handler(|_e, _req| AuthError::RecordNotFound.into()),
+            )
+            .route("/.well-known/jwks.json", web::get().to(jwks::jwks))
+            .route(
+                "/.well-known/openid-configuration",
+                web::get().to(discovery::openid_configuration),
+            )
+            .service(
+                web::scope("/auth")
+                    .route("/register", web::post().to(register::register))
+                    .route("/login", web::post().to(login::login))
+                    .route("/logout", web::get().to(logout::logout))
+                    .route("/refresh", web::post().to(refresh_token::refresh_token))
+                    .route("/revoke", web::post().to(revoke::revoke))
+                    .route("/introspect", web::post().to(introspect::introspect))
+                    .route("/verify", web::get().to(verify::verify))
+                    .route("/sessions", web::get().to(sessions::list_sessions))
+                    .route(
+                        "/sessions/revoke-others",
+                        web::post().to(sessions::revoke_other_sessions),
+                    )
+                    .route(
+                        "/sessions/{session_id}",
+                        web::delete().to(sessions::revoke_session),
+                    ),
+            )
+            .service(
+                web::scope("/oauth")
+                    .app_data(
+                        web::QueryConfig::default().error_handler(|e, _req| {
+                            OAuthError::invalid_request(e.to_string()).into()
+                        }),
+                    )
+                    .app_data(
+                        web::FormConfig::default().error_handler(|e, _req| {
+                            OAuthError::invalid_request(e.to_string()).into()
+                        }),
+                    )
+                    .route("/authorize", web::get().to(authorize::authorize))
+                    .route("/authorize", web::post().to(authorize::authorize_consent))
+                    .route("/token", web::post().to(token::token))
+                    .route("/userinfo", web::get().to(userinfo::userinfo))
+                    .route("/userinfo", web::post().to(userinfo::userinfo)),
+            );
+        if let Some(gateway) = &self.gateway {
+            gateway.configure(cfg);
+        }
+    }
+
+    pub async fn send(&self, request: test::TestRequest) -> ServiceResponse {
+        self.send_with(request, |_| {}).await
+    }
+
+    // `routes` mounts endpoints only a single test needs next to the ones of the server
+    pub async fn send_with(
+        &self,
+        request: test::TestRequest,
+        routes: impl FnOnce(&mut web::ServiceConfig),
+    ) -> ServiceResponse {
+        let app = test::init_service(
+            App::new()
+                .configure(|cfg| self.configure(cfg))
+                .configure(routes),
+        )
+        .await;
+        test::call_service(&app, request.to_request()).await
+    }
+
+    pub async fn register(&self, username: &str) {
+        let response = self.send(register_request(username)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    // Registers `USERNAME`, the user most tests act as
+    pub async fn registered() -> TestServer {
+        let server = TestServer::new();
+        server.register(USERNAME).await;
+        server
+    }
+
+    // Cookies of a successful login
+    pub async fn login(&self, username: &str) -> Vec<Cookie<'static>> {
+        let response = self.send(login_request(username)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        response_cookies(&response)
+    }
+
+    // Tokens of a successful login that asked for them in the body
+    pub async fn login_for_tokens(&self, username: &str) -> TokenResponse {
+        let request = test::TestRequest::post()
+            .uri("/auth/login")
+            .set_json(serde_json::json!({
+                "username": username,
+                "password": PASSWORD,
+                "token_delivery": "body",
+            }));
+        let response = self.send(request).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        test::read_body_json(response).await
+    }
+}
+
+// Encrypts the keyring of a test, any 32 bytes will do
+pub fn key_encryption() -> KeyEncryption {
+    KeyEncryption::Aes256Gcm([7; 32])
+}
+
+// A private in-memory SQLite database, its single connection keeps it alive for the whole test
+pub async fn sqlite_pool() -> sqlx::Pool<sqlx::Sqlite> {
+    SqlitePoolOptions::new()
+        .max_connections(1)
+        .idle_timeout(None)
+        .max_lifetime(None)
+        .connect("sqlite::memory:")
+        .await
+        .unwrap()
+}
+
+pub fn register_request(username: &str) -> test::TestRequest {
+    test::TestRequest::post()
+        .uri("/auth/register")
+        .set_json(serde_json::json!({
+            "username": username,
+            "password": PASSWORD,
+            "email": format!("{}@example.com", username.to_lowercase()),
+        }))
+}
+
+pub fn login_request(username: &str) -> test::TestRequest {
+    test::TestRequest::post()
+        .uri("/auth/login")
+        .set_json(serde_json::json!({ "username": username, "password": PASSWORD }))
+}
+
+pub fn response_cookies(response: &ServiceResponse) -> Vec<Cookie<'static>> {
+    response
+        .headers()
+        .get_all(header::SET_COOKIE)
+        .map(|value| Cookie::parse(value.to_str().unwrap().to_string()).unwrap())
+        .collect()
+}
+
+pub fn cookie(cookies: &[Cookie<'static>], name: &str) -> Cookie<'static> {
+    cookies
+        .iter()
+        .find(|cookie| cookie.name() == name)
+        .unwrap()
+        .clone()
+}
+
+// Replaces the cookies of `cookies` with the ones set by a later response
+pub fn merge_cookies(
+    mut cookies: Vec<Cookie<'static>>,
+    updated: Vec<Cookie<'static>>,
+) -> Vec<Cookie<'static>> {
+    cookies.retain(|cookie| updated.iter().all(|new| new.name() != cookie.name()));
+    cookies.extend(updated);
+    cookies
+}
+
+pub fn with_cookies(request: test::TestRequest, cookies: &[Cookie<'static>]) -> test::TestRequest {
+    cookies
+        .iter()
+        .fold(request, |request, cookie| request.cookie(cookie.clone()))
+}
+
+pub fn bearer(request: test::TestRequest, token: &str) -> test::TestRequest {
+    request.insert_header((header::AUTHORIZATION, format!("Bearer {}", token)))
+}
+
+pub fn basic_auth(client_id: &str, client_secret: &str) -> String {
+    let credentials = format!("{}:{}", client_id, client_secret);
+    format!(
+        "Basic {}",
+        base64::engine::general_purpose::STANDARD.encode(credentials)
+    )
+}
+
+pub async fn problem_code(response: ServiceResponse) -> String {
+    let problem: ProblemDetails = test::read_body_json(response).await;
+    problem.code
+}
+
+pub async fn assert_problem(response: ServiceResponse, status: StatusCode, code: &str) {
+    assert_eq!(response.status(), status);
+    assert_eq!(
+        response.headers().get(header::CONTENT_TYPE).unwrap(),
+        PROBLEM_JSON_CONTENT_TYPE
+    );
+    let problem: ProblemDetails = test::read_body_json(response).await;
+    assert_eq!(problem.code, code);
+    assert_eq!(problem.status, status.as_u16());
+}
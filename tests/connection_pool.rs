@@ -0,0 +1,31 @@
+use std::env;
+
+use auth_server::startup::environment_constants::get_environment_constants;
+use auth_server::storage::sqlite::create_sqlite_pool;
+
+// Environment variables are process wide, so everything that sets them lives in one test
+#[actix_web::test]
+async fn pool_is_sized_from_the_environment() {
+    let database_path = env::temp_dir().join(format!("auth_server_pool_{}.db", std::process::id()));
+    env::set_var("SQLITE_DATABASE_PATH", &database_path);
+    env::set_var("DATABASE_MAX_CONNECTIONS", "not a number");
+    assert_eq!(get_environment_constants().database_max_connections, 10);
+
+    env::set_var("DATABASE_MAX_CONNECTIONS", "1");
+    env::set_var("DATABASE_ACQUIRE_TIMEOUT", "1");
+    let constants = get_environment_constants();
+    assert_eq!(constants.database_max_connections, 1);
+    assert_eq!(constants.database_acquire_timeout, 1);
+
+    let pool = create_sqlite_pool(&constants).await.unwrap();
+    let connection = pool.acquire().await.unwrap();
+    // every worker waits for the one shared connection instead of opening its own
+    assert!(pool.acquire().await.is_err());
+    drop(connection);
+    assert!(pool.acquire().await.is_ok());
+
+    pool.close().await;
+    for suffix in ["", "-wal", "-shm"] {
+        let _ = std::fs::remove_file(format!("{}{}", database_path.display(), suffix));
+    }
+}
@@ -0,0 +1,177 @@
+mod common;
+
+use actix_web::dev::ServiceResponse;
+use actix_web::http::header::HeaderName;
+use actix_web::http::StatusCode;
+use actix_web::{test, web};
+
+use auth_server::auth::forward_auth::{normalize_path, parse_path_rules, ForwardAuthConfig};
+
+use common::{assert_problem, bearer, TestServer, USERNAME};
+
+async fn start(config: ForwardAuthConfig) -> (TestServer, String) {
+    let mut server = TestServer::registered().await;
+    server.forward_auth = web::Data::new(config);
+    let tokens = server.login_for_tokens(USERNAME).await;
+    (server, tokens.access_token)
+}
+
+async fn verify(
+    server: &TestServer,
+    token: Option<&str>,
+    original_uri: Option<&str>,
+) -> ServiceResponse {
+    let mut request = test::TestRequest::get().uri("/auth/verify");
+    if let Some(token) = token {
+        request = bearer(request, token);
+    }
+    if let Some(original_uri) = original_uri {
+        request = request
+            .insert_header(("X-Forwarded-Proto", "https"))
+            .insert_header(("X-Forwarded-Host", "app.example.com"))
+            .insert_header(("X-Forwarded-Uri", original_uri));
+    }
+    server.send(request).await
+}
+
+fn header_value<'a>(response: &'a ServiceResponse, name: &str) -> &'a str {
+    response.headers().get(name).unwrap().to_str().unwrap()
+}
+
+#[actix_web::test]
+async fn identity_headers_are_returned_for_valid_tokens() {
+    let (server, token) = start(ForwardAuthConfig::default()).await;
+
+    let response = verify(&server, Some(&token), None).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(header_value(&response, "X-Auth-User"), "Zenek");
+    assert_eq!(header_value(&response, "X-Auth-User-Id"), "1");
+    assert_eq!(
+        header_value(&response, "X-Auth-Subscription"),
+        "non-premium"
+    );
+
+    // proxies treat anything but 401 and 403 as a failure of the auth server itself
+    assert_problem(
+        verify(&server, None, None).await,
+        StatusCode::UNAUTHORIZED,
+        "missing_credentials",
+    )
+    .await;
+    assert_problem(
+        verify(&server, Some("garbage"), None).await,
+        StatusCode::UNAUTHORIZED,
+        "invalid_token",
+    )
+    .await;
+}
+
+#[actix_web::test]
+async fn path_rules_require_subscription_levels() {
+    let config = ForwardAuthConfig {
+        user_header: HeaderName::from_static("x-remote-user"),
+        path_rules: parse_path_rules("/premium=normal-premium, /premium/trial=non-premium")
+            .unwrap(),
+        ..ForwardAuthConfig::default()
+    };
+    let (server, token) = start(config).await;
+
+    let response = verify(&server, Some(&token), Some("/premium/trial/page")).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(header_value(&response, "x-remote-user"), "Zenek");
+    assert!(response.headers().get("X-Auth-User").is_none());
+    assert_problem(
+        verify(&server, Some(&token), Some("/premium/reports?page=2")).await,
+        StatusCode::FORBIDDEN,
+        "insufficient_permissions",
+    )
+    .await;
+
+    let user_id = server.storage.get_user("Zenek").await.unwrap().user_id;
+    server
+        .storage
+        .set_user_subscription_level(user_id, "enterprise-premium")
+        .await
+        .unwrap();
+    let response = verify(&server, Some(&token), Some("/premium/reports")).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        header_value(&response, "X-Auth-Subscription"),
+        "enterprise-premium"
+    );
+
+    assert!(parse_path_rules("/premium=gold").is_err());
+    assert!(parse_path_rules("premium=basic-premium").is_err());
+    assert!(parse_path_rules("").unwrap().is_empty());
+}
+
+#[actix_web::test]
+async fn path_rules_survive_path_tricks() {
+    let config = ForwardAuthConfig {
+        path_rules: parse_path_rules("/premium=normal-premium").unwrap(),
+        ..ForwardAuthConfig::default()
+    };
+    let (server, token) = start(config).await;
+
+    for path in [
+        "/premium",
+        "//premium",
+        "/x/../premium",
+        "/./premium/",
+        "/%70remium",
+        "/premium%2Freports",
+        "/Premium",
+        "/PREMIUM/reports",
+        "\\premium",
+    ] {
+        assert_eq!(
+            verify(&server, Some(&token), Some(path)).await.status(),
+            StatusCode::FORBIDDEN,
+            "{}",
+            path
+        );
+    }
+    // whole segments only
+    for path in [
+        "/premiumfoo",
+        "/premium-free",
+        "/free/premium",
+        "/x/../free",
+    ] {
+        assert_eq!(
+            verify(&server, Some(&token), Some(path)).await.status(),
+            StatusCode::OK,
+            "{}",
+            path
+        );
+    }
+
+    assert_eq!(normalize_path("//a/./b/../c//%64"), "/a/c/d");
+    assert_eq!(normalize_path("/../.."), "/");
+}
+
+#[actix_web::test]
+async fn unauthenticated_requests_are_sent_to_the_login_page() {
+    let config = ForwardAuthConfig {
+        login_url: Some("https://auth.example.com/login".parse().unwrap()),
+        ..ForwardAuthConfig::default()
+    };
+    let (server, token) = start(config).await;
+
+    let response = verify(&server, None, Some("/dashboard?tab=1")).await;
+    assert_eq!(response.status(), StatusCode::FOUND);
+    assert_eq!(
+        header_value(&response, "Location"),
+        "https://auth.example.com/login?rd=https%3A%2F%2Fapp.example.com%2Fdashboard%3Ftab%3D1"
+    );
+    let response = verify(&server, Some("garbage"), None).await;
+    assert_eq!(response.status(), StatusCode::FOUND);
+    assert_eq!(
+        header_value(&response, "Location"),
+        "https://auth.example.com/login"
+    );
+    assert_eq!(
+        verify(&server, Some(&token), None).await.status(),
+        StatusCode::OK
+    );
+}
@@ -0,0 +1,154 @@
+mod common;
+
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use actix_web::http::{header, StatusCode};
+use actix_web::{test, web, App, HttpRequest, HttpResponse, HttpServer};
+use serde::Deserialize;
+
+use auth_server::gateway::routes::{parse_gateway_routes, Gateway};
+
+use common::{assert_problem, bearer, TestServer, USERNAME};
+
+#[derive(Deserialize)]
+struct Echo {
+    method: String,
+    uri: String,
+    headers: BTreeMap<String, String>,
+    body: String,
+}
+
+async fn echo(req: HttpRequest, body: web::Bytes) -> HttpResponse {
+    let headers: BTreeMap<String, String> = req
+        .headers()
+        .iter()
+        .map(|(name, value)| (name.to_string(), value.to_str().unwrap().to_string()))
+        .collect();
+    HttpResponse::Ok()
+        .insert_header(("X-Upstream", "legacy"))
+        .json(serde_json::json!({
+            "method": req.method().as_str(),
+            "uri": req.uri().to_string(),
+            "headers": headers,
+            "body": String::from_utf8(body.to_vec()).unwrap(),
+        }))
+}
+
+fn start_upstream() -> SocketAddr {
+    let server = HttpServer::new(|| App::new().default_service(web::to(echo)))
+        .workers(1)
+        .bind("127.0.0.1:0")
+        .unwrap();
+    let address = server.addrs()[0];
+    actix_web::rt::spawn(server.run());
+    address
+}
+
+async fn start(routes: &str) -> (TestServer, String) {
+    let mut server = TestServer::registered().await;
+    let gateway = Gateway::new(
+        parse_gateway_routes(routes).unwrap(),
+        Duration::from_secs(1),
+    )
+    .unwrap();
+    server.gateway = Some(gateway);
+    let tokens = server.login_for_tokens(USERNAME).await;
+    (server, tokens.access_token)
+}
+
+#[actix_web::test]
+async fn authenticated_requests_reach_the_upstream_with_identity_headers() {
+    let upstream = start_upstream();
+    let (server, token) = start(&format!("/legacy=http://{}/app", upstream)).await;
+
+    let request = bearer(
+        test::TestRequest::post().uri("/legacy/reports?year=2023"),
+        &token,
+    )
+    .insert_header((header::COOKIE, "theme=dark; token=stolen"))
+    .insert_header(("X-Auth-User", "admin"))
+    .insert_header((header::CONNECTION, "X-Internal"))
+    .insert_header(("X-Internal", "secret"))
+    .insert_header((header::CONTENT_LENGTH, "5"))
+    .set_payload("hello");
+    let response = server.send(request).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers().get("X-Upstream").unwrap(), "legacy");
+    let echo: Echo = test::read_body_json(response).await;
+    assert_eq!(echo.method, "POST");
+    assert_eq!(echo.uri, "/app/reports?year=2023");
+    assert_eq!(echo.body, "hello");
+    assert_eq!(echo.headers["x-auth-user"], "Zenek");
+    assert_eq!(echo.headers["x-auth-user-id"], "1");
+    assert_eq!(echo.headers["x-auth-subscription"], "non-premium");
+    assert_eq!(echo.headers["cookie"], "theme=dark");
+    assert!(echo.headers.contains_key("x-forwarded-host"));
+    assert!(!echo.headers.contains_key("authorization"));
+    assert!(!echo.headers.contains_key("x-internal"));
+
+    let request = bearer(test::TestRequest::get().uri("/legacy"), &token);
+    let echo: Echo = test::read_body_json(server.send(request).await).await;
+    assert_eq!(echo.method, "GET");
+    assert_eq!(echo.uri, "/app");
+    assert_eq!(echo.body, "");
+}
+
+#[actix_web::test]
+async fn unauthenticated_requests_never_reach_the_upstream() {
+    let upstream = start_upstream();
+    let (server, _) = start(&format!("/legacy=http://{}", upstream)).await;
+
+    let request = test::TestRequest::get().uri("/legacy/reports");
+    assert_problem(
+        server.send(request).await,
+        StatusCode::BAD_REQUEST,
+        "missing_cookie",
+    )
+    .await;
+    let request = test::TestRequest::get()
+        .uri("/legacy/reports")
+        .insert_header((header::AUTHORIZATION, "Bearer garbage"));
+    assert_problem(
+        server.send(request).await,
+        StatusCode::UNAUTHORIZED,
+        "invalid_token",
+    )
+    .await;
+}
+
+#[actix_web::test]
+async fn unreachable_upstream_is_a_bad_gateway() {
+    let (server, token) = start("/legacy=http://127.0.0.1:1").await;
+
+    let request = bearer(test::TestRequest::get().uri("/legacy/reports"), &token);
+    assert_problem(
+        server.send(request).await,
+        StatusCode::BAD_GATEWAY,
+        "upstream_request_failed",
+    )
+    .await;
+}
+
+#[actix_web::test]
+async fn invalid_routes_are_rejected() {
+    for routes in [
+        "/legacy",
+        "legacy=http://127.0.0.1:9000",
+        "/=http://127.0.0.1:9000",
+        "/auth/legacy=http://127.0.0.1:9000",
+        "/legacy=ftp://127.0.0.1",
+        "/legacy=not a url",
+    ] {
+        assert!(parse_gateway_routes(routes).is_err(), "{}", routes);
+    }
+    let routes =
+        parse_gateway_routes(" /a/=http://a.internal , /b=https://b.internal/app").unwrap();
+    assert_eq!(routes.len(), 2);
+    assert_eq!(routes[0].prefix, "/a");
+    assert_eq!(
+        routes[1].upstream_url("/b/x", "y=1"),
+        "https://b.internal/app/x?y=1"
+    );
+}
@@ -0,0 +1,136 @@
+mod common;
+
+use std::sync::Arc;
+
+use actix_web::cookie::Cookie;
+use actix_web::http::StatusCode;
+use actix_web::test;
+
+use auth_server::migrations::runner::{run_pending_migrations, SQLITE_MIGRATOR};
+use auth_server::storage::sqlite::SqliteStorage;
+
+use common::{
+    assert_problem, login_request, merge_cookies, register_request, response_cookies, sqlite_pool,
+    with_cookies, TestServer, PASSWORD,
+};
+
+// Every one of them is stored and compared as plain text
+const HOSTILE_NAMES: [&str; 6] = [
+    "o'brien",
+    "robert'); DROP TABLE users; --",
+    "\" OR \"1\"=\"1",
+    "admin' --",
+    "x' UNION SELECT password FROM users --",
+    "zażółć 🦀 '\\",
+];
+
+async fn sqlite_server() -> (TestServer, sqlx::Pool<sqlx::Sqlite>) {
+    let pool = sqlite_pool().await;
+    run_pending_migrations(&pool, &SQLITE_MIGRATOR)
+        .await
+        .unwrap();
+    let server = TestServer::with_storage(Arc::new(SqliteStorage::new(pool.clone())));
+    (server, pool)
+}
+
+async fn table_names(pool: &sqlx::Pool<sqlx::Sqlite>) -> Vec<String> {
+    sqlx::query_scalar("SELECT name FROM sqlite_master WHERE type = 'table' ORDER BY name")
+        .fetch_all(pool)
+        .await
+        .unwrap()
+}
+
+fn refresh_request(cookies: &[Cookie<'static>]) -> test::TestRequest {
+    with_cookies(test::TestRequest::post().uri("/auth/refresh"), cookies)
+}
+
+#[actix_web::test]
+async fn hostile_usernames_register_login_and_refresh() {
+    let (server, pool) = sqlite_server().await;
+    let tables = table_names(&pool).await;
+
+    for username in HOSTILE_NAMES {
+        let request = test::TestRequest::post()
+            .uri("/auth/register")
+            .set_json(serde_json::json!({
+                "username": username,
+                "password": PASSWORD,
+                "email": format!("{}@example.com", username),
+            }));
+        assert_eq!(server.send(request).await.status(), StatusCode::OK);
+        assert_problem(
+            server.send(register_request(username)).await,
+            StatusCode::CONFLICT,
+            "username_taken",
+        )
+        .await;
+
+        let response = server.send(login_request(username)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let cookies = response_cookies(&response);
+        let response = server.send(refresh_request(&cookies)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let cookies = merge_cookies(cookies, response_cookies(&response));
+        assert_eq!(
+            server.send(refresh_request(&cookies)).await.status(),
+            StatusCode::OK
+        );
+    }
+
+    let mut usernames: Vec<String> = sqlx::query_scalar("SELECT username FROM users")
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+    usernames.sort();
+    let mut expected: Vec<String> = HOSTILE_NAMES.iter().map(|name| name.to_string()).collect();
+    expected.sort();
+    assert_eq!(usernames, expected);
+    assert_eq!(table_names(&pool).await, tables);
+}
+
+#[actix_web::test]
+async fn hostile_login_matches_no_user() {
+    let (server, pool) = sqlite_server().await;
+    server.register("zenek").await;
+
+    for username in ["zenek' --", "' OR '1'='1", "' OR 1=1; DROP TABLE users; --"] {
+        assert_problem(
+            server.send(login_request(username)).await,
+            StatusCode::UNAUTHORIZED,
+            "invalid_credentials",
+        )
+        .await;
+    }
+    assert!(table_names(&pool).await.contains(&"users".to_string()));
+    assert_eq!(server.login("zenek").await.len(), 3);
+}
+
+#[actix_web::test]
+async fn hostile_session_cookie_finds_no_session() {
+    let (server, pool) = sqlite_server().await;
+    server.register("zenek").await;
+    let cookies = server.login("zenek").await;
+    let tables = table_names(&pool).await;
+
+    for session in [
+        "' OR '1'='1",
+        "x' UNION SELECT user_id FROM users --",
+        "'); DELETE FROM sessions; --",
+        "ünïcødé'\"",
+    ] {
+        let cookies = merge_cookies(cookies.clone(), vec![Cookie::new("session", session)]);
+        assert_problem(
+            server.send(refresh_request(&cookies)).await,
+            StatusCode::UNAUTHORIZED,
+            "session_not_found",
+        )
+        .await;
+    }
+
+    // the real session survived
+    assert_eq!(
+        server.send(refresh_request(&cookies)).await.status(),
+        StatusCode::OK
+    );
+    assert_eq!(table_names(&pool).await, tables);
+}
@@ -0,0 +1,307 @@
+mod common;
+
+use actix_web::cookie::Cookie;
+use actix_web::http::StatusCode;
+use actix_web::{test, web};
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::{decode, decode_header, encode, Algorithm, DecodingKey, Validation};
+use openssl::ec::{EcGroup, EcKey};
+use openssl::nid::Nid;
+use openssl::pkey::PKey;
+use openssl::rsa::Rsa;
+
+use auth_server::auth::token::access_token::Claims;
+use auth_server::auth::token::constants::{
+    ACCESS_TOKEN_AUDIENCE, ACCESS_TOKEN_TYPE, ID_TOKEN_TYPE,
+};
+use auth_server::auth::token::key_encryption::{KeyEncryption, ENCRYPTED_KEY_PREFIX};
+use auth_server::auth::token::keyring::{
+    generate_signing_key, import_signing_key, promote_signing_key, retire_signing_key, Keyring,
+};
+use auth_server::auth::token::signing_key::{load_token_signer, SigningKey, TokenSigner};
+use auth_server::startup::environment_constants::get_environment_constants;
+use auth_server::storage::memory::MemoryStorage;
+use auth_server::storage::{KeyStore, StoredSigningKey};
+
+use common::{cookie, key_encryption, merge_cookies, with_cookies, TestServer, USERNAME};
+
+fn private_key_pem(algorithm: Algorithm) -> Vec<u8> {
+    match algorithm {
+        Algorithm::RS256 => Rsa::generate(2048).unwrap().private_key_to_pem().unwrap(),
+        Algorithm::ES256 => {
+            let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+            EcKey::generate(&group)
+                .unwrap()
+                .private_key_to_pem()
+                .unwrap()
+        }
+        Algorithm::EdDSA => PKey::generate_ed25519()
+            .unwrap()
+            .private_key_to_pem_pkcs8()
+            .unwrap(),
+        _ => unreachable!(),
+    }
+}
+
+// Registers `USERNAME` with a server signing through `keyring`
+async fn keyring_server(mut server: TestServer, keyring: Keyring) -> TestServer {
+    server.signer = web::Data::new(TokenSigner::Keyring(keyring));
+    server.register(USERNAME).await;
+    server
+}
+
+async fn jwk_set(server: &TestServer) -> JwkSet {
+    let request = test::TestRequest::get().uri("/.well-known/jwks.json");
+    test::read_body_json(server.send(request).await).await
+}
+
+async fn logout_status(server: &TestServer, cookies: &[Cookie<'static>]) -> StatusCode {
+    let request = with_cookies(test::TestRequest::get().uri("/auth/logout"), cookies);
+    server.send(request).await.status()
+}
+
+fn access_token(cookies: &[Cookie<'static>]) -> String {
+    cookie(cookies, "token").value().to_string()
+}
+
+fn with_access_token(cookies: Vec<Cookie<'static>>, token: String) -> Vec<Cookie<'static>> {
+    merge_cookies(cookies, vec![Cookie::new("token", token)])
+}
+
+#[actix_web::test]
+async fn access_tokens_verify_against_published_keys() {
+    for algorithm in [Algorithm::RS256, Algorithm::ES256, Algorithm::EdDSA] {
+        let pem = String::from_utf8(private_key_pem(algorithm)).unwrap();
+        let key_id = SigningKey::from_pem(algorithm, pem.as_bytes(), None)
+            .unwrap()
+            .key_id;
+        let server = TestServer::new();
+        import_signing_key(
+            server.storage.as_ref(),
+            &key_encryption(),
+            algorithm,
+            &pem,
+            None,
+        )
+        .await
+        .unwrap();
+        let keyring = Keyring::load(server.storage.as_ref(), key_encryption())
+            .await
+            .unwrap();
+        let server = keyring_server(server, keyring).await;
+        let cookies = server.login(USERNAME).await;
+        let token = access_token(&cookies);
+
+        let jwk_set = jwk_set(&server).await;
+        let header = decode_header(&token).unwrap();
+        assert_eq!(header.alg, algorithm);
+        assert_eq!(header.kid.as_deref(), Some(key_id.as_str()));
+        assert_eq!(header.typ.as_deref(), Some(ACCESS_TOKEN_TYPE));
+        let jwk = jwk_set.find(&key_id).unwrap();
+        let mut validation = Validation::new(algorithm);
+        validation.set_audience(&[ACCESS_TOKEN_AUDIENCE]);
+        let claims = decode::<Claims>(&token, &DecodingKey::from_jwk(jwk).unwrap(), &validation)
+            .unwrap()
+            .claims;
+        assert_eq!(claims.username, USERNAME);
+
+        // the server itself accepts its asymmetric tokens
+        assert_eq!(logout_status(&server, &cookies).await, StatusCode::OK);
+    }
+}
+
+#[actix_web::test]
+async fn rotation_keeps_old_tokens_valid_until_retirement() {
+    let server = TestServer::new();
+    let storage = server.storage.clone();
+    let old_key = generate_signing_key(storage.as_ref(), &key_encryption(), Algorithm::EdDSA, true)
+        .await
+        .unwrap();
+    let keyring = Keyring::load(storage.as_ref(), key_encryption())
+        .await
+        .unwrap();
+    let server = keyring_server(server, keyring).await;
+    let old_cookies = server.login(USERNAME).await;
+
+    // a pending key is published before it signs anything
+    let new_key =
+        generate_signing_key(storage.as_ref(), &key_encryption(), Algorithm::ES256, false)
+            .await
+            .unwrap();
+    let TokenSigner::Keyring(keyring) = server.signer.get_ref() else {
+        unreachable!()
+    };
+    keyring.reload(storage.as_ref()).await.unwrap();
+    assert!(jwk_set(&server).await.find(&new_key.key_id).is_some());
+    let header = decode_header(&access_token(&server.login(USERNAME).await)).unwrap();
+    assert_eq!(header.kid.as_deref(), Some(old_key.key_id.as_str()));
+
+    promote_signing_key(storage.as_ref(), &new_key.key_id)
+        .await
+        .unwrap();
+    keyring.reload(storage.as_ref()).await.unwrap();
+    let new_cookies = server.login(USERNAME).await;
+    let header = decode_header(&access_token(&new_cookies)).unwrap();
+    assert_eq!(header.kid.as_deref(), Some(new_key.key_id.as_str()));
+
+    // tokens signed before the promotion are still accepted
+    let cookies = with_access_token(new_cookies, access_token(&old_cookies));
+    assert_eq!(logout_status(&server, &cookies).await, StatusCode::OK);
+
+    let now = chrono::Utc::now().timestamp();
+    retire_signing_key(storage.as_ref(), &old_key.key_id, now)
+        .await
+        .unwrap();
+    assert!(retire_signing_key(storage.as_ref(), &new_key.key_id, now)
+        .await
+        .is_err());
+    keyring.reload(storage.as_ref()).await.unwrap();
+    assert!(jwk_set(&server).await.find(&old_key.key_id).is_none());
+
+    let session_cookies = server.login(USERNAME).await;
+    let cookies = with_access_token(session_cookies, access_token(&old_cookies));
+    assert_eq!(
+        logout_status(&server, &cookies).await,
+        StatusCode::UNAUTHORIZED
+    );
+}
+
+// Signed with the published key, but meant for something else than this server
+#[actix_web::test]
+async fn tokens_of_another_type_or_audience_are_rejected() {
+    let server = TestServer::new();
+    generate_signing_key(
+        server.storage.as_ref(),
+        &key_encryption(),
+        Algorithm::ES256,
+        true,
+    )
+    .await
+    .unwrap();
+    let keyring = Keyring::load(server.storage.as_ref(), key_encryption())
+        .await
+        .unwrap();
+    let server = keyring_server(server, keyring).await;
+    let cookies = server.login(USERNAME).await;
+    let mut validation = Validation::default();
+    validation.insecure_disable_signature_validation();
+    let claims = decode::<Claims>(
+        &access_token(&cookies),
+        &DecodingKey::from_secret(&[]),
+        &validation,
+    )
+    .unwrap()
+    .claims;
+
+    let TokenSigner::Keyring(keyring) = server.signer.get_ref() else {
+        unreachable!()
+    };
+    let (header, encoding_key) = keyring.signing_key().unwrap();
+    let sign = |typ: &str, aud: &str| {
+        let mut header = header.clone();
+        header.typ = Some(typ.to_string());
+        let claims = Claims {
+            aud: aud.to_string(),
+            ..claims.clone()
+        };
+        encode(&header, &claims, &encoding_key).unwrap()
+    };
+    for token in [
+        sign(ID_TOKEN_TYPE, ACCESS_TOKEN_AUDIENCE),
+        sign(ACCESS_TOKEN_TYPE, "grafana"),
+    ] {
+        let cookies = with_access_token(cookies.clone(), token);
+        assert_eq!(
+            logout_status(&server, &cookies).await,
+            StatusCode::UNAUTHORIZED
+        );
+    }
+    let cookies = with_access_token(cookies, sign(ACCESS_TOKEN_TYPE, ACCESS_TOKEN_AUDIENCE));
+    assert_eq!(logout_status(&server, &cookies).await, StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn per_user_secrets_publish_no_keys() {
+    assert!(jwk_set(&TestServer::new()).await.keys.is_empty());
+}
+
+#[actix_web::test]
+async fn key_must_match_algorithm() {
+    let pem = private_key_pem(Algorithm::EdDSA);
+    assert!(SigningKey::from_pem(Algorithm::RS256, &pem, None).is_err());
+    assert!(SigningKey::from_pem(Algorithm::ES256, &pem, None).is_err());
+}
+
+#[actix_web::test]
+async fn private_keys_are_encrypted_at_rest() {
+    let storage = MemoryStorage::new();
+    let generated = generate_signing_key(&storage, &key_encryption(), Algorithm::ES256, true)
+        .await
+        .unwrap();
+    let stored = storage.get_signing_keys().await.unwrap().remove(0);
+    assert!(stored.private_key.starts_with(ENCRYPTED_KEY_PREFIX));
+    assert!(!stored.private_key.contains("PRIVATE KEY"));
+
+    let keyring = Keyring::load(&storage, key_encryption()).await.unwrap();
+    assert!(keyring.decoding_key(&generated.key_id).is_some());
+
+    // neither another key nor no key at all opens it, and the server does not start
+    let other_key = KeyEncryption::Aes256Gcm([8; 32]);
+    let error = Keyring::load(&storage, other_key).await.err().unwrap();
+    assert!(error.to_string().contains(&generated.key_id));
+    assert!(Keyring::load(&storage, KeyEncryption::Plaintext)
+        .await
+        .is_err());
+    let mut constants = get_environment_constants();
+    constants.jwt_signing_algorithm = "ES256".to_string();
+    constants.jwt_private_key_path = String::new();
+    constants.signing_key_encryption_key =
+        base64::Engine::encode(&base64::engine::general_purpose::STANDARD, [8u8; 32]);
+    assert!(load_token_signer(&constants, &storage).await.is_err());
+    assert_eq!(storage.get_signing_keys().await.unwrap().len(), 1);
+
+    // a sealed key copied under another id does not open either
+    storage
+        .store_signing_key(&StoredSigningKey {
+            key_id: "copy".to_string(),
+            ..stored
+        })
+        .await
+        .unwrap();
+    assert!(Keyring::load(&storage, key_encryption()).await.is_err());
+}
+
+#[actix_web::test]
+async fn keys_stored_before_encryption_still_load() {
+    let storage = MemoryStorage::new();
+    let generated =
+        generate_signing_key(&storage, &KeyEncryption::Plaintext, Algorithm::EdDSA, true)
+            .await
+            .unwrap();
+    assert!(generated.private_key.contains("PRIVATE KEY"));
+    let keyring = Keyring::load(&storage, key_encryption()).await.unwrap();
+    assert!(keyring.decoding_key(&generated.key_id).is_some());
+}
+
+#[actix_web::test]
+async fn plaintext_keys_need_an_explicit_opt_in() {
+    let mut constants = get_environment_constants();
+    constants.signing_key_encryption_key = String::new();
+    constants.signing_keys_unencrypted = false;
+    assert!(KeyEncryption::from_environment(&constants).is_err());
+
+    constants.signing_keys_unencrypted = true;
+    assert!(matches!(
+        KeyEncryption::from_environment(&constants),
+        Ok(KeyEncryption::Plaintext)
+    ));
+
+    constants.signing_key_encryption_key = "too short".to_string();
+    assert!(KeyEncryption::from_environment(&constants).is_err());
+    constants.signing_key_encryption_key =
+        base64::Engine::encode(&base64::engine::general_purpose::STANDARD, [1u8; 32]);
+    assert!(matches!(
+        KeyEncryption::from_environment(&constants),
+        Ok(KeyEncryption::Aes256Gcm(_))
+    ));
+}
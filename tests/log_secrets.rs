@@ -0,0 +1,65 @@
+mod common;
+
+use std::sync::Mutex;
+
+use actix_web::http::StatusCode;
+use actix_web::test;
+use log::{Log, Metadata, Record};
+
+use common::{cookie, with_cookies, TestServer, PASSWORD, USERNAME};
+
+static LOGGED: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+struct CapturingLogger;
+
+impl Log for CapturingLogger {
+    fn enabled(&self, _metadata: &Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &Record) {
+        LOGGED.lock().unwrap().push(record.args().to_string());
+    }
+
+    fn flush(&self) {}
+}
+
+// The logger is process wide, so this binary holds a single test
+#[actix_web::test]
+async fn secrets_never_reach_the_log() {
+    log::set_boxed_logger(Box::new(CapturingLogger)).unwrap();
+    log::set_max_level(log::LevelFilter::Trace);
+
+    let server = TestServer::registered().await;
+    let cookies = server.login(USERNAME).await;
+    let request = with_cookies(test::TestRequest::get().uri("/auth/logout"), &cookies);
+    assert_eq!(server.send(request).await.status(), StatusCode::OK);
+
+    let user = server.storage.get_user(USERNAME).await.unwrap();
+    let mut secrets = vec![
+        PASSWORD.to_string(),
+        user.password,
+        server
+            .storage
+            .get_secret_access_key(user.user_id)
+            .await
+            .unwrap(),
+        server
+            .storage
+            .get_secret_refresh_key(user.user_id)
+            .await
+            .unwrap(),
+    ];
+    for name in ["token", "refresh_token", "session"] {
+        secrets.push(cookie(&cookies, name).value().to_string());
+    }
+
+    let logged = LOGGED.lock().unwrap();
+    // validating the access token logs its claims
+    assert!(logged.iter().any(|line| line.contains(USERNAME)));
+    for line in logged.iter() {
+        for secret in &secrets {
+            assert!(!line.contains(secret.as_str()), "secret logged: {}", line);
+        }
+    }
+}
@@ -0,0 +1,140 @@
+mod common;
+
+use auth_server::migrations::runner::{
+    get_migration_status, latest_migration_version, migrate_to_version, run_pending_migrations,
+    SQLITE_MIGRATOR,
+};
+use auth_server::migrations::status::MigrationState;
+use auth_server::storage::sqlite::SqliteStorage;
+use auth_server::storage::{NewSession, SessionStore};
+
+use common::sqlite_pool;
+
+fn migration_count() -> usize {
+    SQLITE_MIGRATOR
+        .iter()
+        .filter(|migration| !migration.migration_type.is_down_migration())
+        .count()
+}
+
+#[actix_web::test]
+async fn migrations_apply_once() {
+    let pool = sqlite_pool().await;
+    assert_eq!(
+        run_pending_migrations(&pool, &SQLITE_MIGRATOR)
+            .await
+            .unwrap(),
+        migration_count()
+    );
+    // a second run, like the next server start, finds nothing to do
+    assert_eq!(
+        run_pending_migrations(&pool, &SQLITE_MIGRATOR)
+            .await
+            .unwrap(),
+        0
+    );
+
+    let statuses = get_migration_status(&pool, &SQLITE_MIGRATOR).await.unwrap();
+    assert_eq!(statuses.len(), migration_count());
+    assert!(statuses
+        .iter()
+        .all(|status| status.state == MigrationState::Applied));
+    assert_eq!(
+        statuses.last().map(|status| status.version),
+        latest_migration_version(&SQLITE_MIGRATOR)
+    );
+}
+
+#[actix_web::test]
+async fn status_reports_pending_migrations() {
+    let pool = sqlite_pool().await;
+    let statuses = get_migration_status(&pool, &SQLITE_MIGRATOR).await.unwrap();
+    assert_eq!(statuses.len(), migration_count());
+    assert!(statuses
+        .iter()
+        .all(|status| status.state == MigrationState::Pending));
+
+    // the lines `migrate status` prints
+    let first = statuses[0].to_string();
+    assert!(first.starts_with("   1\tpending"), "{}", first);
+    assert!(first.ends_with("initial schema"), "{}", first);
+}
+
+#[actix_web::test]
+async fn migrate_up_stops_at_the_requested_version() {
+    let pool = sqlite_pool().await;
+    assert_eq!(
+        migrate_to_version(&pool, &SQLITE_MIGRATOR, 2)
+            .await
+            .unwrap(),
+        2
+    );
+    // the target is already reached
+    assert_eq!(
+        migrate_to_version(&pool, &SQLITE_MIGRATOR, 2)
+            .await
+            .unwrap(),
+        0
+    );
+
+    for status in get_migration_status(&pool, &SQLITE_MIGRATOR).await.unwrap() {
+        let expected = if status.version <= 2 {
+            MigrationState::Applied
+        } else {
+            MigrationState::Pending
+        };
+        assert_eq!(status.state, expected, "{}", status);
+    }
+
+    assert!(migrate_to_version(&pool, &SQLITE_MIGRATOR, 9999)
+        .await
+        .is_err());
+    assert_eq!(
+        run_pending_migrations(&pool, &SQLITE_MIGRATOR)
+            .await
+            .unwrap(),
+        migration_count() - 2
+    );
+}
+
+#[actix_web::test]
+async fn session_ids_survive_the_autoincrement_rebuild() {
+    let pool = sqlite_pool().await;
+    migrate_to_version(&pool, &SQLITE_MIGRATOR, 11)
+        .await
+        .unwrap();
+    sqlx::query(
+        "INSERT INTO session_table (session_uuid, user_id, session_id, created_at) \
+         VALUES ('uuid', 1, 7, 1000)",
+    )
+    .execute(&pool)
+    .await
+    .unwrap();
+
+    run_pending_migrations(&pool, &SQLITE_MIGRATOR)
+        .await
+        .unwrap();
+    let storage = SqliteStorage::new(pool);
+    let session = storage.get_session("uuid").await.unwrap();
+    assert_eq!((session.session_id, session.created_at), (7, 1000));
+
+    storage.drop_session("uuid").await.unwrap();
+    let session_uuid = storage
+        .create_session(&NewSession {
+            user_id: 1,
+            created_at: 2000,
+            expires_at: 3000,
+            idle_timeout: 500,
+            ip_address: None,
+            user_agent: None,
+            client_id: None,
+            scope: None,
+            auth_time: None,
+        })
+        .await
+        .unwrap();
+    assert_eq!(
+        storage.get_session(&session_uuid).await.unwrap().session_id,
+        8
+    );
+}
@@ -0,0 +1,116 @@
+mod common;
+
+use actix_web::cookie::Cookie;
+use actix_web::dev::ServiceResponse;
+use actix_web::http::StatusCode;
+use actix_web::test;
+use jsonwebtoken::{decode, DecodingKey, Validation};
+
+use auth_server::auth::token::access_token::Claims;
+use auth_server::auth::token::constants::REFRESH_ALGORITHM;
+use auth_server::auth::token::refresh_token::RefreshClaims;
+
+use common::{
+    assert_problem, cookie, merge_cookies, response_cookies, with_cookies, TestServer, USERNAME,
+};
+
+// Cookie jar of one device
+struct Device {
+    cookies: Vec<Cookie<'static>>,
+}
+
+impl Device {
+    fn cookie(&self, name: &str) -> String {
+        cookie(&self.cookies, name).value().to_string()
+    }
+
+    fn request(&self, request: test::TestRequest) -> test::TestRequest {
+        with_cookies(request, &self.cookies)
+    }
+
+    fn update(&mut self, response: &ServiceResponse) {
+        let cookies = std::mem::take(&mut self.cookies);
+        self.cookies = merge_cookies(cookies, response_cookies(response));
+    }
+
+    // Both tokens of the device have to point at the session cookie of the device
+    fn assert_tokens_bound_to_own_session(&self) {
+        let mut validation = Validation::new(REFRESH_ALGORITHM);
+        validation.insecure_disable_signature_validation();
+        let refresh_claims = decode::<RefreshClaims>(
+            &self.cookie("refresh_token"),
+            &DecodingKey::from_secret(&[]),
+            &validation,
+        )
+        .unwrap()
+        .claims;
+        let mut validation = Validation::default();
+        validation.insecure_disable_signature_validation();
+        let claims = decode::<Claims>(
+            &self.cookie("token"),
+            &DecodingKey::from_secret(&[]),
+            &validation,
+        )
+        .unwrap()
+        .claims;
+
+        assert_eq!(refresh_claims.session, self.cookie("session"));
+        assert_eq!(claims.session, self.cookie("session"));
+    }
+}
+
+async fn login(server: &TestServer) -> Device {
+    Device {
+        cookies: server.login(USERNAME).await,
+    }
+}
+
+async fn refresh(server: &TestServer, device: &mut Device) -> ServiceResponse {
+    let response = server
+        .send(device.request(test::TestRequest::post().uri("/auth/refresh")))
+        .await;
+    if response.status() == StatusCode::OK {
+        device.update(&response);
+    }
+    response
+}
+
+async fn logout(server: &TestServer, device: &Device) -> StatusCode {
+    server
+        .send(device.request(test::TestRequest::get().uri("/auth/logout")))
+        .await
+        .status()
+}
+
+#[actix_web::test]
+async fn every_device_keeps_its_own_session() {
+    let server = TestServer::registered().await;
+    let mut laptop = login(&server).await;
+    let mut phone = login(&server).await;
+    assert_ne!(laptop.cookie("session"), phone.cookie("session"));
+    laptop.assert_tokens_bound_to_own_session();
+    phone.assert_tokens_bound_to_own_session();
+
+    // the refresh chains are independent, rotating one does not touch the other
+    assert_eq!(refresh(&server, &mut laptop).await.status(), StatusCode::OK);
+    assert_eq!(refresh(&server, &mut phone).await.status(), StatusCode::OK);
+    assert_eq!(refresh(&server, &mut laptop).await.status(), StatusCode::OK);
+    let mut tablet = login(&server).await;
+    assert_eq!(refresh(&server, &mut phone).await.status(), StatusCode::OK);
+    assert_eq!(refresh(&server, &mut laptop).await.status(), StatusCode::OK);
+    for device in [&laptop, &phone, &tablet] {
+        device.assert_tokens_bound_to_own_session();
+    }
+
+    assert_eq!(logout(&server, &phone).await, StatusCode::OK);
+    let response = refresh(&server, &mut phone).await;
+    assert_problem(response, StatusCode::UNAUTHORIZED, "session_not_found").await;
+
+    // the remaining devices are not logged out with the phone
+    assert_eq!(refresh(&server, &mut laptop).await.status(), StatusCode::OK);
+    assert_eq!(refresh(&server, &mut tablet).await.status(), StatusCode::OK);
+    assert_eq!(logout(&server, &laptop).await, StatusCode::OK);
+    assert_eq!(refresh(&server, &mut tablet).await.status(), StatusCode::OK);
+    tablet.assert_tokens_bound_to_own_session();
+    assert_eq!(logout(&server, &tablet).await, StatusCode::OK);
+}
@@ -0,0 +1,339 @@
+mod common;
+
+use actix_web::cookie::Cookie;
+use actix_web::dev::ServiceResponse;
+use actix_web::http::{header, StatusCode};
+use actix_web::{test, web, HttpResponse};
+use url::Url;
+
+use auth_server::auth::client::create_client;
+use auth_server::auth::guard::require_auth::RequireAuth;
+use auth_server::auth::oauth::config::OAuthConfig;
+use auth_server::auth::oauth::pkce::code_challenge;
+use auth_server::auth::token::delivery::TokenResponse;
+use auth_server::auth::token::introspection::Introspection;
+use auth_server::errors::oauth_error::OAuthErrorBody;
+
+use common::{assert_problem, bearer, cookie, TestServer, USERNAME};
+
+const REDIRECT_URI: &str = "https://photos.example.com/callback";
+const CODE_VERIFIER: &str = "dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk";
+
+// Registers and logs in a user, registers a client, returns the session cookie of the user
+// and the client credentials
+async fn setup() -> (TestServer, Cookie<'static>, (String, String)) {
+    let server = TestServer::registered().await;
+    let session_cookie = cookie(&server.login(USERNAME).await, "session");
+    let (client, client_secret) = create_client(
+        server.storage.as_ref(),
+        "photos",
+        &[REDIRECT_URI.to_string()],
+        &[],
+        true,
+    )
+    .await
+    .unwrap();
+    (server, session_cookie, (client.client_id, client_secret))
+}
+
+fn authorization_query(client_id: &str, scope: &str) -> Vec<(&'static str, String)> {
+    vec![
+        ("response_type", "code".to_string()),
+        ("client_id", client_id.to_string()),
+        ("redirect_uri", REDIRECT_URI.to_string()),
+        ("scope", scope.to_string()),
+        ("state", "xyz".to_string()),
+        ("code_challenge", code_challenge(CODE_VERIFIER)),
+        ("code_challenge_method", "S256".to_string()),
+    ]
+}
+
+fn authorize_uri(query: &[(&str, String)]) -> String {
+    let mut uri = Url::parse("http://localhost/oauth/authorize").unwrap();
+    uri.query_pairs_mut().extend_pairs(query);
+    format!("/oauth/authorize?{}", uri.query().unwrap())
+}
+
+// Query of the redirect the response points to
+fn redirect_query(response: &ServiceResponse) -> Vec<(String, String)> {
+    assert_eq!(response.status(), StatusCode::FOUND);
+    let location = response.headers().get(header::LOCATION).unwrap();
+    let location = Url::parse(location.to_str().unwrap()).unwrap();
+    assert!(location.as_str().starts_with(REDIRECT_URI));
+    location.query_pairs().into_owned().collect()
+}
+
+fn parameter<'a>(query: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    query
+        .iter()
+        .find(|(key, _)| key == name)
+        .map(|(_, value)| value.as_str())
+}
+
+// Goes through the consent page and returns the issued authorization code
+async fn authorize_with_consent(
+    server: &TestServer,
+    session_cookie: &Cookie<'static>,
+    client_id: &str,
+    scope: &str,
+) -> String {
+    let query = authorization_query(client_id, scope);
+    let request = test::TestRequest::get()
+        .uri(&authorize_uri(&query))
+        .cookie(session_cookie.clone());
+    let response = server.send(request).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let page = String::from_utf8(test::read_body(response).await.to_vec()).unwrap();
+    assert!(page.contains("photos asks for access"));
+
+    let mut form = query;
+    form.push(("consent", "allow".to_string()));
+    let request = test::TestRequest::post()
+        .uri("/oauth/authorize")
+        .cookie(session_cookie.clone())
+        .set_form(&form);
+    let response = server.send(request).await;
+    let query = redirect_query(&response);
+    assert_eq!(parameter(&query, "state"), Some("xyz"));
+    parameter(&query, "code").unwrap().to_string()
+}
+
+async fn exchange(
+    server: &TestServer,
+    (client_id, client_secret): &(String, String),
+    code: &str,
+    code_verifier: &str,
+) -> ServiceResponse {
+    let request = test::TestRequest::post().uri("/oauth/token").set_form([
+        ("grant_type", "authorization_code"),
+        ("code", code),
+        ("redirect_uri", REDIRECT_URI),
+        ("code_verifier", code_verifier),
+        ("client_id", client_id),
+        ("client_secret", client_secret),
+    ]);
+    server.send(request).await
+}
+
+#[actix_web::test]
+async fn code_is_exchanged_once_for_scoped_tokens() {
+    let (server, session_cookie, client) = setup().await;
+    let code = authorize_with_consent(&server, &session_cookie, &client.0, "photos:read").await;
+
+    let response = exchange(&server, &client, &code, CODE_VERIFIER).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.headers().get(header::CACHE_CONTROL).unwrap(),
+        "no-store"
+    );
+    let tokens: TokenResponse = test::read_body_json(response).await;
+    assert_eq!(tokens.scope.as_deref(), Some("photos:read"));
+
+    let request = test::TestRequest::post().uri("/auth/introspect").set_form([
+        ("token", tokens.access_token.as_str()),
+        ("client_id", &client.0),
+        ("client_secret", &client.1),
+    ]);
+    let introspection: Introspection = test::read_body_json(server.send(request).await).await;
+    assert!(introspection.active);
+    assert_eq!(introspection.username.as_deref(), Some("Zenek"));
+    assert_eq!(introspection.scope.as_deref(), Some("photos:read"));
+    let user = server.storage.get_user("Zenek").await.unwrap();
+    let sessions = server
+        .storage
+        .get_user_sessions(user.user_id)
+        .await
+        .unwrap();
+    assert_eq!(sessions[1].client_id.as_deref(), Some(client.0.as_str()));
+    assert_eq!(sessions[1].user_agent.as_deref(), Some("photos"));
+
+    let response = exchange(&server, &client, &code, CODE_VERIFIER).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let error: OAuthErrorBody = test::read_body_json(response).await;
+    assert_eq!(error.error, "invalid_grant");
+
+    // the consent is remembered, a narrower request skips the page
+    let query = authorization_query(&client.0, "");
+    let request = test::TestRequest::get()
+        .uri(&authorize_uri(&query))
+        .cookie(session_cookie);
+    let query = redirect_query(&server.send(request).await);
+    assert!(parameter(&query, "code").is_some());
+}
+
+#[actix_web::test]
+async fn pkce_and_redirect_uri_are_enforced() {
+    let (server, session_cookie, client) = setup().await;
+    let code = authorize_with_consent(&server, &session_cookie, &client.0, "").await;
+    let other_verifier = "x".repeat(43);
+    let response = exchange(&server, &client, &code, &other_verifier).await;
+    let error: OAuthErrorBody = test::read_body_json(response).await;
+    assert_eq!(error.error, "invalid_grant");
+    // a failed attempt does not burn the code
+    let response = exchange(&server, &client, &code, CODE_VERIFIER).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let mut query = authorization_query(&client.0, "");
+    query.retain(|(name, _)| *name != "code_challenge_method");
+    let request = test::TestRequest::get()
+        .uri(&authorize_uri(&query))
+        .cookie(session_cookie.clone());
+    let query = redirect_query(&server.send(request).await);
+    assert_eq!(parameter(&query, "error"), Some("invalid_request"));
+    assert_eq!(parameter(&query, "state"), Some("xyz"));
+
+    // errors are never sent to a redirect uri the client did not register
+    let mut query = authorization_query(&client.0, "");
+    query[2].1 = "https://attacker.example.com/callback".to_string();
+    let request = test::TestRequest::get()
+        .uri(&authorize_uri(&query))
+        .cookie(session_cookie);
+    let response = server.send(request).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert!(response.headers().get(header::LOCATION).is_none());
+}
+
+#[actix_web::test]
+async fn users_without_session_log_in_and_may_deny() {
+    let (mut server, session_cookie, client) = setup().await;
+    let query = authorization_query(&client.0, "photos:read");
+
+    let request = test::TestRequest::get().uri(&authorize_uri(&query));
+    let response = server.send(request).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    server.oauth = web::Data::new(OAuthConfig {
+        login_url: Some(Url::parse("https://example.com/login").unwrap()),
+        ..OAuthConfig::default()
+    });
+    let request = test::TestRequest::get().uri(&authorize_uri(&query));
+    let response = server.send(request).await;
+    assert_eq!(response.status(), StatusCode::FOUND);
+    let location = response.headers().get(header::LOCATION).unwrap();
+    let location = Url::parse(location.to_str().unwrap()).unwrap();
+    let (_, rd) = location.query_pairs().find(|(key, _)| key == "rd").unwrap();
+    assert!(rd.contains("/oauth/authorize?response_type=code"));
+
+    let mut form = query;
+    form.push(("consent", "deny".to_string()));
+    let request = test::TestRequest::post()
+        .uri("/oauth/authorize")
+        .cookie(session_cookie)
+        .set_form(&form);
+    let query = redirect_query(&server.send(request).await);
+    assert_eq!(parameter(&query, "error"), Some("access_denied"));
+    assert!(parameter(&query, "code").is_none());
+}
+
+#[actix_web::test]
+async fn refresh_tokens_only_work_for_their_client() {
+    let (server, session_cookie, client) = setup().await;
+    let code = authorize_with_consent(&server, &session_cookie, &client.0, "photos:read").await;
+    let response = exchange(&server, &client, &code, CODE_VERIFIER).await;
+    let tokens: TokenResponse = test::read_body_json(response).await;
+
+    let (other_client, other_secret) =
+        create_client(server.storage.as_ref(), "other", &[], &[], false)
+            .await
+            .unwrap();
+    let request = test::TestRequest::post().uri("/oauth/token").set_form([
+        ("grant_type", "refresh_token"),
+        ("refresh_token", tokens.refresh_token.as_str()),
+        ("client_id", &other_client.client_id),
+        ("client_secret", &other_secret),
+    ]);
+    let error: OAuthErrorBody = test::read_body_json(server.send(request).await).await;
+    assert_eq!(error.error, "invalid_grant");
+
+    let request = test::TestRequest::post().uri("/oauth/token").set_form([
+        ("grant_type", "refresh_token"),
+        ("refresh_token", tokens.refresh_token.as_str()),
+        ("client_id", &client.0),
+        ("client_secret", &client.1),
+    ]);
+    let response = server.send(request).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let refreshed: TokenResponse = test::read_body_json(response).await;
+    assert_eq!(refreshed.scope.as_deref(), Some("photos:read"));
+    assert_ne!(refreshed.refresh_token, tokens.refresh_token);
+}
+
+// Tokens granted to a client through consent
+async fn client_tokens(
+    server: &TestServer,
+    session_cookie: &Cookie<'static>,
+    client: &(String, String),
+) -> TokenResponse {
+    let code = authorize_with_consent(server, session_cookie, &client.0, "photos:read").await;
+    let response = exchange(server, client, &code, CODE_VERIFIER).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    test::read_body_json(response).await
+}
+
+#[actix_web::test]
+async fn client_tokens_are_refused_by_first_party_endpoints() {
+    let (server, session_cookie, client) = setup().await;
+    let tokens = client_tokens(&server, &session_cookie, &client).await;
+
+    let requests = [
+        test::TestRequest::get().uri("/auth/logout"),
+        test::TestRequest::get().uri("/auth/sessions"),
+        test::TestRequest::post().uri("/auth/sessions/revoke-others"),
+        test::TestRequest::delete().uri("/auth/sessions/1"),
+        test::TestRequest::get().uri("/auth/verify"),
+        test::TestRequest::get().uri("/reports"),
+    ];
+    for request in requests {
+        let response = server
+            .send_with(bearer(request, &tokens.access_token), |cfg| {
+                cfg.service(
+                    web::scope("/reports")
+                        .wrap(RequireAuth::new())
+                        .route("", web::get().to(HttpResponse::Ok)),
+                );
+            })
+            .await;
+        assert_problem(response, StatusCode::FORBIDDEN, "insufficient_permissions").await;
+    }
+
+    // both sessions are untouched and the token is still good for the client
+    let user = server.storage.get_user(USERNAME).await.unwrap();
+    let sessions = server
+        .storage
+        .get_user_sessions(user.user_id)
+        .await
+        .unwrap();
+    assert_eq!(sessions.len(), 2);
+    let request = test::TestRequest::post().uri("/auth/introspect").set_form([
+        ("token", tokens.access_token.as_str()),
+        ("client_id", &client.0),
+        ("client_secret", &client.1),
+    ]);
+    let introspection: Introspection = test::read_body_json(server.send(request).await).await;
+    assert!(introspection.active);
+}
+
+#[actix_web::test]
+async fn client_refresh_tokens_are_refused_by_the_refresh_endpoint() {
+    let (server, session_cookie, client) = setup().await;
+    let tokens = client_tokens(&server, &session_cookie, &client).await;
+
+    let request = test::TestRequest::post()
+        .uri("/auth/refresh")
+        .set_json(serde_json::json!({ "refresh_token": tokens.refresh_token }));
+    assert_problem(
+        server.send(request).await,
+        StatusCode::UNAUTHORIZED,
+        "invalid_token",
+    )
+    .await;
+
+    // the token was not rotated, the client can still redeem it
+    let request = test::TestRequest::post().uri("/oauth/token").set_form([
+        ("grant_type", "refresh_token"),
+        ("refresh_token", tokens.refresh_token.as_str()),
+        ("client_id", &client.0),
+        ("client_secret", &client.1),
+    ]);
+    assert_eq!(server.send(request).await.status(), StatusCode::OK);
+}
@@ -0,0 +1,293 @@
+mod common;
+
+use actix_web::cookie::Cookie;
+use actix_web::http::{header, StatusCode};
+use actix_web::{test, web};
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::{
+    decode, decode_header, encode, Algorithm, DecodingKey, EncodingKey, Header, Validation,
+};
+use url::Url;
+
+use auth_server::auth::api_requests::discovery;
+use auth_server::auth::client::create_client;
+use auth_server::auth::oauth::config::OAuthConfig;
+use auth_server::auth::oauth::id_token::IdTokenClaims;
+use auth_server::auth::oauth::pkce::code_challenge;
+use auth_server::auth::token::constants::{ID_TOKEN_TYPE, REFRESH_TOKEN_TYPE};
+use auth_server::auth::token::delivery::TokenResponse;
+use auth_server::auth::token::keyring::{generate_signing_key, Keyring};
+use auth_server::auth::token::signing_key::TokenSigner;
+
+use common::{assert_problem, bearer, cookie, key_encryption, TestServer, USERNAME};
+
+const ISSUER: &str = "https://auth.example.com";
+const REDIRECT_URI: &str = "https://grafana.example.com/login/generic_oauth";
+const CODE_VERIFIER: &str = "dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk";
+
+// Registers and logs in a user and registers a client, ID tokens are signed with ES256
+// unless the provider keeps per-user secrets
+async fn setup(keyring: bool) -> (TestServer, Cookie<'static>, (String, String)) {
+    let mut provider = TestServer::registered().await;
+    if keyring {
+        generate_signing_key(
+            provider.storage.as_ref(),
+            &key_encryption(),
+            Algorithm::ES256,
+            true,
+        )
+        .await
+        .unwrap();
+        let keyring = Keyring::load(provider.storage.as_ref(), key_encryption())
+            .await
+            .unwrap();
+        provider.signer = web::Data::new(TokenSigner::Keyring(keyring));
+    }
+    provider.oauth = web::Data::new(OAuthConfig {
+        issuer: Some(ISSUER.to_string()),
+        ..OAuthConfig::default()
+    });
+    let session_cookie = cookie(&provider.login(USERNAME).await, "session");
+
+    let (client, client_secret) = create_client(
+        provider.storage.as_ref(),
+        "grafana",
+        &[REDIRECT_URI.to_string()],
+        &[],
+        false,
+    )
+    .await
+    .unwrap();
+    (provider, session_cookie, (client.client_id, client_secret))
+}
+
+// Runs the authorization code flow with consent and returns the redirect query
+async fn authorize(
+    provider: &TestServer,
+    session_cookie: &Cookie<'static>,
+    client_id: &str,
+    scope: &str,
+) -> Vec<(String, String)> {
+    let form = [
+        ("response_type", "code".to_string()),
+        ("client_id", client_id.to_string()),
+        ("redirect_uri", REDIRECT_URI.to_string()),
+        ("scope", scope.to_string()),
+        ("nonce", "n-0S6_WzA2Mj".to_string()),
+        ("code_challenge", code_challenge(CODE_VERIFIER)),
+        ("code_challenge_method", "S256".to_string()),
+        ("consent", "allow".to_string()),
+    ];
+    let request = test::TestRequest::post()
+        .uri("/oauth/authorize")
+        .cookie(session_cookie.clone())
+        .set_form(form);
+    let response = provider.send(request).await;
+    assert_eq!(response.status(), StatusCode::FOUND);
+    let location = response.headers().get(header::LOCATION).unwrap();
+    Url::parse(location.to_str().unwrap())
+        .unwrap()
+        .query_pairs()
+        .into_owned()
+        .collect()
+}
+
+async fn id_token_claims(provider: &TestServer, id_token: &str, client_id: &str) -> IdTokenClaims {
+    let request = test::TestRequest::get().uri("/.well-known/jwks.json");
+    let jwks: JwkSet = test::read_body_json(provider.send(request).await).await;
+    let header = decode_header(id_token).unwrap();
+    assert_eq!(header.typ.as_deref(), Some(ID_TOKEN_TYPE));
+    let jwk = jwks.find(&header.kid.unwrap()).unwrap();
+    let mut validation = Validation::new(Algorithm::ES256);
+    validation.set_audience(&[client_id]);
+    validation.set_issuer(&[ISSUER]);
+    decode::<IdTokenClaims>(id_token, &DecodingKey::from_jwk(jwk).unwrap(), &validation)
+        .unwrap()
+        .claims
+}
+
+#[actix_web::test]
+async fn id_tokens_identify_the_user_to_the_client() {
+    let (provider, session_cookie, client) = setup(true).await;
+    let query = authorize(&provider, &session_cookie, &client.0, "openid email").await;
+    let (_, code) = query.iter().find(|(key, _)| key == "code").unwrap();
+
+    let request = test::TestRequest::post().uri("/oauth/token").set_form([
+        ("grant_type", "authorization_code"),
+        ("code", code),
+        ("redirect_uri", REDIRECT_URI),
+        ("code_verifier", CODE_VERIFIER),
+        ("client_id", &client.0),
+        ("client_secret", &client.1),
+    ]);
+    let response = provider.send(request).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let tokens: TokenResponse = test::read_body_json(response).await;
+    let claims = id_token_claims(&provider, tokens.id_token.as_deref().unwrap(), &client.0).await;
+    let user = provider.storage.get_user("Zenek").await.unwrap();
+    assert_eq!(claims.sub, user.user_id.to_string());
+    assert_eq!(claims.nonce.as_deref(), Some("n-0S6_WzA2Mj"));
+    assert_eq!(claims.email.as_deref(), Some("zenek@example.com"));
+    assert!(claims.preferred_username.is_none());
+    let login_session = &provider
+        .storage
+        .get_user_sessions(user.user_id)
+        .await
+        .unwrap()[0];
+    assert_eq!(claims.auth_time, Some(login_session.created_at));
+
+    let request = bearer(
+        test::TestRequest::get().uri("/oauth/userinfo"),
+        &tokens.access_token,
+    );
+    let response = provider.send(request).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let userinfo: serde_json::Value = test::read_body_json(response).await;
+    assert_eq!(
+        userinfo,
+        serde_json::json!({ "sub": user.user_id.to_string(), "email": "zenek@example.com" })
+    );
+
+    // refreshed ID tokens report the original login without the nonce
+    let request = test::TestRequest::post().uri("/oauth/token").set_form([
+        ("grant_type", "refresh_token"),
+        ("refresh_token", tokens.refresh_token.as_str()),
+        ("client_id", &client.0),
+        ("client_secret", &client.1),
+    ]);
+    let refreshed: TokenResponse = test::read_body_json(provider.send(request).await).await;
+    let claims =
+        id_token_claims(&provider, refreshed.id_token.as_deref().unwrap(), &client.0).await;
+    assert_eq!(claims.auth_time, Some(login_session.created_at));
+    assert!(claims.nonce.is_none());
+}
+
+#[actix_web::test]
+async fn id_tokens_are_not_refresh_tokens() {
+    let (provider, session_cookie, client) = setup(true).await;
+    let query = authorize(&provider, &session_cookie, &client.0, "openid").await;
+    let (_, code) = query.iter().find(|(key, _)| key == "code").unwrap();
+    let request = test::TestRequest::post().uri("/oauth/token").set_form([
+        ("grant_type", "authorization_code"),
+        ("code", code),
+        ("redirect_uri", REDIRECT_URI),
+        ("code_verifier", CODE_VERIFIER),
+        ("client_id", &client.0),
+        ("client_secret", &client.1),
+    ]);
+    let tokens: TokenResponse = test::read_body_json(provider.send(request).await).await;
+    let id_token = tokens.id_token.unwrap();
+    assert_eq!(
+        decode_header(&tokens.refresh_token).unwrap().typ.as_deref(),
+        Some(REFRESH_TOKEN_TYPE)
+    );
+
+    // the claims of a first-party refresh token signed with the refresh key but typed as an
+    // ID token
+    let refresh_token = cookie(&provider.login(USERNAME).await, "refresh_token")
+        .value()
+        .to_string();
+    let user = provider.storage.get_user(USERNAME).await.unwrap();
+    let refresh_key = provider
+        .storage
+        .get_secret_refresh_key(user.user_id)
+        .await
+        .unwrap();
+    let mut validation = Validation::new(Algorithm::HS512);
+    validation.validate_exp = false;
+    let claims = decode::<serde_json::Value>(
+        &refresh_token,
+        &DecodingKey::from_secret(refresh_key.as_bytes()),
+        &validation,
+    )
+    .unwrap()
+    .claims;
+    let mut header = Header::new(Algorithm::HS512);
+    header.typ = Some(ID_TOKEN_TYPE.to_string());
+    let retyped = encode(
+        &header,
+        &claims,
+        &EncodingKey::from_secret(refresh_key.as_bytes()),
+    )
+    .unwrap();
+
+    for token in [id_token, retyped] {
+        let request = test::TestRequest::post()
+            .uri("/auth/refresh")
+            .set_json(serde_json::json!({ "refresh_token": token }));
+        assert_problem(
+            provider.send(request).await,
+            StatusCode::UNAUTHORIZED,
+            "invalid_token",
+        )
+        .await;
+    }
+    let request = test::TestRequest::post()
+        .uri("/auth/refresh")
+        .set_json(serde_json::json!({ "refresh_token": refresh_token }));
+    assert_eq!(provider.send(request).await.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn discovery_describes_the_provider() {
+    let (mut provider, _, _) = setup(true).await;
+    provider.oauth = web::Data::new(OAuthConfig::default());
+    let request = test::TestRequest::get()
+        .uri("/.well-known/openid-configuration")
+        .insert_header((header::HOST, "login.example.com"));
+    let response = provider.send(request).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let configuration: discovery::OpenIdConfiguration = test::read_body_json(response).await;
+    assert_eq!(configuration.issuer, "http://login.example.com");
+    assert_eq!(
+        configuration.token_endpoint,
+        "http://login.example.com/oauth/token"
+    );
+    assert_eq!(
+        configuration.jwks_uri,
+        "http://login.example.com/.well-known/jwks.json"
+    );
+    assert_eq!(
+        configuration.id_token_signing_alg_values_supported,
+        ["ES256"]
+    );
+    assert_eq!(configuration.code_challenge_methods_supported, ["S256"]);
+    assert_eq!(
+        configuration.grant_types_supported,
+        ["authorization_code", "refresh_token", "client_credentials"]
+    );
+}
+
+// Without a keyring there are no ID tokens to describe
+#[actix_web::test]
+async fn discovery_needs_a_keyring() {
+    let (provider, _, _) = setup(false).await;
+    let request = test::TestRequest::get().uri("/.well-known/openid-configuration");
+    assert_eq!(provider.send(request).await.status(), StatusCode::NOT_FOUND);
+}
+
+#[actix_web::test]
+async fn openid_needs_published_keys_and_the_openid_scope() {
+    let (provider, session_cookie, client) = setup(false).await;
+    let query = authorize(&provider, &session_cookie, &client.0, "openid").await;
+    assert!(query.contains(&("error".to_string(), "invalid_scope".to_string())));
+
+    // access tokens granted without openid can not read the userinfo
+    let query = authorize(&provider, &session_cookie, &client.0, "photos").await;
+    let (_, code) = query.iter().find(|(key, _)| key == "code").unwrap();
+    let request = test::TestRequest::post().uri("/oauth/token").set_form([
+        ("grant_type", "authorization_code"),
+        ("code", code),
+        ("redirect_uri", REDIRECT_URI),
+        ("code_verifier", CODE_VERIFIER),
+        ("client_id", &client.0),
+        ("client_secret", &client.1),
+    ]);
+    let tokens: TokenResponse = test::read_body_json(provider.send(request).await).await;
+    assert!(tokens.id_token.is_none());
+    let request = bearer(
+        test::TestRequest::get().uri("/oauth/userinfo"),
+        &tokens.access_token,
+    );
+    assert_eq!(provider.send(request).await.status(), StatusCode::FORBIDDEN);
+}
@@ -0,0 +1,66 @@
+mod common;
+
+use actix_web::http::{header, StatusCode};
+use actix_web::{test, ResponseError};
+
+use auth_server::errors::auth_error::AuthError;
+use auth_server::errors::problem_details::{ProblemDetails, PROBLEM_JSON_CONTENT_TYPE};
+
+use common::{assert_problem, login_request, TestServer, USERNAME};
+
+#[actix_web::test]
+async fn client_errors_explain_themselves() {
+    let server = TestServer::registered().await;
+    let request = test::TestRequest::post()
+        .uri("/auth/register")
+        .insert_header((header::CONTENT_TYPE, "application/json"))
+        .set_payload("{\"username\": ");
+    let response = server.send(request).await;
+    assert_eq!(
+        response.headers().get(header::CONTENT_TYPE).unwrap(),
+        PROBLEM_JSON_CONTENT_TYPE
+    );
+    let problem: ProblemDetails = test::read_body_json(response).await;
+    assert_eq!(problem.status, 400);
+    assert_eq!(problem.code, "invalid_request_body");
+    assert_eq!(
+        problem.problem_type,
+        "urn:auth-server:problem:invalid_request_body"
+    );
+    assert!(
+        problem.detail.starts_with(&problem.title),
+        "{}",
+        problem.detail
+    );
+
+    let request = test::TestRequest::post()
+        .uri("/auth/login")
+        .set_json(serde_json::json!({ "username": USERNAME, "password": "wrong" }));
+    assert_problem(
+        server.send(request).await,
+        StatusCode::UNAUTHORIZED,
+        "invalid_credentials",
+    )
+    .await;
+    // an unknown user fails exactly like a wrong password
+    assert_problem(
+        server.send(login_request("ziutek")).await,
+        StatusCode::UNAUTHORIZED,
+        "invalid_credentials",
+    )
+    .await;
+}
+
+#[actix_web::test]
+async fn server_errors_hide_their_cause() {
+    let response =
+        AuthError::Storage("password authentication failed for user".to_string()).error_response();
+    assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    let body = actix_web::body::to_bytes(response.into_body())
+        .await
+        .unwrap();
+    let problem: ProblemDetails = serde_json::from_slice(&body).unwrap();
+    assert_eq!(problem.code, "storage_error");
+    assert_eq!(problem.detail, problem.title);
+    assert!(!String::from_utf8_lossy(&body).contains("password authentication"));
+}
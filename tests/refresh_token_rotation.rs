@@ -0,0 +1,55 @@
+mod common;
+
+use actix_web::cookie::Cookie;
+use actix_web::http::StatusCode;
+use actix_web::test;
+
+use common::{merge_cookies, problem_code, response_cookies, with_cookies, TestServer, USERNAME};
+
+fn refresh_request(cookies: &[Cookie<'static>]) -> test::TestRequest {
+    with_cookies(test::TestRequest::post().uri("/auth/refresh"), cookies)
+}
+
+async fn logged_in_server() -> (TestServer, Vec<Cookie<'static>>) {
+    let server = TestServer::registered().await;
+    let cookies = server.login(USERNAME).await;
+    (server, cookies)
+}
+
+#[actix_web::test]
+async fn refresh_tokens_rotate() {
+    let (server, cookies) = logged_in_server().await;
+    let first = merge_cookies(
+        cookies.clone(),
+        response_cookies(&server.send(refresh_request(&cookies)).await),
+    );
+    let second = merge_cookies(
+        first.clone(),
+        response_cookies(&server.send(refresh_request(&first)).await),
+    );
+    assert_ne!(
+        first.iter().find(|cookie| cookie.name() == "refresh_token"),
+        second
+            .iter()
+            .find(|cookie| cookie.name() == "refresh_token")
+    );
+}
+
+#[actix_web::test]
+async fn reused_refresh_token_revokes_family_and_session() {
+    let (server, cookies) = logged_in_server().await;
+    let rotated = merge_cookies(
+        cookies.clone(),
+        response_cookies(&server.send(refresh_request(&cookies)).await),
+    );
+
+    // the stolen copy of the first token is replayed
+    let response = server.send(refresh_request(&cookies)).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(problem_code(response).await, "refresh_token_reused");
+
+    // the legitimate successor is useless as well, its session is gone
+    let response = server.send(refresh_request(&rotated)).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(problem_code(response).await, "session_not_found");
+}
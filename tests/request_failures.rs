@@ -0,0 +1,534 @@
+mod common;
+
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+
+use actix_web::cookie::Cookie;
+use actix_web::dev::ServiceResponse;
+use actix_web::http::{header, StatusCode};
+use actix_web::test;
+use async_trait::async_trait;
+use url::Url;
+
+use auth_server::auth::client::create_client;
+use auth_server::auth::oauth::pkce::code_challenge;
+use auth_server::auth::session::drop_session_on_failure;
+use auth_server::auth::user::UserInfo;
+use auth_server::errors::auth_error::AuthError;
+use auth_server::migrations::runner::{run_pending_migrations, SQLITE_MIGRATOR};
+use auth_server::storage::memory::MemoryStorage;
+use auth_server::storage::sqlite::SqliteStorage;
+use auth_server::storage::{
+    ClientStore, KeyStore, NewSession, NewUser, SessionStore, StoredAuthorizationCode,
+    StoredClient, StoredRefreshToken, StoredSession, StoredSigningKey, UserStore,
+};
+
+use common::{
+    assert_problem, login_request, register_request, response_cookies, sqlite_pool, with_cookies,
+    TestServer, USERNAME,
+};
+
+const REDIRECT_URI: &str = "https://photos.example.com/callback";
+const CODE_VERIFIER: &str = "dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk";
+
+// Memory storage that fails the named operations as if the database went away
+#[derive(Default)]
+struct FailingStorage {
+    inner: MemoryStorage,
+    failing: Mutex<HashSet<&'static str>>,
+    refresh_tokens: Mutex<Vec<String>>,
+}
+
+impl FailingStorage {
+    fn fail(&self, operation: &'static str) {
+        self.failing.lock().unwrap().insert(operation);
+    }
+
+    fn check(&self, operation: &'static str) -> Result<(), AuthError> {
+        if self.failing.lock().unwrap().contains(operation) {
+            return Err(AuthError::Storage(format!(
+                "simulated {} failure",
+                operation
+            )));
+        }
+        Ok(())
+    }
+
+    // Asserts that a failed request left no trace of `USERNAME` logging in
+    async fn assert_no_login_rows(&self) {
+        let user_id = self.inner.get_user(USERNAME).await.unwrap().user_id;
+        assert!(self
+            .inner
+            .get_user_sessions(user_id)
+            .await
+            .unwrap()
+            .is_empty());
+        let refresh_tokens = self.refresh_tokens.lock().unwrap().clone();
+        for token_id in &refresh_tokens {
+            assert!(self.inner.get_refresh_token(token_id).await.is_err());
+        }
+    }
+}
+
+#[async_trait]
+impl UserStore for FailingStorage {
+    async fn does_user_id_exists(&self, user_id: i64) -> Result<bool, AuthError> {
+        self.check("does_user_id_exists")?;
+        self.inner.does_user_id_exists(user_id).await
+    }
+
+    async fn does_username_exists(&self, username: &str) -> Result<bool, AuthError> {
+        self.check("does_username_exists")?;
+        self.inner.does_username_exists(username).await
+    }
+
+    async fn get_user(&self, username: &str) -> Result<UserInfo, AuthError> {
+        self.check("get_user")?;
+        self.inner.get_user(username).await
+    }
+
+    async fn get_user_with_user_id(&self, user_id: i64) -> Result<UserInfo, AuthError> {
+        self.check("get_user_with_user_id")?;
+        self.inner.get_user_with_user_id(user_id).await
+    }
+
+    async fn store_new_user(&self, new_user: &NewUser<'_>) -> Result<i64, AuthError> {
+        self.check("store_new_user")?;
+        self.inner.store_new_user(new_user).await
+    }
+
+    async fn set_user_subscription_level(
+        &self,
+        user_id: i64,
+        level: &str,
+    ) -> Result<(), AuthError> {
+        self.check("set_user_subscription_level")?;
+        self.inner.set_user_subscription_level(user_id, level).await
+    }
+
+    async fn get_user_subscription_level(&self, user_id: i64) -> Result<String, AuthError> {
+        self.check("get_user_subscription_level")?;
+        self.inner.get_user_subscription_level(user_id).await
+    }
+
+    async fn get_user_roles(&self, user_id: i64) -> Result<Vec<String>, AuthError> {
+        self.check("get_user_roles")?;
+        self.inner.get_user_roles(user_id).await
+    }
+
+    async fn grant_user_role(&self, user_id: i64, role: &str) -> Result<(), AuthError> {
+        self.check("grant_user_role")?;
+        self.inner.grant_user_role(user_id, role).await
+    }
+
+    async fn revoke_user_role(&self, user_id: i64, role: &str) -> Result<(), AuthError> {
+        self.check("revoke_user_role")?;
+        self.inner.revoke_user_role(user_id, role).await
+    }
+}
+
+#[async_trait]
+impl SessionStore for FailingStorage {
+    async fn create_session(&self, new_session: &NewSession<'_>) -> Result<String, AuthError> {
+        self.check("create_session")?;
+        self.inner.create_session(new_session).await
+    }
+
+    async fn get_session_user_id(&self, session_uuid: &str) -> Result<i64, AuthError> {
+        self.check("get_session_user_id")?;
+        self.inner.get_session_user_id(session_uuid).await
+    }
+
+    async fn get_session(&self, session_uuid: &str) -> Result<StoredSession, AuthError> {
+        self.check("get_session")?;
+        self.inner.get_session(session_uuid).await
+    }
+
+    async fn get_user_sessions(&self, user_id: i64) -> Result<Vec<StoredSession>, AuthError> {
+        self.check("get_user_sessions")?;
+        self.inner.get_user_sessions(user_id).await
+    }
+
+    async fn touch_session(&self, session_uuid: &str, used_at: i64) -> Result<(), AuthError> {
+        self.check("touch_session")?;
+        self.inner.touch_session(session_uuid, used_at).await
+    }
+
+    async fn drop_session(&self, session_uuid: &str) -> Result<(), AuthError> {
+        self.check("drop_session")?;
+        self.inner.drop_session(session_uuid).await
+    }
+
+    async fn purge_expired_sessions(&self, now: i64) -> Result<u64, AuthError> {
+        self.check("purge_expired_sessions")?;
+        self.inner.purge_expired_sessions(now).await
+    }
+
+    async fn store_refresh_token(
+        &self,
+        refresh_token: &StoredRefreshToken,
+    ) -> Result<(), AuthError> {
+        self.check("store_refresh_token")?;
+        self.inner.store_refresh_token(refresh_token).await?;
+        self.refresh_tokens
+            .lock()
+            .unwrap()
+            .push(refresh_token.token_id.clone());
+        Ok(())
+    }
+
+    async fn get_refresh_token(&self, token_id: &str) -> Result<StoredRefreshToken, AuthError> {
+        self.check("get_refresh_token")?;
+        self.inner.get_refresh_token(token_id).await
+    }
+
+    async fn use_refresh_token(&self, token_id: &str, used_at: i64) -> Result<bool, AuthError> {
+        self.check("use_refresh_token")?;
+        self.inner.use_refresh_token(token_id, used_at).await
+    }
+
+    async fn revoke_refresh_token_family(
+        &self,
+        family_id: &str,
+        revoked_at: i64,
+    ) -> Result<(), AuthError> {
+        self.check("revoke_refresh_token_family")?;
+        self.inner
+            .revoke_refresh_token_family(family_id, revoked_at)
+            .await
+    }
+
+    async fn delete_refresh_token_family(&self, family_id: &str) -> Result<(), AuthError> {
+        self.check("delete_refresh_token_family")?;
+        self.inner.delete_refresh_token_family(family_id).await
+    }
+
+    async fn revoke_access_token(&self, jti: &str, expires_at: i64) -> Result<(), AuthError> {
+        self.check("revoke_access_token")?;
+        self.inner.revoke_access_token(jti, expires_at).await
+    }
+
+    async fn is_access_token_revoked(&self, jti: &str) -> Result<bool, AuthError> {
+        self.check("is_access_token_revoked")?;
+        self.inner.is_access_token_revoked(jti).await
+    }
+}
+
+#[async_trait]
+impl KeyStore for FailingStorage {
+    async fn get_secret_access_key(&self, user_id: i64) -> Result<String, AuthError> {
+        self.check("get_secret_access_key")?;
+        self.inner.get_secret_access_key(user_id).await
+    }
+
+    async fn get_secret_refresh_key(&self, user_id: i64) -> Result<String, AuthError> {
+        self.check("get_secret_refresh_key")?;
+        self.inner.get_secret_refresh_key(user_id).await
+    }
+
+    async fn store_signing_key(&self, signing_key: &StoredSigningKey) -> Result<(), AuthError> {
+        self.check("store_signing_key")?;
+        self.inner.store_signing_key(signing_key).await
+    }
+
+    async fn get_signing_keys(&self) -> Result<Vec<StoredSigningKey>, AuthError> {
+        self.check("get_signing_keys")?;
+        self.inner.get_signing_keys().await
+    }
+
+    async fn set_signing_key_activation(
+        &self,
+        key_id: &str,
+        activates_at: i64,
+    ) -> Result<(), AuthError> {
+        self.check("set_signing_key_activation")?;
+        self.inner
+            .set_signing_key_activation(key_id, activates_at)
+            .await
+    }
+
+    async fn set_signing_key_retirement(
+        &self,
+        key_id: &str,
+        retires_at: i64,
+    ) -> Result<(), AuthError> {
+        self.check("set_signing_key_retirement")?;
+        self.inner
+            .set_signing_key_retirement(key_id, retires_at)
+            .await
+    }
+}
+
+#[async_trait]
+impl ClientStore for FailingStorage {
+    async fn store_client(&self, client: &StoredClient) -> Result<(), AuthError> {
+        self.check("store_client")?;
+        self.inner.store_client(client).await
+    }
+
+    async fn get_client(&self, client_id: &str) -> Result<StoredClient, AuthError> {
+        self.check("get_client")?;
+        self.inner.get_client(client_id).await
+    }
+
+    async fn get_clients(&self) -> Result<Vec<StoredClient>, AuthError> {
+        self.check("get_clients")?;
+        self.inner.get_clients().await
+    }
+
+    async fn delete_client(&self, client_id: &str) -> Result<(), AuthError> {
+        self.check("delete_client")?;
+        self.inner.delete_client(client_id).await
+    }
+
+    async fn store_authorization_code(
+        &self,
+        authorization_code: &StoredAuthorizationCode,
+    ) -> Result<(), AuthError> {
+        self.check("store_authorization_code")?;
+        self.inner
+            .store_authorization_code(authorization_code)
+            .await
+    }
+
+    async fn get_authorization_code(
+        &self,
+        code: &str,
+    ) -> Result<StoredAuthorizationCode, AuthError> {
+        self.check("get_authorization_code")?;
+        self.inner.get_authorization_code(code).await
+    }
+
+    async fn use_authorization_code(&self, code: &str, used_at: i64) -> Result<bool, AuthError> {
+        self.check("use_authorization_code")?;
+        self.inner.use_authorization_code(code, used_at).await
+    }
+
+    async fn get_consented_scope(
+        &self,
+        user_id: i64,
+        client_id: &str,
+    ) -> Result<Option<String>, AuthError> {
+        self.check("get_consented_scope")?;
+        self.inner.get_consented_scope(user_id, client_id).await
+    }
+
+    async fn store_consent(
+        &self,
+        user_id: i64,
+        client_id: &str,
+        scope: &str,
+        granted_at: i64,
+    ) -> Result<(), AuthError> {
+        self.check("store_consent")?;
+        self.inner
+            .store_consent(user_id, client_id, scope, granted_at)
+            .await
+    }
+}
+
+async fn send(storage: &Arc<FailingStorage>, request: test::TestRequest) -> ServiceResponse {
+    TestServer::with_storage(storage.clone())
+        .send(request)
+        .await
+}
+
+async fn registered_storage() -> Arc<FailingStorage> {
+    let storage = Arc::new(FailingStorage::default());
+    let response = send(&storage, register_request(USERNAME)).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    storage
+}
+
+// Registers and logs in, returning the cookies set by the login response
+async fn logged_in_storage() -> (Arc<FailingStorage>, Vec<Cookie<'static>>) {
+    let storage = registered_storage().await;
+    let response = send(&storage, login_request(USERNAME)).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let cookies = response_cookies(&response);
+    (storage, cookies)
+}
+
+#[actix_web::test]
+async fn register_survives_username_lookup_failure() {
+    let storage = Arc::new(FailingStorage::default());
+    storage.fail("does_username_exists");
+    let response = send(&storage, register_request(USERNAME)).await;
+    assert_problem(response, StatusCode::INTERNAL_SERVER_ERROR, "storage_error").await;
+}
+
+#[actix_web::test]
+async fn register_survives_insert_failure() {
+    let storage = Arc::new(FailingStorage::default());
+    storage.fail("store_new_user");
+    let response = send(&storage, register_request(USERNAME)).await;
+    assert_problem(response, StatusCode::INTERNAL_SERVER_ERROR, "storage_error").await;
+
+    assert!(!storage.inner.does_username_exists(USERNAME).await.unwrap());
+    assert!(storage.inner.get_secret_access_key(1).await.is_err());
+    assert!(storage.inner.get_secret_refresh_key(1).await.is_err());
+}
+
+// The keys are inserted after the user, a conflicting refresh key makes the last insert fail
+#[actix_web::test]
+async fn register_rolls_back_when_a_key_insert_fails() {
+    let pool = sqlite_pool().await;
+    run_pending_migrations(&pool, &SQLITE_MIGRATOR)
+        .await
+        .unwrap();
+    sqlx::query("INSERT INTO refresh_access_keys (user_id, secret_refresh_key) VALUES (1, 'x')")
+        .execute(&pool)
+        .await
+        .unwrap();
+    let server = TestServer::with_storage(Arc::new(SqliteStorage::new(pool.clone())));
+    let response = server.send(register_request(USERNAME)).await;
+    assert_problem(response, StatusCode::INTERNAL_SERVER_ERROR, "storage_error").await;
+
+    for table in ["users", "secret_access_keys"] {
+        let count: i64 = sqlx::query_scalar(&format!("SELECT COUNT(*) FROM {}", table))
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(count, 0, "{} has rows left", table);
+    }
+}
+
+#[actix_web::test]
+async fn login_survives_storage_failures() {
+    for operation in [
+        "get_user",
+        "create_session",
+        "store_refresh_token",
+        "get_secret_access_key",
+        "get_secret_refresh_key",
+    ] {
+        let storage = registered_storage().await;
+        storage.fail(operation);
+        let response = send(&storage, login_request(USERNAME)).await;
+        assert_problem(response, StatusCode::INTERNAL_SERVER_ERROR, "storage_error").await;
+        storage.assert_no_login_rows().await;
+    }
+}
+
+// The session of a failed login can only be dropped while the storage works, a failed drop
+// is answered instead of the error that made the login fail
+#[actix_web::test]
+async fn failed_drop_of_a_new_session_is_reported() {
+    let storage = registered_storage().await;
+    storage.fail("store_refresh_token");
+    storage.fail("drop_session");
+    let response = send(&storage, login_request(USERNAME)).await;
+    assert_problem(response, StatusCode::INTERNAL_SERVER_ERROR, "storage_error").await;
+
+    let user_id = storage.inner.get_user(USERNAME).await.unwrap().user_id;
+    let sessions = storage.inner.get_user_sessions(user_id).await.unwrap();
+    assert_eq!(sessions.len(), 1);
+    let result: Result<(), AuthError> = drop_session_on_failure(
+        Err(AuthError::SessionLimitReached),
+        &sessions[0].session_uuid,
+        storage.as_ref(),
+    )
+    .await;
+    assert!(matches!(result, Err(AuthError::Storage(_))));
+}
+
+#[actix_web::test]
+async fn refresh_survives_storage_failures() {
+    for operation in [
+        "get_session_user_id",
+        "get_session",
+        "does_user_id_exists",
+        "get_user_with_user_id",
+        "get_secret_access_key",
+        "get_secret_refresh_key",
+        "get_refresh_token",
+        "use_refresh_token",
+        "store_refresh_token",
+    ] {
+        let (storage, cookies) = logged_in_storage().await;
+        storage.fail(operation);
+        let request = with_cookies(test::TestRequest::post().uri("/auth/refresh"), &cookies);
+        let response = send(&storage, request).await;
+        assert_problem(response, StatusCode::INTERNAL_SERVER_ERROR, "storage_error").await;
+    }
+}
+
+#[actix_web::test]
+async fn logout_survives_storage_failures() {
+    for operation in [
+        "get_session_user_id",
+        "get_session",
+        "get_secret_access_key",
+        "is_access_token_revoked",
+        "drop_session",
+    ] {
+        let (storage, cookies) = logged_in_storage().await;
+        storage.fail(operation);
+        let request = with_cookies(test::TestRequest::get().uri("/auth/logout"), &cookies);
+        let response = send(&storage, request).await;
+        assert_problem(response, StatusCode::INTERNAL_SERVER_ERROR, "storage_error").await;
+    }
+}
+
+#[actix_web::test]
+async fn requests_without_cookies_are_rejected() {
+    let storage = registered_storage().await;
+    let response = send(&storage, test::TestRequest::get().uri("/auth/logout")).await;
+    assert_problem(response, StatusCode::BAD_REQUEST, "missing_cookie").await;
+
+    let response = send(&storage, test::TestRequest::post().uri("/auth/refresh")).await;
+    assert_problem(response, StatusCode::BAD_REQUEST, "missing_cookie").await;
+}
+
+// The OAuth session is created before its tokens, like the one of a login
+#[actix_web::test]
+async fn code_exchange_survives_storage_failures() {
+    for operation in ["get_secret_access_key", "store_refresh_token"] {
+        let (storage, cookies) = logged_in_storage().await;
+        let (client, client_secret) = create_client(
+            storage.as_ref(),
+            "photos",
+            &[REDIRECT_URI.to_string()],
+            &[],
+            false,
+        )
+        .await
+        .unwrap();
+        let request = with_cookies(test::TestRequest::post().uri("/oauth/authorize"), &cookies)
+            .set_form([
+                ("response_type", "code".to_string()),
+                ("client_id", client.client_id.clone()),
+                ("redirect_uri", REDIRECT_URI.to_string()),
+                ("code_challenge", code_challenge(CODE_VERIFIER)),
+                ("code_challenge_method", "S256".to_string()),
+                ("consent", "allow".to_string()),
+            ]);
+        let response = send(&storage, request).await;
+        assert_eq!(response.status(), StatusCode::FOUND);
+        let location = response.headers().get(header::LOCATION).unwrap();
+        let (_, code) = Url::parse(location.to_str().unwrap())
+            .unwrap()
+            .query_pairs()
+            .into_owned()
+            .find(|(key, _)| key == "code")
+            .unwrap();
+
+        storage.fail(operation);
+        let request = test::TestRequest::post().uri("/oauth/token").set_form([
+            ("grant_type", "authorization_code"),
+            ("code", &code),
+            ("redirect_uri", REDIRECT_URI),
+            ("code_verifier", CODE_VERIFIER),
+            ("client_id", &client.client_id),
+            ("client_secret", &client_secret),
+        ]);
+        let response = send(&storage, request).await;
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+
+        // only the session of the login is left
+        let user_id = storage.inner.get_user(USERNAME).await.unwrap().user_id;
+        let sessions = storage.inner.get_user_sessions(user_id).await.unwrap();
+        assert_eq!(sessions.len(), 1);
+        assert!(sessions[0].client_id.is_none());
+    }
+}
@@ -0,0 +1,115 @@
+mod common;
+
+use actix_web::dev::ServiceResponse;
+use actix_web::http::StatusCode;
+use actix_web::{test, web, HttpResponse};
+
+use auth_server::auth::guard::authenticated_user::AuthenticatedUser;
+use auth_server::auth::guard::require_auth::RequireAuth;
+
+use common::{assert_problem, bearer, TestServer, USERNAME};
+
+async fn whoami(user: AuthenticatedUser) -> HttpResponse {
+    HttpResponse::Ok().body(user.username)
+}
+
+// Application routes behind the extractor and the guard
+fn guarded_routes(cfg: &mut web::ServiceConfig) {
+    cfg.route("/whoami", web::get().to(whoami))
+        .service(
+            web::scope("/reports")
+                .wrap(RequireAuth::new())
+                .route("", web::get().to(HttpResponse::Ok)),
+        )
+        .service(
+            web::scope("/admin")
+                .wrap(RequireAuth::new().role("admin"))
+                .route("", web::get().to(whoami)),
+        )
+        .service(
+            web::scope("/premium")
+                .wrap(RequireAuth::new().subscription_level("normal-premium"))
+                .route("", web::get().to(whoami)),
+        );
+}
+
+async fn send(server: &TestServer, request: test::TestRequest) -> ServiceResponse {
+    server.send_with(request, guarded_routes).await
+}
+
+async fn start() -> (TestServer, String) {
+    let server = TestServer::registered().await;
+    let tokens = server.login_for_tokens(USERNAME).await;
+    (server, tokens.access_token)
+}
+
+async fn get(server: &TestServer, uri: &str, token: &str) -> ServiceResponse {
+    send(server, bearer(test::TestRequest::get().uri(uri), token)).await
+}
+
+#[actix_web::test]
+async fn extractor_and_guard_require_a_valid_token() {
+    let (server, token) = start().await;
+
+    for uri in ["/whoami", "/reports"] {
+        let response = send(&server, test::TestRequest::get().uri(uri)).await;
+        assert_problem(response, StatusCode::BAD_REQUEST, "missing_cookie").await;
+        assert_problem(
+            get(&server, uri, "garbage").await,
+            StatusCode::UNAUTHORIZED,
+            "invalid_token",
+        )
+        .await;
+        assert_eq!(get(&server, uri, &token).await.status(), StatusCode::OK);
+    }
+    let body = test::read_body(get(&server, "/whoami", &token).await).await;
+    assert_eq!(body, "Zenek");
+}
+
+#[actix_web::test]
+async fn role_guard_follows_granted_roles() {
+    let (server, token) = start().await;
+    let storage = &server.storage;
+    let user_id = storage.get_user("Zenek").await.unwrap().user_id;
+
+    assert_problem(
+        get(&server, "/admin", &token).await,
+        StatusCode::FORBIDDEN,
+        "insufficient_permissions",
+    )
+    .await;
+    storage.grant_user_role(user_id, "admin").await.unwrap();
+    storage.grant_user_role(user_id, "admin").await.unwrap();
+    assert_eq!(storage.get_user_roles(user_id).await.unwrap(), ["admin"]);
+    let response = get(&server, "/admin", &token).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(test::read_body(response).await, "Zenek");
+
+    storage.revoke_user_role(user_id, "admin").await.unwrap();
+    assert_problem(
+        get(&server, "/admin", &token).await,
+        StatusCode::FORBIDDEN,
+        "insufficient_permissions",
+    )
+    .await;
+}
+
+#[actix_web::test]
+async fn subscription_guard_lets_higher_levels_through() {
+    let (server, token) = start().await;
+    let storage = &server.storage;
+    let user_id = storage.get_user("Zenek").await.unwrap().user_id;
+
+    for (level, status) in [
+        ("non-premium", StatusCode::FORBIDDEN),
+        ("basic-premium", StatusCode::FORBIDDEN),
+        ("normal-premium", StatusCode::OK),
+        ("enterprise-premium", StatusCode::OK),
+    ] {
+        storage
+            .set_user_subscription_level(user_id, level)
+            .await
+            .unwrap();
+        assert_eq!(get(&server, "/premium", &token).await.status(), status);
+    }
+}
@@ -0,0 +1,62 @@
+mod common;
+
+use actix_web::http::StatusCode;
+use actix_web::test;
+
+use common::{assert_problem, cookie, with_cookies, TestServer, USERNAME};
+
+#[actix_web::test]
+async fn tokens_stop_working_once_their_session_is_dropped() {
+    let server = TestServer::registered().await;
+    let old_cookies = server.login(USERNAME).await;
+    let new_cookies = server.login(USERNAME).await;
+
+    let request = with_cookies(test::TestRequest::get().uri("/auth/logout"), &old_cookies);
+    assert_eq!(server.send(request).await.status(), StatusCode::OK);
+
+    // a live session cookie does not revive tokens issued for the dropped session
+    let replayed = vec![
+        cookie(&new_cookies, "session"),
+        cookie(&old_cookies, "token"),
+    ];
+    let request = with_cookies(test::TestRequest::get().uri("/auth/logout"), &replayed);
+    assert_problem(
+        server.send(request).await,
+        StatusCode::UNAUTHORIZED,
+        "session_not_found",
+    )
+    .await;
+
+    let replayed = vec![
+        cookie(&new_cookies, "session"),
+        cookie(&old_cookies, "refresh_token"),
+    ];
+    let request = with_cookies(test::TestRequest::post().uri("/auth/refresh"), &replayed);
+    assert_problem(
+        server.send(request).await,
+        StatusCode::UNAUTHORIZED,
+        "session_not_found",
+    )
+    .await;
+
+    // the other session is untouched
+    let request = with_cookies(test::TestRequest::get().uri("/auth/logout"), &new_cookies);
+    assert_eq!(server.send(request).await.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn tokens_are_bound_to_their_user() {
+    let server = TestServer::registered().await;
+    let zenek = server.login(USERNAME).await;
+    server.register("Mietek").await;
+    let mietek = server.login("Mietek").await;
+
+    let replayed = vec![cookie(&mietek, "session"), cookie(&zenek, "refresh_token")];
+    let request = with_cookies(test::TestRequest::post().uri("/auth/refresh"), &replayed);
+    assert_problem(
+        server.send(request).await,
+        StatusCode::UNAUTHORIZED,
+        "invalid_token",
+    )
+    .await;
+}
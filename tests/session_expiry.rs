@@ -0,0 +1,103 @@
+mod common;
+
+use std::sync::Arc;
+
+use actix_web::cookie::Cookie;
+use actix_web::http::StatusCode;
+use actix_web::{test, web};
+
+use auth_server::auth::session::SessionPolicy;
+use auth_server::errors::auth_error::AuthError;
+use auth_server::storage::memory::MemoryStorage;
+use auth_server::storage::{NewSession, Storage, StoredRefreshToken};
+
+use common::{assert_problem, with_cookies, TestServer, USERNAME};
+
+async fn logged_in_server(policy: SessionPolicy) -> (TestServer, Vec<Cookie<'static>>) {
+    let mut server = TestServer::new();
+    server.session_policy = web::Data::new(policy);
+    server.register(USERNAME).await;
+    let cookies = server.login(USERNAME).await;
+    (server, cookies)
+}
+
+async fn assert_refresh_rejected(server: &TestServer, cookies: &[Cookie<'static>], code: &str) {
+    let request = with_cookies(test::TestRequest::post().uri("/auth/refresh"), cookies);
+    assert_problem(server.send(request).await, StatusCode::UNAUTHORIZED, code).await;
+}
+
+#[actix_web::test]
+async fn idle_session_expires() {
+    let (server, cookies) = logged_in_server(SessionPolicy {
+        idle_timeout: 0,
+        ..SessionPolicy::default()
+    })
+    .await;
+    assert_refresh_rejected(&server, &cookies, "session_expired").await;
+    // the expired session is dropped on the spot
+    assert_refresh_rejected(&server, &cookies, "session_not_found").await;
+}
+
+#[actix_web::test]
+async fn session_expires_after_absolute_lifetime() {
+    let (server, cookies) = logged_in_server(SessionPolicy {
+        absolute_lifetime: 0,
+        ..SessionPolicy::default()
+    })
+    .await;
+    assert_refresh_rejected(&server, &cookies, "session_expired").await;
+}
+
+#[actix_web::test]
+async fn purge_removes_expired_sessions_and_their_refresh_tokens() {
+    let storage: Arc<dyn Storage> = Arc::new(MemoryStorage::new());
+    let mut session_uuids = Vec::new();
+    // idle for too long, past its lifetime and still active
+    for (last_used_at, expires_at) in [(0, 10_000), (500, 900), (500, 10_000)] {
+        let session_uuid = storage
+            .create_session(&NewSession {
+                user_id: 1,
+                created_at: 0,
+                expires_at,
+                idle_timeout: 600,
+                ip_address: None,
+                user_agent: None,
+                client_id: None,
+                scope: None,
+                auth_time: None,
+            })
+            .await
+            .unwrap();
+        storage
+            .touch_session(&session_uuid, last_used_at)
+            .await
+            .unwrap();
+        storage
+            .store_refresh_token(&StoredRefreshToken {
+                token_id: session_uuid.clone(),
+                family_id: session_uuid.clone(),
+                session_uuid: session_uuid.clone(),
+                user_id: 1,
+                expires_at: 10_000,
+                used_at: None,
+                revoked_at: None,
+            })
+            .await
+            .unwrap();
+        session_uuids.push(session_uuid);
+    }
+
+    assert_eq!(storage.purge_expired_sessions(1000).await.unwrap(), 2);
+    for expired in &session_uuids[..2] {
+        assert!(matches!(
+            storage.get_session(expired).await,
+            Err(AuthError::RecordNotFound)
+        ));
+        assert!(matches!(
+            storage.get_refresh_token(expired).await,
+            Err(AuthError::RecordNotFound)
+        ));
+    }
+    assert!(storage.get_session(&session_uuids[2]).await.is_ok());
+    assert!(storage.get_refresh_token(&session_uuids[2]).await.is_ok());
+}
@@ -0,0 +1,98 @@
+mod common;
+
+use actix_web::cookie::Cookie;
+use actix_web::dev::ServiceResponse;
+use actix_web::http::StatusCode;
+use actix_web::{test, web};
+
+use auth_server::auth::session::{SessionLimitPolicy, SessionPolicy};
+
+use common::{assert_problem, login_request, response_cookies, with_cookies, TestServer, USERNAME};
+
+// One session for non-premium users, unlimited for every other tier
+async fn registered_server(limit_policy: SessionLimitPolicy) -> TestServer {
+    let mut server = TestServer::new();
+    server.session_policy = web::Data::new(SessionPolicy {
+        session_limits: [1, 0, 0, 0],
+        limit_policy,
+        ..SessionPolicy::default()
+    });
+    server.register(USERNAME).await;
+    server
+}
+
+async fn login(server: &TestServer) -> ServiceResponse {
+    server.send(login_request(USERNAME)).await
+}
+
+async fn refresh(server: &TestServer, cookies: &[Cookie<'static>]) -> ServiceResponse {
+    let request = with_cookies(test::TestRequest::post().uri("/auth/refresh"), cookies);
+    server.send(request).await
+}
+
+#[actix_web::test]
+async fn login_over_the_limit_is_rejected() {
+    let server = registered_server(SessionLimitPolicy::RejectNew).await;
+    let first = login(&server).await;
+    assert_eq!(first.status(), StatusCode::OK);
+
+    let second = login(&server).await;
+    assert_problem(second, StatusCode::FORBIDDEN, "session_limit_reached").await;
+    let user = server.storage.get_user(USERNAME).await.unwrap();
+    assert_eq!(
+        server
+            .storage
+            .get_user_sessions(user.user_id)
+            .await
+            .unwrap()
+            .len(),
+        1
+    );
+    assert_eq!(
+        refresh(&server, &response_cookies(&first)).await.status(),
+        StatusCode::OK
+    );
+}
+
+#[actix_web::test]
+async fn login_over_the_limit_evicts_the_oldest_session() {
+    let server = registered_server(SessionLimitPolicy::EvictOldest).await;
+    let first = response_cookies(&login(&server).await);
+    let second = login(&server).await;
+    assert_eq!(second.status(), StatusCode::OK);
+
+    assert_problem(
+        refresh(&server, &first).await,
+        StatusCode::UNAUTHORIZED,
+        "session_not_found",
+    )
+    .await;
+    assert_eq!(
+        refresh(&server, &response_cookies(&second)).await.status(),
+        StatusCode::OK
+    );
+}
+
+#[actix_web::test]
+async fn limit_follows_the_subscription_tier() {
+    let server = registered_server(SessionLimitPolicy::RejectNew).await;
+    let user = server.storage.get_user(USERNAME).await.unwrap();
+    server
+        .storage
+        .set_user_subscription_level(user.user_id, "enterprise-premium")
+        .await
+        .unwrap();
+
+    for _ in 0..3 {
+        assert_eq!(login(&server).await.status(), StatusCode::OK);
+    }
+    assert_eq!(
+        server
+            .storage
+            .get_user_sessions(user.user_id)
+            .await
+            .unwrap()
+            .len(),
+        3
+    );
+}
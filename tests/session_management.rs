@@ -0,0 +1,162 @@
+mod common;
+
+use actix_web::cookie::Cookie;
+use actix_web::http::{header, StatusCode};
+use actix_web::test;
+
+use auth_server::auth::api_requests::sessions::SessionInfo;
+use auth_server::storage::NewSession;
+
+use common::{login_request, response_cookies, with_cookies, TestServer};
+
+async fn login(server: &TestServer, username: &str, device: &str) -> Vec<Cookie<'static>> {
+    let request = login_request(username).insert_header((header::USER_AGENT, device));
+    let response = server.send(request).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    response_cookies(&response)
+}
+
+async fn list_sessions(server: &TestServer, cookies: &[Cookie<'static>]) -> Vec<SessionInfo> {
+    let request = with_cookies(test::TestRequest::get().uri("/auth/sessions"), cookies);
+    let response = server.send(request).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    test::read_body_json(response).await
+}
+
+async fn revoke_session(
+    server: &TestServer,
+    cookies: &[Cookie<'static>],
+    session_id: &str,
+) -> StatusCode {
+    let request = with_cookies(
+        test::TestRequest::delete().uri(&format!("/auth/sessions/{}", session_id)),
+        cookies,
+    );
+    server.send(request).await.status()
+}
+
+async fn refresh_status(server: &TestServer, cookies: &[Cookie<'static>]) -> StatusCode {
+    let request = with_cookies(test::TestRequest::post().uri("/auth/refresh"), cookies);
+    server.send(request).await.status()
+}
+
+#[actix_web::test]
+async fn lost_phone_can_be_signed_out() {
+    let server = TestServer::new();
+    server.register("Zenek").await;
+    let laptop = login(&server, "Zenek", "laptop").await;
+    let phone = login(&server, "Zenek", "phone").await;
+
+    let sessions = list_sessions(&server, &laptop).await;
+    assert_eq!(sessions.len(), 2);
+    let current = sessions.iter().find(|session| session.current).unwrap();
+    assert_eq!(current.user_agent.as_deref(), Some("laptop"));
+    let lost = sessions.iter().find(|session| !session.current).unwrap();
+    assert_eq!(lost.user_agent.as_deref(), Some("phone"));
+    assert!(lost.created_at > 0 && lost.last_used_at >= lost.created_at);
+
+    assert_eq!(
+        revoke_session(&server, &laptop, &lost.id.to_string()).await,
+        StatusCode::NO_CONTENT
+    );
+    assert_eq!(
+        refresh_status(&server, &phone).await,
+        StatusCode::UNAUTHORIZED
+    );
+    assert_eq!(refresh_status(&server, &laptop).await, StatusCode::OK);
+    assert_eq!(list_sessions(&server, &laptop).await.len(), 1);
+
+    // the same id can not be revoked twice, and ids that do not parse are unknown as well
+    let lost_id = lost.id.to_string();
+    assert_eq!(
+        revoke_session(&server, &laptop, &lost_id).await,
+        StatusCode::NOT_FOUND
+    );
+    assert_eq!(
+        revoke_session(&server, &laptop, "phone").await,
+        StatusCode::NOT_FOUND
+    );
+}
+
+#[actix_web::test]
+async fn sessions_of_other_users_are_invisible() {
+    let server = TestServer::new();
+    server.register("Zenek").await;
+    server.register("Mietek").await;
+    let zenek = login(&server, "Zenek", "laptop").await;
+    let mietek = login(&server, "Mietek", "laptop").await;
+
+    let mietek_sessions = list_sessions(&server, &mietek).await;
+    assert_eq!(mietek_sessions.len(), 1);
+    let mietek_session_id = mietek_sessions[0].id.to_string();
+    assert_eq!(
+        revoke_session(&server, &zenek, &mietek_session_id).await,
+        StatusCode::NOT_FOUND
+    );
+    assert_eq!(refresh_status(&server, &mietek).await, StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn revoke_others_keeps_the_current_session() {
+    let server = TestServer::new();
+    server.register("Zenek").await;
+    let laptop = login(&server, "Zenek", "laptop").await;
+    let phone = login(&server, "Zenek", "phone").await;
+    let tablet = login(&server, "Zenek", "tablet").await;
+
+    let request = with_cookies(
+        test::TestRequest::post().uri("/auth/sessions/revoke-others"),
+        &laptop,
+    );
+    assert_eq!(server.send(request).await.status(), StatusCode::NO_CONTENT);
+
+    let sessions = list_sessions(&server, &laptop).await;
+    assert_eq!(sessions.len(), 1);
+    assert!(sessions[0].current);
+    assert_eq!(
+        refresh_status(&server, &phone).await,
+        StatusCode::UNAUTHORIZED
+    );
+    assert_eq!(
+        refresh_status(&server, &tablet).await,
+        StatusCode::UNAUTHORIZED
+    );
+}
+
+#[actix_web::test]
+async fn expired_sessions_are_not_listed() {
+    let server = TestServer::new();
+    server.register("Zenek").await;
+    let laptop = login(&server, "Zenek", "laptop").await;
+
+    // expired long ago, but the sweeper did not get to it yet
+    let user = server.storage.get_user("Zenek").await.unwrap();
+    server
+        .storage
+        .create_session(&NewSession {
+            user_id: user.user_id,
+            created_at: 0,
+            expires_at: 10_000,
+            idle_timeout: 600,
+            ip_address: None,
+            user_agent: Some("phone"),
+            client_id: None,
+            scope: None,
+            auth_time: None,
+        })
+        .await
+        .unwrap();
+    assert_eq!(
+        server
+            .storage
+            .get_user_sessions(user.user_id)
+            .await
+            .unwrap()
+            .len(),
+        2
+    );
+
+    let sessions = list_sessions(&server, &laptop).await;
+    assert_eq!(sessions.len(), 1);
+    assert!(sessions[0].current);
+}
@@ -0,0 +1,136 @@
+mod common;
+
+use auth_server::errors::auth_error::AuthError;
+use auth_server::migrations::runner::{run_pending_migrations, SQLITE_MIGRATOR};
+use auth_server::storage::memory::MemoryStorage;
+use auth_server::storage::sqlite::SqliteStorage;
+use auth_server::storage::{NewSession, NewUser, Storage};
+
+async fn sqlite_storage() -> SqliteStorage {
+    let pool = common::sqlite_pool().await;
+    run_pending_migrations(&pool, &SQLITE_MIGRATOR)
+        .await
+        .unwrap();
+    SqliteStorage::new(pool)
+}
+
+fn new_user(username: &str) -> NewUser<'_> {
+    NewUser {
+        username,
+        password_hash: "hash",
+        email: "zenek@example.com",
+        secret_access_key: "access key",
+        secret_refresh_key: "refresh key",
+    }
+}
+
+fn new_session(user_id: i64) -> NewSession<'static> {
+    NewSession {
+        user_id,
+        created_at: 1000,
+        expires_at: 2000,
+        idle_timeout: 500,
+        ip_address: Some("127.0.0.1"),
+        user_agent: None,
+        client_id: None,
+        scope: None,
+        auth_time: Some(1000),
+    }
+}
+
+async fn users_round_trip(storage: &dyn Storage) {
+    let user_id = storage.store_new_user(&new_user("zenek")).await.unwrap();
+    assert!(storage.does_user_id_exists(user_id).await.unwrap());
+    assert!(storage.does_username_exists("zenek").await.unwrap());
+    assert!(!storage.does_username_exists("Zenek").await.unwrap());
+
+    let user = storage.get_user("zenek").await.unwrap();
+    assert_eq!(user.user_id, user_id);
+    assert_eq!(user.password, "hash");
+    assert_eq!(
+        storage
+            .get_user_with_user_id(user_id)
+            .await
+            .unwrap()
+            .username,
+        "zenek"
+    );
+    assert_eq!(
+        storage.get_secret_access_key(user_id).await.unwrap(),
+        "access key"
+    );
+    assert_eq!(
+        storage.get_secret_refresh_key(user_id).await.unwrap(),
+        "refresh key"
+    );
+
+    assert!(matches!(
+        storage.store_new_user(&new_user("zenek")).await,
+        Err(AuthError::DuplicateRecord)
+    ));
+    assert!(matches!(
+        storage.get_user("ziutek").await,
+        Err(AuthError::RecordNotFound)
+    ));
+    assert!(matches!(
+        storage.get_user_with_user_id(user_id + 1).await,
+        Err(AuthError::RecordNotFound)
+    ));
+}
+
+async fn sessions_round_trip(storage: &dyn Storage) {
+    let user_id = storage.store_new_user(&new_user("zenek")).await.unwrap();
+    let first = storage.create_session(&new_session(user_id)).await.unwrap();
+    let second = storage.create_session(&new_session(user_id)).await.unwrap();
+    assert_ne!(first, second);
+    assert_eq!(storage.get_session_user_id(&first).await.unwrap(), user_id);
+
+    let session = storage.get_session(&second).await.unwrap();
+    assert_eq!(session.last_used_at, 1000);
+    assert_eq!(session.ip_address.as_deref(), Some("127.0.0.1"));
+    storage.touch_session(&second, 1200).await.unwrap();
+    assert_eq!(
+        storage.get_session(&second).await.unwrap().last_used_at,
+        1200
+    );
+
+    let sessions = storage.get_user_sessions(user_id).await.unwrap();
+    let session_ids: Vec<i64> = sessions.iter().map(|session| session.session_id).collect();
+    assert_eq!(session_ids.len(), 2);
+    assert!(session_ids[0] < session_ids[1]);
+
+    // the id of a revoked session never identifies another one
+    storage.drop_session(&second).await.unwrap();
+    assert!(matches!(
+        storage.get_session(&second).await,
+        Err(AuthError::RecordNotFound)
+    ));
+    let third = storage.create_session(&new_session(user_id)).await.unwrap();
+    assert!(storage.get_session(&third).await.unwrap().session_id > session_ids[1]);
+
+    // the first session idled out, the third one was used since
+    storage.touch_session(&third, 1600).await.unwrap();
+    assert_eq!(storage.purge_expired_sessions(1600).await.unwrap(), 1);
+    assert!(storage.get_session(&first).await.is_err());
+    assert!(storage.get_session(&third).await.is_ok());
+}
+
+#[actix_web::test]
+async fn memory_users_round_trip() {
+    users_round_trip(&MemoryStorage::new()).await;
+}
+
+#[actix_web::test]
+async fn memory_sessions_round_trip() {
+    sessions_round_trip(&MemoryStorage::new()).await;
+}
+
+#[actix_web::test]
+async fn sqlite_users_round_trip() {
+    users_round_trip(&sqlite_storage().await).await;
+}
+
+#[actix_web::test]
+async fn sqlite_sessions_round_trip() {
+    sessions_round_trip(&sqlite_storage().await).await;
+}
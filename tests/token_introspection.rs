@@ -0,0 +1,173 @@
+mod common;
+
+use actix_web::cookie::Cookie;
+use actix_web::http::{header, StatusCode};
+use actix_web::test;
+use url::form_urlencoded::byte_serialize;
+
+use auth_server::auth::client::create_client;
+use auth_server::auth::token::introspection::Introspection;
+use auth_server::auth::utils::password::hash_password;
+use auth_server::storage::StoredClient;
+
+use common::{assert_problem, basic_auth, cookie, with_cookies, TestServer, USERNAME};
+
+// Registers a user and a client, returns the cookies of a fresh login and the client credentials
+async fn setup() -> (TestServer, Vec<Cookie<'static>>, (String, String)) {
+    let server = TestServer::registered().await;
+    let cookies = server.login(USERNAME).await;
+    let (client, client_secret) =
+        create_client(server.storage.as_ref(), "legacy billing", &[], &[], true)
+            .await
+            .unwrap();
+    (server, cookies, (client.client_id, client_secret))
+}
+
+fn token(cookies: &[Cookie<'static>]) -> String {
+    cookie(cookies, "token").value().to_string()
+}
+
+async fn introspect(
+    server: &TestServer,
+    (client_id, client_secret): &(String, String),
+    token: &str,
+) -> Introspection {
+    let request = test::TestRequest::post()
+        .uri("/auth/introspect")
+        .insert_header((header::AUTHORIZATION, basic_auth(client_id, client_secret)))
+        .set_form([("token", token)]);
+    let response = server.send(request).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    test::read_body_json(response).await
+}
+
+#[actix_web::test]
+async fn active_token_is_described() {
+    let (server, cookies, client) = setup().await;
+    let introspection = introspect(&server, &client, &token(&cookies)).await;
+
+    assert!(introspection.active);
+    let user = server.storage.get_user("Zenek").await.unwrap();
+    let session = &server
+        .storage
+        .get_user_sessions(user.user_id)
+        .await
+        .unwrap()[0];
+    assert_eq!(introspection.sub, Some(user.user_id.to_string()));
+    assert_eq!(introspection.username.as_deref(), Some("Zenek"));
+    assert_eq!(introspection.session_id, Some(session.session_id));
+    assert_eq!(
+        introspection.subscription_level.as_deref(),
+        Some("non-premium")
+    );
+    assert!(introspection.iat.unwrap() < introspection.exp.unwrap());
+}
+
+#[actix_web::test]
+async fn invalid_revoked_and_logged_out_tokens_are_inactive() {
+    let (server, cookies, client) = setup().await;
+    let request = test::TestRequest::post().uri("/auth/introspect").set_form([
+        ("token", "garbage"),
+        ("client_id", client.0.as_str()),
+        ("client_secret", client.1.as_str()),
+    ]);
+    let response = server.send(request).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = test::read_body(response).await;
+    assert_eq!(body, r#"{"active":false}"#);
+
+    let request = test::TestRequest::post()
+        .uri("/auth/revoke")
+        .set_form([("token", token(&cookies))]);
+    assert_eq!(server.send(request).await.status(), StatusCode::OK);
+    assert!(!introspect(&server, &client, &token(&cookies)).await.active);
+
+    let (server, cookies, client) = setup().await;
+    let request = with_cookies(test::TestRequest::get().uri("/auth/logout"), &cookies);
+    assert_eq!(server.send(request).await.status(), StatusCode::OK);
+    assert!(!introspect(&server, &client, &token(&cookies)).await.active);
+}
+
+#[actix_web::test]
+async fn only_registered_clients_may_introspect() {
+    let (server, cookies, (client_id, _)) = setup().await;
+    let token = token(&cookies);
+    let requests = [
+        test::TestRequest::post()
+            .uri("/auth/introspect")
+            .set_form([("token", token.as_str())]),
+        test::TestRequest::post()
+            .uri("/auth/introspect")
+            .insert_header((header::AUTHORIZATION, basic_auth(&client_id, "wrong")))
+            .set_form([("token", token.as_str())]),
+        test::TestRequest::post()
+            .uri("/auth/introspect")
+            .insert_header((header::AUTHORIZATION, basic_auth("unknown", "wrong")))
+            .set_form([("token", token.as_str())]),
+    ];
+    for request in requests {
+        assert_problem(
+            server.send(request).await,
+            StatusCode::UNAUTHORIZED,
+            "invalid_client",
+        )
+        .await;
+    }
+}
+
+#[actix_web::test]
+async fn only_resource_servers_may_introspect() {
+    let (server, cookies, _) = setup().await;
+    let (client, client_secret) = create_client(server.storage.as_ref(), "photos", &[], &[], false)
+        .await
+        .unwrap();
+    let request = test::TestRequest::post()
+        .uri("/auth/introspect")
+        .insert_header((
+            header::AUTHORIZATION,
+            basic_auth(&client.client_id, &client_secret),
+        ))
+        .set_form([("token", token(&cookies))]);
+    assert_problem(
+        server.send(request).await,
+        StatusCode::FORBIDDEN,
+        "insufficient_permissions",
+    )
+    .await;
+}
+
+#[actix_web::test]
+async fn basic_credentials_are_form_urlencoded() {
+    let (server, cookies, _) = setup().await;
+    let (client_id, client_secret) = ("legacy billing", "p+ss:w%rd&ü");
+    server
+        .storage
+        .store_client(&StoredClient {
+            client_id: client_id.to_string(),
+            client_secret: hash_password(client_secret).unwrap(),
+            name: "legacy billing".to_string(),
+            created_at: 0,
+            redirect_uris: String::new(),
+            allowed_scopes: String::new(),
+            resource_server: true,
+        })
+        .await
+        .unwrap();
+
+    let encode = |value: &str| byte_serialize(value.as_bytes()).collect::<String>();
+    let client = (encode(client_id), encode(client_secret));
+    assert_eq!(client.0, "legacy+billing");
+    assert!(introspect(&server, &client, &token(&cookies)).await.active);
+
+    // unencoded, the plus of the secret reads as a space
+    let request = test::TestRequest::post()
+        .uri("/auth/introspect")
+        .insert_header((header::AUTHORIZATION, basic_auth(&client.0, client_secret)))
+        .set_form([("token", token(&cookies))]);
+    assert_problem(
+        server.send(request).await,
+        StatusCode::UNAUTHORIZED,
+        "invalid_client",
+    )
+    .await;
+}
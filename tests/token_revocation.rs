@@ -0,0 +1,153 @@
+mod common;
+
+use actix_web::cookie::Cookie;
+use actix_web::http::StatusCode;
+use actix_web::test;
+use chrono::Utc;
+
+use auth_server::auth::token::revocation::RevocationList;
+use auth_server::storage::memory::MemoryStorage;
+
+use common::{assert_problem, cookie, response_cookies, with_cookies, TestServer, USERNAME};
+
+async fn revoke(server: &TestServer, form: serde_json::Value) -> StatusCode {
+    let request = test::TestRequest::post().uri("/auth/revoke").set_form(form);
+    server.send(request).await.status()
+}
+
+fn cookie_value(cookies: &[Cookie<'static>], name: &str) -> String {
+    cookie(cookies, name).value().to_string()
+}
+
+#[actix_web::test]
+async fn revoked_access_token_is_rejected_while_the_session_lives_on() {
+    let server = TestServer::registered().await;
+    let cookies = server.login(USERNAME).await;
+
+    let status = revoke(
+        &server,
+        serde_json::json!({ "token": cookie_value(&cookies, "token") }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let logout = test::TestRequest::get().uri("/auth/logout");
+    assert_problem(
+        server.send(with_cookies(logout, &cookies)).await,
+        StatusCode::UNAUTHORIZED,
+        "token_revoked",
+    )
+    .await;
+
+    // the refresh token still gets a fresh access token for the same session
+    let refresh = test::TestRequest::post().uri("/auth/refresh");
+    let response = server.send(with_cookies(refresh, &cookies)).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let refreshed = [
+        vec![Cookie::new("session", cookie_value(&cookies, "session"))],
+        response_cookies(&response),
+    ]
+    .concat();
+    let logout = test::TestRequest::get().uri("/auth/logout");
+    assert_eq!(
+        server.send(with_cookies(logout, &refreshed)).await.status(),
+        StatusCode::OK
+    );
+}
+
+#[actix_web::test]
+async fn revoked_refresh_token_ends_its_session() {
+    let server = TestServer::registered().await;
+    let cookies = server.login(USERNAME).await;
+
+    let status = revoke(
+        &server,
+        serde_json::json!({
+            "token": cookie_value(&cookies, "refresh_token"),
+            "token_type_hint": "refresh_token",
+        }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let refresh = test::TestRequest::post().uri("/auth/refresh");
+    assert_problem(
+        server.send(with_cookies(refresh, &cookies)).await,
+        StatusCode::UNAUTHORIZED,
+        "session_not_found",
+    )
+    .await;
+    let logout = test::TestRequest::get().uri("/auth/logout");
+    assert_problem(
+        server.send(with_cookies(logout, &cookies)).await,
+        StatusCode::UNAUTHORIZED,
+        "session_not_found",
+    )
+    .await;
+}
+
+#[actix_web::test]
+async fn unknown_tokens_are_accepted_but_a_missing_one_is_not() {
+    let server = TestServer::registered().await;
+    let cookies = server.login(USERNAME).await;
+
+    for token in ["garbage", "a.b.c"] {
+        let status = revoke(&server, serde_json::json!({ "token": token })).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+    // a wrong hint does not keep the token from being revoked
+    let status = revoke(
+        &server,
+        serde_json::json!({
+            "token": cookie_value(&cookies, "token"),
+            "token_type_hint": "refresh_token",
+        }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let logout = test::TestRequest::get().uri("/auth/logout");
+    assert_problem(
+        server.send(with_cookies(logout, &cookies)).await,
+        StatusCode::UNAUTHORIZED,
+        "token_revoked",
+    )
+    .await;
+
+    let request = test::TestRequest::post()
+        .uri("/auth/revoke")
+        .set_form(serde_json::json!({ "token_type_hint": "access_token" }));
+    assert_problem(
+        server.send(request).await,
+        StatusCode::BAD_REQUEST,
+        "invalid_request_body",
+    )
+    .await;
+}
+
+#[actix_web::test]
+async fn revocations_of_other_replicas_are_seen_once_the_cache_expires() {
+    let storage = MemoryStorage::new();
+    let expires_at = Utc::now().timestamp() + 60;
+    let cached = RevocationList::new(60);
+    let uncached = RevocationList::new(0);
+    assert!(!cached
+        .is_revoked("jti", expires_at, &storage)
+        .await
+        .unwrap());
+    assert!(!uncached
+        .is_revoked("jti", expires_at, &storage)
+        .await
+        .unwrap());
+
+    let other_replica = RevocationList::default();
+    other_replica
+        .revoke("jti", expires_at, &storage)
+        .await
+        .unwrap();
+    assert!(!cached
+        .is_revoked("jti", expires_at, &storage)
+        .await
+        .unwrap());
+    assert!(uncached
+        .is_revoked("jti", expires_at, &storage)
+        .await
+        .unwrap());
+}